@@ -0,0 +1,53 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::glob::Glob;
+
+/// Which files in a directory are converted, from the `--include` and `--exclude` globs. A file is converted if it
+/// matches an include glob, or none were given, and no exclude glob
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    include: Vec<Glob>,
+    exclude: Vec<Glob>,
+}
+
+impl Filter {
+    pub fn new(include: Vec<Glob>, exclude: Vec<Glob>) -> Self {
+        Self { include, exclude }
+    }
+
+    /// Whether to convert the file at `relative`, its path relative to the directory being converted
+    pub fn accepts(&self, relative: &Path) -> bool {
+        let path = relative.components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        (self.include.is_empty() || self.include.iter().any(|glob| glob.matches(&path)))
+            && !self.exclude.iter().any(|glob| glob.matches(&path))
+    }
+}
+
+/// Every regular file in `dir` and its subdirectories that `filter` accepts, sorted by path. The list is collected
+/// before anything is converted, so files created by the conversion are not picked up. Symbolic links are not followed
+pub fn files_in<P: AsRef<Path>>(dir: P, filter: &Filter) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut directories = vec![dir.as_ref().to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(&directory)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+
+            if file_type.is_dir() {
+                directories.push(entry.path());
+            } else if file_type.is_file() && filter.accepts(entry.path().strip_prefix(dir.as_ref()).unwrap_or(&entry.path())) {
+                files.push(entry.path());
+            }
+        }
+    }
+
+    files.sort();
+
+    Ok(files)
+}
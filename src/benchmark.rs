@@ -0,0 +1,111 @@
+use std::sync::atomic::AtomicBool;
+use std::time::{Duration, Instant};
+
+use crate::error::Result;
+use crate::options::{EncodeOptions, Container};
+use crate::random::SeededRng;
+
+/// Sizes of the buffers converted by `run`
+pub const BENCHMARK_SIZES: [usize; 3] = [4 << 10, 256 << 10, 4 << 20];
+
+/// Each size is converted repeatedly until at least this many bytes have been processed, so small buffers are timed
+/// over more than a handful of microseconds
+const BENCHMARK_BYTES: usize = 4 << 20;
+
+/// Time taken to convert one buffer size, summed over every repetition
+#[derive(Debug, Clone, Copy)]
+pub struct BenchmarkResult {
+    pub size: usize,
+    pub repetitions: usize,
+    pub encode_time: Duration,
+    pub decode_time: Duration,
+    pub hash_time: Duration,
+}
+
+impl BenchmarkResult {
+    /// Megabytes (10^6 bytes) processed per second over `time`
+    fn throughput_mb_s(&self, time: Duration) -> f64 {
+        let seconds = time.as_secs_f64();
+
+        if seconds > 0.0 {
+            (self.size * self.repetitions) as f64 / 1_000_000.0 / seconds
+        } else {
+            0.0
+        }
+    }
+
+    pub fn encode_mb_s(&self) -> f64 { self.throughput_mb_s(self.encode_time) }
+
+    pub fn decode_mb_s(&self) -> f64 { self.throughput_mb_s(self.decode_time) }
+
+    pub fn hash_mb_s(&self) -> f64 { self.throughput_mb_s(self.hash_time) }
+}
+
+/// Converts in-memory buffers of each of `BENCHMARK_SIZES` to bitmaps and back as described by `options`, and times
+/// the encoding, the decoding and the digest of each. Bitmaps are always benchmarked, whatever the container. Nothing
+/// is read from or written to disk
+pub fn run(options: &EncodeOptions, cancel: &AtomicBool) -> Result<Vec<BenchmarkResult>> {
+    let options = options.clone().with_container(Container::Bitmap);
+
+    let mut rng = SeededRng::new(0);
+
+    BENCHMARK_SIZES.iter().map(|&size| {
+        let data: Vec<u8> = (0..size).map(|_| rng.next_u64() as u8).collect();
+
+        let repetitions = (BENCHMARK_BYTES / size).max(1);
+
+        let mut result = BenchmarkResult {
+            size,
+            repetitions,
+            encode_time: Duration::default(),
+            decode_time: Duration::default(),
+            hash_time: Duration::default(),
+        };
+
+        for _ in 0..repetitions {
+            let start = Instant::now();
+
+            let mut bitmap = Vec::new();
+
+            crate::stream::encode_bytes(&data, & mut bitmap, &options, cancel)?;
+
+            result.encode_time += start.elapsed();
+
+            let start = Instant::now();
+
+            let (_, decoded) = crate::decode_in_memory(bitmap, cancel)?;
+
+            result.decode_time += start.elapsed();
+
+            debug_assert_eq!(decoded, data);
+
+            let start = Instant::now();
+
+            crate::slice_digests(&data, 0..size as u64);
+
+            result.hash_time += start.elapsed();
+        }
+
+        Ok(result)
+    }).collect()
+}
+
+/// Formats `size` in the largest binary unit it is a whole number of
+fn format_size(size: usize) -> String {
+    if size >= 1 << 20 && size.is_multiple_of(1 << 20) {
+        format!("{} MiB", size >> 20)
+    } else if size >= 1 << 10 && size.is_multiple_of(1 << 10) {
+        format!("{} KiB", size >> 10)
+    } else {
+        format!("{} B", size)
+    }
+}
+
+/// Prints a table of the throughput of each size, in MB/s
+pub fn print_results(results: &[BenchmarkResult]) {
+    println!("{:<10} {:>15} {:>15} {:>15}", "Size", "Encode (MB/s)", "Decode (MB/s)", "Hash (MB/s)");
+
+    for result in results {
+        println!("{:<10} {:>15.2} {:>15.2} {:>15.2}", format_size(result.size), result.encode_mb_s(), result.decode_mb_s(), result.hash_mb_s());
+    }
+}
@@ -0,0 +1,216 @@
+use std::ffi::OsString;
+use std::path::Path;
+
+use crate::error::{Result, Error, ErrorKind};
+
+/// Name of the config file looked for in the working directory when `--config` is not given
+pub const DEFAULT_CONFIG_FILE: &str = "b2b.toml";
+
+/// Options that can be given defaults in a config file, and whether each takes a value. Keys are the long
+/// names of the command line flags, with `_` accepted in place of `-`. `in-place` is left out, as a flag on the
+/// command line could not turn it off again
+const CONFIG_KEYS: [(&str, bool); 19] = [
+    ("fast", false),
+    ("quick-verify", false),
+    ("strict", false),
+    ("restore-on-error", false),
+    ("report", false),
+    ("stats", false),
+    ("force", false),
+    ("honor-offset", false),
+    ("canonical", false),
+    ("bitmap-digest", false),
+    ("layout", true),
+    ("shuffle", true),
+    ("seed", true),
+    ("container", true),
+    ("ext", true),
+    ("min-dimension", true),
+    ("max-dimension", true),
+    ("chunk-size", true),
+    ("hash-range", true),
+];
+
+/// Pairs of options that can't be used together. A default from the config is dropped if the command line gives the
+/// other option of a pair, so that `strict = true` does not make `--fast` an error, for example
+const CONFLICTS: [(&str, &str); 5] = [
+    ("fast", "strict"),
+    ("shuffle", "seed"),
+    ("shuffle", "layout"),
+    ("canonical", "layout"),
+    ("canonical", "shuffle"),
+];
+
+fn invalid<E: Into<Box<dyn std::error::Error>>>(error: E) -> Error {
+    Error::new(ErrorKind::InvalidConfig, error)
+}
+
+/// A value in the config file
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+}
+
+/// Parses a single value: a basic string, an integer or a boolean
+fn parse_value(text: &str, line: usize) -> Result<Value> {
+    if let Some(quoted) = text.strip_prefix('"') {
+        let mut value = String::new();
+        let mut chars = quoted.chars();
+
+        loop {
+            match chars.next() {
+                Some('"') => break,
+                Some('\\') => match chars.next() {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    _ => return Err(invalid(format!("Unsupported escape on line {}", line))),
+                },
+                Some(c) => value.push(c),
+                None => return Err(invalid(format!("Unterminated string on line {}", line))),
+            }
+        }
+
+        let rest = chars.as_str().trim_start();
+
+        if !rest.is_empty() && !rest.starts_with('#') {
+            return Err(invalid(format!("Unexpected text after the value on line {}", line)));
+        }
+
+        return Ok(Value::String(value));
+    }
+
+    //Anything else runs to a comment or the end of the line
+    let text = text.split('#').next().unwrap_or("").trim();
+
+    match text {
+        "true" => Ok(Value::Bool(true)),
+        "false" => Ok(Value::Bool(false)),
+        _ => text.replace('_', "").parse::<i64>().map(Value::Integer).map_err(|_| invalid(format!("Invalid value '{}' on line {}", text, line))),
+    }
+}
+
+/// Defaults for command line options, read from a toml file. Only top level `key = value` pairs holding strings,
+/// integers or booleans are supported, which covers every option that can be configured
+#[derive(Debug, Default)]
+pub struct Config {
+    values: Vec<(&'static str, Value)>,
+}
+
+impl Config {
+    /// Parses the text of a config file
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut config = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.starts_with('[') {
+                return Err(invalid(format!("Tables are not supported (line {})", i + 1)));
+            }
+
+            let mut parts = line.splitn(2, '=');
+
+            let key = parts.next().unwrap_or("").trim().replace('_', "-");
+            let value = parts.next().ok_or_else(|| invalid(format!("Expected 'key = value' on line {}", i + 1)))?.trim();
+
+            let (key, takes_value) = CONFIG_KEYS.iter()
+                .find(|(name, _)| *name == key)
+                .copied()
+                .ok_or_else(|| invalid(format!("Unknown option '{}' on line {}", key, i + 1)))?;
+
+            let value = parse_value(value, i + 1)?;
+
+            if takes_value == matches!(value, Value::Bool(_)) {
+                return Err(invalid(format!("Option '{}' on line {} {}", key, i + 1, if takes_value { "takes a value, not a boolean" } else { "must be true or false" })));
+            }
+
+            if config.values.iter().any(|(name, _)| *name == key) {
+                return Err(invalid(format!("Option '{}' is given more than once (line {})", key, i + 1)));
+            }
+
+            config.values.push((key, value));
+        }
+
+        Ok(config)
+    }
+
+    /// Reads the config file at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path.as_ref())?)
+    }
+
+    /// The config as command line arguments, leaving out the options for which `given` is true and the options that
+    /// conflict with them. `given` tells whether an option was given on the command line, so the config only fills
+    /// in defaults for the options that weren't
+    pub fn to_args<F: Fn(&str) -> bool>(&self, given: F) -> Vec<OsString> {
+        let overridden = |key: &str| given(key) || CONFLICTS.iter().any(|(a, b)| (*a == key && given(b)) || (*b == key && given(a)));
+
+        self.values.iter().filter(|(key, _)| !overridden(key)).filter_map(|(key, value)| match value {
+            Value::Bool(true) => Some(format!("--{}", key)),
+            Value::Bool(false) => None,
+            Value::Integer(value) => Some(format!("--{}={}", key, value)),
+            Value::String(value) => Some(format!("--{}={}", key, value)),
+        }).map(OsString::from).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(text: &str) -> Vec<String> {
+        Config::parse(text).unwrap().to_args(|_| false).into_iter().map(|arg| arg.into_string().unwrap()).collect()
+    }
+
+    fn error(text: &str) -> String {
+        Config::parse(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn values_become_arguments() {
+        let text = "# Defaults\n\nfast = true\nstats = false\nlayout = \"shuffled\" # comment\nseed = 1_000\nchunk_size = 4096\n";
+
+        assert_eq!(args(text), ["--fast", "--layout=shuffled", "--seed=1000", "--chunk-size=4096"]);
+    }
+
+    #[test]
+    fn given_options_and_their_conflicts_are_left_out() {
+        let config = Config::parse("fast = true\nseed = 3\nstats = true\nlayout = \"column\"\n").unwrap();
+
+        let args: Vec<OsString> = config.to_args(|key| key == "strict" || key == "shuffle");
+
+        assert_eq!(args, ["--stats"]);
+    }
+
+    #[test]
+    fn strings_are_unescaped() {
+        assert_eq!(args(r#"ext = "a\"b\\c""#), [r#"--ext=a"b\c"#]);
+    }
+
+    #[test]
+    fn invalid_configs_are_rejected() {
+        assert!(error("[table]").contains("Tables are not supported (line 1)"));
+        assert!(error("fast").contains("Expected 'key = value' on line 1"));
+        assert!(error("\ncolour = true").contains("Unknown option 'colour' on line 2"));
+        assert!(error("layout = true").contains("takes a value, not a boolean"));
+        assert!(error("in-place = true").contains("Unknown option 'in-place' on line 1"));
+        assert!(error("fast = 1").contains("must be true or false"));
+        assert!(error("fast = true\nfast = false").contains("given more than once (line 2)"));
+        assert!(error("ext = \"bmp").contains("Unterminated string on line 1"));
+        assert!(error("ext = \"bmp\" png").contains("Unexpected text after the value on line 1"));
+        assert!(error("seed = ten").contains("Invalid value 'ten' on line 1"));
+    }
+
+    #[test]
+    fn invalid_configs_are_usage_errors() {
+        assert_eq!(Config::parse("colour = true").unwrap_err().exit_code(), crate::error::EXIT_USAGE);
+    }
+}
@@ -5,9 +5,27 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum ErrorKind {
     IOError(std::io::Error),
     BincodeError(bincode::Error),
+    JsonError(serde_json::Error),
     InvalidBitmapID,
     InvalidB2BSignature,
     BadPaddingSize,
+    BadFileSize,
+    BadHeaderSize,
+    BadPixmapSize,
+    BadOriginalFileSize,
+    OffsetOverflow,
+    BadHashRange,
+    FileChanged,
+    Cancelled,
+    VerificationFailed,
+    InvalidPng,
+    HeaderSizeMismatch,
+    ExpansionTooLarge,
+    InvalidConfig,
+    DimensionTooLarge,
+    InvalidOption,
+    InvalidManifest,
+    FileTooLarge,
 }
 
 #[derive(Debug)]
@@ -16,6 +34,19 @@ pub struct Error {
     error: Box<dyn std::error::Error>,
 }
 
+/// Exit code for errors reading or writing files, including files modified during conversion and cancelled conversions,
+/// and for internal errors
+pub const EXIT_IO: i32 = 1;
+
+/// Exit code when the converted file does not match the digest or CRC stored in the bitmap
+pub const EXIT_VERIFICATION: i32 = 2;
+
+/// Exit code for invalid command line arguments and config files, `EX_USAGE` from `sysexits.h`
+pub const EXIT_USAGE: i32 = 64;
+
+/// Exit code when a bitmap is not a valid b2b bitmap, or its header is corrupt
+pub const EXIT_CORRUPT: i32 = 3;
+
 impl Error {
     pub fn new<E: Into<Box<dyn std::error::Error>>>(kind: ErrorKind, error: E) -> Self {
         Self {
@@ -23,6 +54,37 @@ impl Error {
             error: error.into(),
         }
     }
+
+    /// Code the process should exit with when it fails with this error
+    pub fn exit_code(&self) -> i32 {
+        match self.kind {
+            ErrorKind::IOError(_) | ErrorKind::JsonError(_) | ErrorKind::FileChanged | ErrorKind::Cancelled | ErrorKind::HeaderSizeMismatch | ErrorKind::ExpansionTooLarge | ErrorKind::InvalidManifest | ErrorKind::DimensionTooLarge | ErrorKind::FileTooLarge => EXIT_IO,
+            ErrorKind::VerificationFailed => EXIT_VERIFICATION,
+            ErrorKind::InvalidOption | ErrorKind::InvalidConfig => EXIT_USAGE,
+            ErrorKind::BincodeError(_)
+            | ErrorKind::InvalidBitmapID
+            | ErrorKind::InvalidB2BSignature
+            | ErrorKind::BadPaddingSize
+            | ErrorKind::BadFileSize
+            | ErrorKind::BadHeaderSize
+            | ErrorKind::BadPixmapSize
+            | ErrorKind::BadOriginalFileSize
+            | ErrorKind::OffsetOverflow
+            | ErrorKind::BadHashRange
+            | ErrorKind::InvalidPng => EXIT_CORRUPT,
+        }
+    }
+
+    /// Whether the error may go away if the operation is tried again, such as a file that is briefly busy or a
+    /// network filesystem that timed out. Errors in the contents of a file never are
+    pub fn is_transient(&self) -> bool {
+        use std::io::ErrorKind::*;
+
+        match &self.kind {
+            ErrorKind::IOError(err) => matches!(err.kind(), Interrupted | TimedOut | WouldBlock | ConnectionReset | ConnectionAborted | NotConnected | BrokenPipe | ResourceBusy | ExecutableFileBusy | StaleNetworkFileHandle),
+            _ => false,
+        }
+    }
 }
 
 impl From<std::io::Error> for Error {
@@ -41,4 +103,107 @@ impl From<bincode::Error> for Error {
             error: "".into(),
         }
     }
-}
\ No newline at end of file
+}
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Self {
+        Self {
+            kind: ErrorKind::JsonError(err),
+            error: "".into(),
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::IOError(err) => write!(f, "I/O error: {}", err),
+            ErrorKind::BincodeError(err) => write!(f, "Could not read header: {}", err),
+            ErrorKind::JsonError(err) => write!(f, "Could not read or write JSON: {}", err),
+            ErrorKind::InvalidBitmapID => write!(f, "File is not a bitmap (invalid bitmap ID)"),
+            ErrorKind::InvalidB2BSignature => write!(f, "Bitmap was not created by b2b (invalid signature)"),
+            ErrorKind::BadPaddingSize => write!(f, "Header contains an invalid padding size"),
+            ErrorKind::BadFileSize => write!(f, "File size does not match the size recorded in the header"),
+            ErrorKind::BadHeaderSize => write!(f, "Header contains an invalid header size"),
+            ErrorKind::BadPixmapSize => write!(f, "Pixmap size does not match the width and height of the bitmap"),
+            ErrorKind::BadOriginalFileSize => write!(f, "Header contains an invalid original file size"),
+            ErrorKind::OffsetOverflow => write!(f, "Header fields give offsets outside the range of a bitmap"),
+            ErrorKind::BadHashRange => write!(f, "Invalid hash range"),
+            ErrorKind::FileChanged => write!(f, "File was modified during conversion"),
+            ErrorKind::Cancelled => write!(f, "Conversion was cancelled"),
+            ErrorKind::VerificationFailed => write!(f, "Converted file does not match the stored digest"),
+            ErrorKind::InvalidPng => write!(f, "Png was not written by b2b, or is corrupt"),
+            ErrorKind::HeaderSizeMismatch => write!(f, "Declared header size does not match the serialized header"),
+            ErrorKind::ExpansionTooLarge => write!(f, "Bitmap would be too much larger than the file"),
+            ErrorKind::InvalidConfig => write!(f, "Invalid config file"),
+            ErrorKind::DimensionTooLarge => write!(f, "Bitmap would be larger than the maximum dimension"),
+            ErrorKind::InvalidOption => write!(f, "Invalid option"),
+            ErrorKind::InvalidManifest => write!(f, "Invalid manifest"),
+            ErrorKind::FileTooLarge => write!(f, "File is too large to fit in a bitmap"),
+        }
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description = self.error.to_string();
+
+        if description.is_empty() {
+            write!(f, "{}", self.kind)
+        } else {
+            write!(f, "{} ({})", self.kind, description)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exit_code(kind: ErrorKind) -> i32 {
+        Error::new(kind, "").exit_code()
+    }
+
+    #[test]
+    fn every_kind_has_its_exit_code() {
+        let cases = [
+            (ErrorKind::IOError(std::io::Error::from(std::io::ErrorKind::NotFound)), EXIT_IO),
+            (ErrorKind::BincodeError(Box::new(bincode::ErrorKind::SizeLimit)), EXIT_CORRUPT),
+            (ErrorKind::JsonError(serde_json::from_str::<u8>("x").unwrap_err()), EXIT_IO),
+            (ErrorKind::InvalidBitmapID, EXIT_CORRUPT),
+            (ErrorKind::InvalidB2BSignature, EXIT_CORRUPT),
+            (ErrorKind::BadPaddingSize, EXIT_CORRUPT),
+            (ErrorKind::BadFileSize, EXIT_CORRUPT),
+            (ErrorKind::BadHeaderSize, EXIT_CORRUPT),
+            (ErrorKind::BadPixmapSize, EXIT_CORRUPT),
+            (ErrorKind::BadOriginalFileSize, EXIT_CORRUPT),
+            (ErrorKind::OffsetOverflow, EXIT_CORRUPT),
+            (ErrorKind::BadHashRange, EXIT_CORRUPT),
+            (ErrorKind::FileChanged, EXIT_IO),
+            (ErrorKind::Cancelled, EXIT_IO),
+            (ErrorKind::VerificationFailed, EXIT_VERIFICATION),
+            (ErrorKind::InvalidPng, EXIT_CORRUPT),
+            (ErrorKind::HeaderSizeMismatch, EXIT_IO),
+            (ErrorKind::ExpansionTooLarge, EXIT_IO),
+            (ErrorKind::InvalidConfig, EXIT_USAGE),
+            (ErrorKind::DimensionTooLarge, EXIT_IO),
+            (ErrorKind::InvalidOption, EXIT_USAGE),
+            (ErrorKind::InvalidManifest, EXIT_IO),
+            (ErrorKind::FileTooLarge, EXIT_IO),
+        ];
+
+        for (kind, expected) in cases {
+            let name = format!("{:?}", kind);
+
+            assert_eq!(exit_code(kind), expected, "{}", name);
+        }
+    }
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let codes = [EXIT_IO, EXIT_VERIFICATION, EXIT_USAGE, EXIT_CORRUPT];
+
+        for (i, code) in codes.iter().enumerate() {
+            assert!(!codes[i + 1..].contains(code), "exit code {} is used twice", code);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+/// A single part of a glob pattern
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Literal(char),
+    /// `?`, any character except `/`
+    Any,
+    /// `*`, any run of characters except `/`
+    Star,
+    /// `**` not followed by `/`, any run of characters
+    AnyPath,
+    /// `**/`, any number of whole directories, including none
+    Directories,
+    /// `[...]`, any character in the ranges, or not in them if negated. Never matches `/`
+    Class { negated: bool, ranges: Vec<(char, char)> },
+}
+
+/// A glob pattern, as used by `--include` and `--exclude`. Supports `?`, `*`, `**` and character classes such as
+/// `[a-z]` and `[!0-9]`. Patterns without a `/` are matched against the file name, others against the whole path
+#[derive(Debug, Clone, PartialEq)]
+pub struct Glob {
+    tokens: Vec<Token>,
+    file_name_only: bool,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> std::result::Result<Self, String> {
+        let mut tokens = Vec::new();
+        let mut chars = pattern.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let token = match c {
+                '?' => Token::Any,
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        Token::Directories
+                    } else {
+                        Token::AnyPath
+                    }
+                }
+                '*' => Token::Star,
+                '[' => {
+                    let negated = chars.next_if(|&c| c == '!' || c == '^').is_some();
+                    let mut ranges = Vec::new();
+
+                    loop {
+                        let start = match chars.next() {
+                            //A `]` straight after the `[` is part of the class
+                            Some(']') if !ranges.is_empty() => break,
+                            Some(c) => c,
+                            None => return Err(format!("Unterminated character class in '{}'", pattern)),
+                        };
+
+                        let end = match chars.next_if_eq(&'-') {
+                            Some(_) => match chars.next() {
+                                //A trailing `-` is a literal
+                                Some(']') => {
+                                    ranges.push((start, start));
+                                    ranges.push(('-', '-'));
+                                    break;
+                                }
+                                Some(end) => end,
+                                None => return Err(format!("Unterminated character class in '{}'", pattern)),
+                            },
+                            None => start,
+                        };
+
+                        ranges.push((start, end));
+                    }
+
+                    Token::Class { negated, ranges }
+                }
+                '\\' => Token::Literal(chars.next().ok_or_else(|| format!("Trailing '\\' in '{}'", pattern))?),
+                c => Token::Literal(c),
+            };
+
+            tokens.push(token);
+        }
+
+        Ok(Self {
+            tokens,
+            file_name_only: !pattern.contains('/'),
+        })
+    }
+
+    /// Whether `path`, relative to the directory being searched and separated by `/`, matches the pattern
+    pub fn matches(&self, path: &str) -> bool {
+        let text = if self.file_name_only {
+            path.rsplit('/').next().unwrap_or(path)
+        } else {
+            path
+        };
+
+        matches_tokens(&self.tokens, &text.chars().collect::<Vec<_>>())
+    }
+}
+
+fn matches_tokens(tokens: &[Token], text: &[char]) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return text.is_empty(),
+    };
+
+    match token {
+        Token::Literal(c) => text.first() == Some(c) && matches_tokens(rest, &text[1..]),
+        Token::Any => text.first().is_some_and(|&c| c != '/') && matches_tokens(rest, &text[1..]),
+        Token::Class { negated, ranges } => text.first().is_some_and(|&c| {
+            c != '/' && ranges.iter().any(|&(start, end)| start <= c && c <= end) != *negated
+        }) && matches_tokens(rest, &text[1..]),
+        Token::Star => {
+            let segment = text.iter().position(|&c| c == '/').unwrap_or(text.len());
+
+            (0..=segment).any(|i| matches_tokens(rest, &text[i..]))
+        }
+        Token::AnyPath => (0..=text.len()).any(|i| matches_tokens(rest, &text[i..])),
+        Token::Directories => {
+            matches_tokens(rest, text) || text.iter().enumerate().any(|(i, &c)| c == '/' && matches_tokens(rest, &text[i + 1..]))
+        }
+    }
+}
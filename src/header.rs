@@ -1,19 +1,43 @@
-use serde::{Serialize, Deserialize};
+use serde::{Serialize, Deserialize, Serializer, Deserializer};
 
 use crate::error::{Result, ErrorKind, Error};
+use crate::layout::Layout;
+use crate::crc::Crc32;
 
+use std::convert::TryFrom;
+use std::ops::Range;
+
+/// Size of the pixels in bitmaps written by b2b. Use `Header::bytes_per_pixel` for the size in an existing bitmap
 pub const BYTES_PER_PIXEL: u32 = 4;
-pub const B2B_SIGNATURE: u128 = 0x6FAFEC0D7EF10C4468E85B0B9C0FB9E;
+/// Signature of the current b2b header format. The signature doubles as the format version: whenever the fields of
+/// `B2BHeader` change, it must change too, and the old signature keeps a decode path (see `B2BHeader`)
+pub const B2B_SIGNATURE: u128 = 0xB2B0_0002_3C5E_91A7_6D08_F4E2_1B97_C0DA;
+/// Signature of the first b2b header format, which only holds the padding size, original file size, signature and
+/// digest. Bitmaps written by the first release carry it
+pub const LEGACY_B2B_SIGNATURE: u128 = 0x6FAFEC0D7EF10C4468E85B0B9C0FB9E;
 pub const BITMAP_HEADER_SIZE: u32 = 0x8A;
 pub const BITMAP_ID: u16 = 0x4D42;
-pub const B2B_HEADER_SIZE: u32 = 40;
+pub const B2B_HEADER_SIZE: u32 = 68;
+/// Size of the b2b header of the first format (see `LEGACY_B2B_SIGNATURE`)
+pub const LEGACY_B2B_HEADER_SIZE: u32 = 40;
+/// Number of bytes of the Blake256 digest kept in the header. The digest is truncated to fit in a u128
+pub const DIGEST_STORE_BYTES: usize = 16;
+/// Size of the header region of bitmaps created with `--canonical`: the headers followed by zeros, filling a whole
+/// block so that no block holds both header fields and file data
+pub const CANONICAL_BLOCK_SIZE: u32 = 4096;
+/// Default smallest width and height of a bitmap, as some viewers reject images only a pixel or two across
+pub const DEFAULT_MIN_DIMENSION: u32 = 4;
+
+const _: () = assert!(DIGEST_STORE_BYTES == std::mem::size_of::<u128>(), "Stored digest must fill a u128");
 
 #[derive(Serialize, Deserialize)]
 struct BitmapV5Header {
     //BMP Header
     id: u16,
     file_size: u32,
-    unused1: u32,
+    /// The reserved bytes of the bitmap file header, which hold the CRC32 of the b2b header (see `Header::header_crc_matches`)
+    #[serde(alias = "unused1")]
+    header_crc: u32,
     offset: u32,
 
     //DIB Header
@@ -33,7 +57,10 @@ struct BitmapV5Header {
     blue_mask: u32,
     alpha_mask: u32,
     win: u32,
-    unused2a: u128,
+    /// Digest of the whole bitmap, taken with this field zeroed, or zero if none was stored (see `Header::bitmap_digest`).
+    /// These bytes are the colour space endpoints, which are ignored for the sRGB colour space in `win`
+    #[serde(alias = "unused2a")]
+    bitmap_digest: u128,
     unused2b: u128,
     unused2c: u32,
     red_gamma: u32,
@@ -45,41 +72,272 @@ struct BitmapV5Header {
     reserved: u32,
 }
 
-///If MSB of the u128 is set, the other bits represent the digest. if MSB is 0, there is no digest
-#[derive(Serialize, Deserialize, Clone)]
+///If MSB of the u128 is set, the other bits represent the digest. if MSB is 0, there is no digest. The flag takes the
+///place of the top bit of the digest, so only the low 127 bits of a digest are stored and compared
+#[derive(Clone)]
 struct CompactOptionalDigest(u128);
 
+/// Binary formats store the compact u128, human readable formats (i.e. JSON) store the digest as a hex string or null
+impl Serialize for CompactOptionalDigest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            self.to_hex().serialize(serializer)
+        } else {
+            self.0.serialize(serializer)
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for CompactOptionalDigest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            match Option::<String>::deserialize(deserializer)? {
+                None => Ok(Self::new(None)),
+                Some(hex) => u128::from_str_radix(&hex, 16)
+                    .map(|digest| Self::new(Some(digest)))
+                    .map_err(serde::de::Error::custom),
+            }
+        } else {
+            u128::deserialize(deserializer).map(Self)
+        }
+    }
+}
+
 impl Copy for CompactOptionalDigest {}
 
+/// The digest as 32 lowercase hex digits, or "none"
+impl std::fmt::Display for CompactOptionalDigest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.to_hex() {
+            Some(hex) => write!(f, "{}", hex),
+            None => write!(f, "none"),
+        }
+    }
+}
+
 impl CompactOptionalDigest {
+    /// Bit marking that a digest is present
+    const FLAG: u128 = 1 << 127;
+
+    /// Bits of a digest that are stored
+    const DIGEST_MASK: u128 = !Self::FLAG;
+
     fn new(optional_digest: Option<u128>) -> Self {
         let compact = match optional_digest {
             None => {0}
-            Some(num) => {num | (1 << 127)}
+            //Digests with their top bit set lose it, exactly as `compare` ignores it
+            Some(num) => {(num & Self::DIGEST_MASK) | Self::FLAG}
         };
         Self(compact)
     }
 
     fn get(&self) -> Option<u128> {
 
-        if self.0 & (1 << 127) != 0 {
-            Some(self.0 & !(1u128 << 127))
+        if self.0 & Self::FLAG != 0 {
+            Some(self.0 & Self::DIGEST_MASK)
         } else {
             None
         }
     }
 
+    /// The digest as 32 lowercase hex digits. The flag bit is not part of the digest, so the first digit is at most 7
+    fn to_hex(self) -> Option<String> {
+        self.get().map(|digest| format!("{:032x}", digest))
+    }
+
+    /// Whether `other` matches the stored digest in the bits that are stored. There must be a digest
     fn compare(&self, other: u128) -> bool {
-        self.get().unwrap() == (other & !(1u128 << 127))
+        self.get().unwrap() == (other & Self::DIGEST_MASK)
     }
 }
 
-#[derive(Serialize, Deserialize)]
+/// The b2b header. The fields of the first format (see `LEGACY_B2B_SIGNATURE`) come first and include the signature,
+/// which says whether the fields after them are present. New fields must only ever be added after a change of
+/// signature, so that every format ever written can still be read
 struct B2BHeader {
     padding_size: u32,
     original_file_size: u32,
     signature: u128,
     od: CompactOptionalDigest,
+    /// CRC32 of the original file, only meaningful if `od` contains a digest
+    crc: u32,
+    /// Combined size of the bitmap and b2b headers as written, which is also the number of bytes moved
+    /// from the beginning of the original file to the end of the bitmap
+    header_size: u32,
+    /// How the data following the b2b header is arranged across the pixels
+    layout: Layout,
+    /// The digest and CRC only cover the bytes of the original file from `hash_start` up to (but not including) `hash_end`
+    hash_start: u32,
+    hash_end: u32,
+    /// Seed of the permutation used by `Layout::Shuffled`, or the tile size of `Layout::Tiled`, unused by the other
+    /// layouts
+    shuffle_seed: u64,
+}
+
+/// Number of fields of `B2BHeader` in the first format
+const LEGACY_FIELDS: usize = 4;
+
+/// Every field of `B2BHeader`, in the order they are written
+const B2B_FIELDS: [&str; 10] = ["padding_size", "original_file_size", "signature", "od", "crc", "header_size", "layout", "hash_start", "hash_end", "shuffle_seed"];
+
+/// Headers with the legacy signature only write the fields of the first format
+impl Serialize for B2BHeader {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let fields = if self.is_legacy() { LEGACY_FIELDS } else { B2B_FIELDS.len() };
+
+        let mut state = serializer.serialize_struct("B2BHeader", fields)?;
+
+        state.serialize_field("padding_size", &self.padding_size)?;
+        state.serialize_field("original_file_size", &self.original_file_size)?;
+        state.serialize_field("signature", &self.signature)?;
+        state.serialize_field("od", &self.od)?;
+
+        if !self.is_legacy() {
+            state.serialize_field("crc", &self.crc)?;
+            state.serialize_field("header_size", &self.header_size)?;
+            state.serialize_field("layout", &self.layout)?;
+            state.serialize_field("hash_start", &self.hash_start)?;
+            state.serialize_field("hash_end", &self.hash_end)?;
+            state.serialize_field("shuffle_seed", &self.shuffle_seed)?;
+        }
+
+        state.end()
+    }
+}
+
+/// The fields of `B2BHeader` as written to JSON, where the fields after the first format are missing for legacy headers
+#[derive(Deserialize)]
+struct B2BHeaderFields {
+    padding_size: u32,
+    original_file_size: u32,
+    signature: u128,
+    od: CompactOptionalDigest,
+    crc: Option<u32>,
+    header_size: Option<u32>,
+    layout: Option<Layout>,
+    hash_start: Option<u32>,
+    hash_end: Option<u32>,
+    shuffle_seed: Option<u64>,
+}
+
+/// Reads the fields of the first format, then the rest only if the signature says they are there
+struct B2BHeaderVisitor;
+
+impl<'de> serde::de::Visitor<'de> for B2BHeaderVisitor {
+    type Value = B2BHeader;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a b2b header")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<B2BHeader, A::Error> {
+        fn next<'de, T: Deserialize<'de>, A: serde::de::SeqAccess<'de>>(seq: &mut A, index: usize) -> std::result::Result<T, A::Error> {
+            seq.next_element()?.ok_or_else(|| serde::de::Error::invalid_length(index, &"a b2b header"))
+        }
+
+        let padding_size = next(& mut seq, 0)?;
+        let original_file_size = next(& mut seq, 1)?;
+        let signature: u128 = next(& mut seq, 2)?;
+        let od = next(& mut seq, 3)?;
+
+        if signature == LEGACY_B2B_SIGNATURE {
+            return Ok(B2BHeader::legacy(padding_size, original_file_size, od));
+        }
+
+        Ok(B2BHeader {
+            padding_size,
+            original_file_size,
+            signature,
+            od,
+            crc: next(& mut seq, 4)?,
+            header_size: next(& mut seq, 5)?,
+            layout: next(& mut seq, 6)?,
+            hash_start: next(& mut seq, 7)?,
+            hash_end: next(& mut seq, 8)?,
+            shuffle_seed: next(& mut seq, 9)?,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for B2BHeader {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        if !deserializer.is_human_readable() {
+            return deserializer.deserialize_tuple(B2B_FIELDS.len(), B2BHeaderVisitor);
+        }
+
+        let fields = B2BHeaderFields::deserialize(deserializer)?;
+
+        if fields.signature == LEGACY_B2B_SIGNATURE {
+            return Ok(B2BHeader::legacy(fields.padding_size, fields.original_file_size, fields.od));
+        }
+
+        let missing = |name| serde::de::Error::missing_field(name);
+
+        Ok(B2BHeader {
+            padding_size: fields.padding_size,
+            original_file_size: fields.original_file_size,
+            signature: fields.signature,
+            od: fields.od,
+            crc: fields.crc.ok_or_else(|| missing("crc"))?,
+            header_size: fields.header_size.ok_or_else(|| missing("header_size"))?,
+            layout: fields.layout.ok_or_else(|| missing("layout"))?,
+            hash_start: fields.hash_start.ok_or_else(|| missing("hash_start"))?,
+            hash_end: fields.hash_end.ok_or_else(|| missing("hash_end"))?,
+            shuffle_seed: fields.shuffle_seed.ok_or_else(|| missing("shuffle_seed"))?,
+        })
+    }
+}
+
+/// Result of checking a decoded file against the digest or CRC stored in its bitmap. Every outcome but `Verified`
+/// and `NoDigest` means the check failed, so an outcome that is dropped is a failure that goes unreported
+#[must_use]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VerifyOutcome {
+    /// The file matches the stored digest
+    Verified,
+    /// The header is intact but the file doesn't match it, so the file data was corrupted
+    PayloadCorrupt,
+    /// The header doesn't match its own CRC, so the stored digest was changed and can't be trusted
+    HeaderTampered,
+    /// The file doesn't match, but the bitmap was written without a header CRC, so it can't tell which was changed
+    Mismatch,
+    /// No digest was stored, so the file can't be verified
+    NoDigest,
+}
+
+/// What the outcome means for the decoded file
+impl std::fmt::Display for VerifyOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VerifyOutcome::Verified => write!(f, "the file matches the stored digest"),
+            VerifyOutcome::PayloadCorrupt => write!(f, "the header is intact but the file does not match it, so the file data is corrupt"),
+            VerifyOutcome::HeaderTampered => write!(f, "the header does not match its CRC, so the stored digest was modified and the file may well be intact"),
+            VerifyOutcome::Mismatch => write!(f, "the file does not match the stored digest, and the bitmap predates the header CRC, so either may have been modified"),
+            VerifyOutcome::NoDigest => write!(f, "the bitmap doesn't contain a digest"),
+        }
+    }
+}
+
+impl VerifyOutcome {
+    /// Whether the file was verified, or `None` if it couldn't be
+    pub fn verified(self) -> Option<bool> {
+        match self {
+            VerifyOutcome::Verified => Some(true),
+            VerifyOutcome::NoDigest => None,
+            _ => Some(false),
+        }
+    }
+
+    /// Whether the outcome counts as a failed verification. A missing digest only does if `strict` is set
+    pub fn is_failure(self, strict: bool) -> bool {
+        match self {
+            VerifyOutcome::Verified => false,
+            VerifyOutcome::NoDigest => strict,
+            _ => true,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize)]
@@ -88,22 +346,21 @@ pub struct Header {
     b2b: B2BHeader,
 }
 
-impl BitmapV5Header {
-    fn new(width: u32, height: u32, pixmap_size: u32) -> Self {
-        let file_size = pixmap_size + BITMAP_HEADER_SIZE;
-
+/// A V5 bitmap header with every constant field filled in, and zero width, height and pixmap size
+impl Default for BitmapV5Header {
+    fn default() -> Self {
         Self {
             id: BITMAP_ID,
-            file_size,
-            unused1: 0,
+            file_size: BITMAP_HEADER_SIZE,
+            header_crc: 0,
             offset: BITMAP_HEADER_SIZE,
             dib_size: BITMAP_HEADER_SIZE - 14,
-            width,
-            height,
+            width: 0,
+            height: 0,
             pbnlanes: 1,
             bpp: BYTES_PER_PIXEL as u16 * 8,
             compression: 3,
-            pixmap_size,
+            pixmap_size: 0,
             horizontal: 4000,
             vertical: 4000,
             palette: 0,
@@ -113,7 +370,7 @@ impl BitmapV5Header {
             blue_mask: 0xFF,
             alpha_mask: 0xFF000000,
             win: 0x57696E20,
-            unused2a: 0,
+            bitmap_digest: 0,
             unused2b: 0,
             unused2c: 0,
             red_gamma: 0,
@@ -127,35 +384,423 @@ impl BitmapV5Header {
     }
 }
 
+impl BitmapV5Header {
+    /// `gap` is the number of bytes between the end of the bitmap header and the start of the pixels
+    fn new(width: u32, height: u32, pixmap_size: u32, gap: u32) -> Self {
+        Self {
+            file_size: pixmap_size + BITMAP_HEADER_SIZE + gap,
+            offset: BITMAP_HEADER_SIZE + gap,
+            width,
+            height,
+            pixmap_size,
+            ..Self::default()
+        }
+    }
+}
+
 impl B2BHeader {
-    fn new(padding_size: u32, file_size: u64, optional_digest: Option<u128>) -> Self {
+    fn new(padding_size: u32, file_size: u64, optional_digests: Option<(u128, u32)>) -> Self {
         Self {
             padding_size,
             original_file_size: file_size as u32,
             signature: B2B_SIGNATURE,
-            od: CompactOptionalDigest::new(optional_digest),
+            od: CompactOptionalDigest::new(optional_digests.map(|(digest, _)| digest)),
+            crc: optional_digests.map_or(0, |(_, crc)| crc),
+            header_size: BITMAP_HEADER_SIZE + B2B_HEADER_SIZE,
+            layout: Layout::Row,
+            hash_start: 0,
+            hash_end: file_size as u32,
+            shuffle_seed: 0,
+        }
+    }
+
+    /// A header of the first format. The fields it lacks are given the values they stood for then: no CRC, a header
+    /// region of the bitmap header and the legacy b2b header, the row layout and a digest of the whole file
+    fn legacy(padding_size: u32, original_file_size: u32, od: CompactOptionalDigest) -> Self {
+        Self {
+            padding_size,
+            original_file_size,
+            signature: LEGACY_B2B_SIGNATURE,
+            od,
+            crc: 0,
+            header_size: BITMAP_HEADER_SIZE + LEGACY_B2B_HEADER_SIZE,
+            layout: Layout::Row,
+            hash_start: 0,
+            hash_end: original_file_size,
+            shuffle_seed: 0,
         }
     }
+
+    fn is_legacy(&self) -> bool { self.signature == LEGACY_B2B_SIGNATURE }
+
+    /// Number of bytes the header takes up when written
+    fn serialized_len(&self) -> u32 {
+        if self.is_legacy() { LEGACY_B2B_HEADER_SIZE } else { B2B_HEADER_SIZE }
+    }
+}
+
+/// Whether `signature` is the b2b signature of any format that can be read
+fn is_b2b_signature(signature: u128) -> bool {
+    signature == B2B_SIGNATURE || signature == LEGACY_B2B_SIGNATURE
+}
+
+/// Bitmap header for a `width` by `height` pixel b2b bitmap, as written to the start of the file. Used to rebuild a
+/// bitmap from a container that only keeps its pixels
+pub fn bitmap_header_bytes(width: u32, height: u32) -> Result<Vec<u8>> {
+    Ok(bincode::serialize(&BitmapV5Header::new(width, height, width * height * BYTES_PER_PIXEL, 0))?)
+}
+
+/// Whether `bytes` starts with a b2b header, i.e. one with the b2b signature
+pub fn is_b2b_header(bytes: &[u8]) -> bool {
+    bincode::deserialize::<B2BHeader>(bytes).is_ok_and(|b2b| is_b2b_signature(b2b.signature))
+}
+
+/// The b2b signature of the bitmap that `bytes` start with, if they start with a bitmap header followed by the b2b
+/// signature of any format, whether or not the rest of the b2b header can be read. Reads no more than the bitmap header
+/// and the first format's b2b header
+pub fn b2b_signature(bytes: &[u8]) -> Option<u128> {
+    bincode::deserialize::<(BitmapV5Header, u32, u32, u128)>(bytes).ok()
+        .filter(|(bmp, _, _, signature)| bmp.id == BITMAP_ID && is_b2b_signature(*signature))
+        .map(|(_, _, _, signature)| signature)
+}
+
+/// Whether `bytes` start with a bitmap header followed by the b2b signature of any format (see `b2b_signature`)
+pub fn has_b2b_signature(bytes: &[u8]) -> bool {
+    b2b_signature(bytes).is_some()
+}
+
+/// Reads the b2b header at the start of `pixmap` (a bitmap without its bitmap header), returning the size of the
+/// original file and the header size it records. Returns `None` if the b2b header is damaged, i.e. its signature is
+/// wrong or its sizes don't add up to the length of the pixmap
+pub fn stored_lengths(pixmap: &[u8]) -> Option<(u64, u64)> {
+    let b2b: B2BHeader = bincode::deserialize(pixmap).ok()?;
+
+    let region = b2b.header_size.checked_sub(BITMAP_HEADER_SIZE)?;
+
+    if !is_b2b_signature(b2b.signature) || region < b2b.serialized_len()
+        || region as u64 + b2b.original_file_size as u64 + b2b.padding_size as u64 != pixmap.len() as u64 {
+        None
+    } else {
+        Some((b2b.original_file_size as u64, b2b.header_size as u64))
+    }
+}
+
+/// Name and serialized size of every field of the bitmap and b2b headers, in the order they are written
+pub const HEADER_FIELDS: [(&str, u32); 40] = [
+    ("bmp.id", 2),
+    ("bmp.file_size", 4),
+    ("bmp.header_crc", 4),
+    ("bmp.offset", 4),
+    ("bmp.dib_size", 4),
+    ("bmp.width", 4),
+    ("bmp.height", 4),
+    ("bmp.pbnlanes", 2),
+    ("bmp.bpp", 2),
+    ("bmp.compression", 4),
+    ("bmp.pixmap_size", 4),
+    ("bmp.horizontal", 4),
+    ("bmp.vertical", 4),
+    ("bmp.palette", 4),
+    ("bmp.important", 4),
+    ("bmp.red_mask", 4),
+    ("bmp.green_mask", 4),
+    ("bmp.blue_mask", 4),
+    ("bmp.alpha_mask", 4),
+    ("bmp.win", 4),
+    ("bmp.bitmap_digest", 16),
+    ("bmp.unused2b", 16),
+    ("bmp.unused2c", 4),
+    ("bmp.red_gamma", 4),
+    ("bmp.green_gamma", 4),
+    ("bmp.blue_gamma", 4),
+    ("bmp.intent", 4),
+    ("bmp.profile_data", 4),
+    ("bmp.profile_size", 4),
+    ("bmp.reserved", 4),
+    ("b2b.padding_size", 4),
+    ("b2b.original_file_size", 4),
+    ("b2b.signature", 16),
+    ("b2b.od", 16),
+    ("b2b.crc", 4),
+    ("b2b.header_size", 4),
+    ("b2b.layout", 4),
+    ("b2b.hash_start", 4),
+    ("b2b.hash_end", 4),
+    ("b2b.shuffle_seed", 8),
+];
+
+/// Number of fields of `HEADER_FIELDS` written by headers of the first format (see `LEGACY_B2B_SIGNATURE`)
+pub const LEGACY_HEADER_FIELDS: usize = HEADER_FIELDS.len() - (B2B_FIELDS.len() - LEGACY_FIELDS);
+
+/// Range of bytes taken up by the field `name` of `HEADER_FIELDS`, counting from the start of the bitmap
+fn header_field_range(name: &str) -> Range<u64> {
+    let start: u32 = HEADER_FIELDS.iter().take_while(|(field, _)| *field != name).map(|(_, size)| size).sum();
+
+    let size = HEADER_FIELDS.iter().find(|(field, _)| *field == name).map_or(0, |(_, size)| *size);
+
+    start as u64..(start + size) as u64
+}
+
+/// Checks that `BITMAP_HEADER_SIZE` and `B2B_HEADER_SIZE` match the serialized sizes of the headers. All of the
+/// seeks assume they do, so this should be run before any file is touched. Fails with `ErrorKind::HeaderSizeMismatch`
+/// if a field has been added to or removed from a header without updating its constant or `HEADER_FIELDS`
+pub fn check_header_constants() -> Result<()> {
+    let header = Header {
+        bmp: BitmapV5Header::default(),
+        b2b: B2BHeader::new(0, 0, None),
+    };
+
+    check_header_size_constant("BITMAP_HEADER_SIZE", BITMAP_HEADER_SIZE, bincode::serialize(&header.bmp)?.len())?;
+
+    check_header_size_constant("B2B_HEADER_SIZE", B2B_HEADER_SIZE, header.b2b_header_serialized().len())?;
+
+    check_header_size_constant("HEADER_FIELDS", HEADER_FIELDS.iter().map(|(_, size)| size).sum(), header.serialized_len() as usize)
+}
+
+fn check_header_size_constant(name: &str, declared: u32, serialized: usize) -> Result<()> {
+    if declared as usize != serialized {
+        Err(Error::new(ErrorKind::HeaderSizeMismatch, format!("{} is {} but the header serializes to {} bytes", name, declared, serialized)))
+    } else {
+        Ok(())
+    }
 }
 
 impl Header {
-    pub fn new(file_size: u64, optional_digest: Option<u128>) -> Self {
-        let (width, height, pixmap_size, padding_size) = Self::get_properties(file_size);
+    /// Creates a header for a file of `file_size` bytes. `optional_digests` holds the Blake256 digest
+    /// and CRC32 of the file, if verification is to be supported. The bitmap is at least `min_dimension`
+    /// pixels wide and tall. If `honor_offset` is set, the pixel offset in the bitmap header points past the
+    /// b2b header, so viewers that honour it don't draw the b2b header as pixels. If `canonical` is set, the header
+    /// region is padded with zeros to `CANONICAL_BLOCK_SIZE` bytes (see `canonical`). Fails if the file is too large to
+    /// fit in a bitmap
+    pub fn new(file_size: u64, optional_digests: Option<(u128, u32)>, min_dimension: u32, max_dimension: Option<u32>, honor_offset: bool, canonical: bool) -> Result<Self> {
+        let header_size = if canonical { CANONICAL_BLOCK_SIZE } else { Self::bitmap_header_size() + Self::b2b_header_size() };
 
-        Self {
-            bmp: BitmapV5Header::new(width, height, pixmap_size),
-            b2b: B2BHeader::new(padding_size, file_size, optional_digest),
+        let b2b_region = header_size - Self::bitmap_header_size();
+
+        let (width, height, pixmap_size, padding_size) = Self::get_properties(file_size, min_dimension, max_dimension, honor_offset, b2b_region)?;
+
+        let gap = if honor_offset { b2b_region } else { 0 };
+
+        let mut header = Self {
+            bmp: BitmapV5Header::new(width, height, pixmap_size - gap, gap),
+            b2b: B2BHeader::new(padding_size, file_size, optional_digests),
+        };
+
+        header.b2b.header_size = header_size;
+
+        header.seal();
+
+        Ok(header)
+    }
+
+    /// The b2b header as written to the bitmap, straight after the bitmap header. Useful when debugging the on-disk format
+    pub fn b2b_header_serialized(&self) -> Vec<u8> {
+        bincode::serialize(&self.b2b).expect("Header fields always serialize")
+    }
+
+    /// Number of bytes this header takes up when written to the start of a bitmap. For headers read back from a
+    /// bitmap, `header_size` gives the size of the header region that was actually written, which may be larger
+    pub fn serialized_len(&self) -> u64 {
+        bincode::serialized_size(self).expect("Header fields always serialize")
+    }
+
+    /// Reconstructs a header for a pixmap of `pixmap_len` bytes whose headers have been lost. The width and height are
+    /// the most nearly square pair that fits the pixmap exactly, which is how `Header::new` chooses them. The b2b header
+    /// is filled in as if the pixmap held no padding, as the real one is stored at the start of the pixmap. Returns
+    /// `None` if the length is not a whole number of pixels or is too small to hold a b2b header, or if the pixels only
+    /// factor into a shape more than twice as wide as it is tall
+    pub fn from_pixmap_len(pixmap_len: u64) -> Option<Header> {
+        let bytes_per_pixel = BYTES_PER_PIXEL as u64;
+
+        if !pixmap_len.is_multiple_of(bytes_per_pixel) || pixmap_len < Self::b2b_header_size() as u64 || pixmap_len > u32::MAX as u64 {
+            return None;
+        }
+
+        let pixels = pixmap_len / bytes_per_pixel;
+
+        let height = (1..=pixels.isqrt()).rev().find(|height| pixels.is_multiple_of(*height))?;
+        let width = pixels / height;
+
+        if width > 2 * height {
+            return None;
+        }
+
+        Self::from_parts(width as u32, height as u32, pixmap_len as u32, 0, pixmap_len as u32 - Self::b2b_header_size(), None).ok()
+    }
+
+    /// Builds a header from parts that are already known, such as those of an existing bitmap, instead of working them
+    /// out from the file size like `Header::new`. `pixmap_size` is the size of the pixels, so must be exactly `width` by
+    /// `height` pixels, and the b2b header, the original file and the padding must fill it exactly. The header uses the
+    /// row layout and the default header size. Fails with the error of the first check the parts don't pass
+    pub fn from_parts(width: u32, height: u32, pixmap_size: u32, padding_size: u32, original_file_size: u32, optional_digests: Option<(u128, u32)>) -> Result<Header> {
+        if pixmap_size > u32::MAX - Self::bitmap_header_size() {
+            return Err(Error::new(ErrorKind::BadPixmapSize, format!("{} bytes of pixels do not fit in a bitmap", pixmap_size)));
         }
+
+        let mut header = Self {
+            bmp: BitmapV5Header::new(width, height, pixmap_size, 0),
+            b2b: B2BHeader::new(padding_size, original_file_size as u64, optional_digests),
+        };
+
+        header.seal();
+
+        header.check_pixmap_alignment()?;
+
+        header.check_padding_size()?;
+
+        header.check_offsets()?;
+
+        header.check_header_size()?;
+
+        header.check_original_file_size()?;
+
+        Ok(header)
     }
 
+    /// Size of everything after the bitmap header: the b2b header, the file data and the padding. If the b2b header
+    /// is not drawn as pixels (see `pixel_gap`), this is larger than the size of the pixels in the bitmap header
     pub fn pixmap_size(&self) -> u32 {
-        self.bmp.pixmap_size
+        self.bmp.pixmap_size.saturating_add(self.pixel_gap())
     }
 
+    /// Number of bytes between the end of the bitmap header and the first pixel. Zero unless the bitmap was created
+    /// with the pixel offset pointing past the b2b header, in which case it is the size of the b2b header
+    pub fn pixel_gap(&self) -> u32 { self.bmp.offset.saturating_sub(Self::bitmap_header_size()) }
+
+    /// Number of bytes of the b2b header that are drawn as pixels, which the layouts count as the first pixels
+    pub fn header_bytes_in_pixels(&self) -> u32 { (self.header_size() - Self::bitmap_header_size()).saturating_sub(self.pixel_gap()) }
+
     pub fn padding_size(&self) -> u32 { self.b2b.padding_size }
 
     pub fn original_file_size(&self) -> u32 { self.b2b.original_file_size }
 
+    /// Size of the header region recorded in the bitmap. Unlike `serialized_len`, this reflects the
+    /// header that was actually written, and is what the decoder should use
+    pub fn header_size(&self) -> u32 { self.b2b.header_size }
+
+    /// Whether an original file of `file_size` bytes is smaller than the header region, so the file must grow before
+    /// the header fits at its start. Only the `file_size` bytes of such a file are stored after the header region
+    pub fn needs_expansion(&self, file_size: u64) -> bool { file_size < self.header_size() as u64 }
+
+    pub fn layout(&self) -> Layout { self.b2b.layout }
+
+    /// Whether the header region fills a whole block. The rest of the original file is then stored at the same
+    /// block aligned offsets as in the original, and the header never shares a block with file data, which suits
+    /// backup tools that deduplicate by block. Recorded by `header_size`, so the header needs no extra field
+    pub fn canonical(&self) -> bool { self.header_size() == CANONICAL_BLOCK_SIZE }
+
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.b2b.layout = layout;
+        self.seal();
+    }
+
+    pub fn shuffle_seed(&self) -> u64 { self.b2b.shuffle_seed }
+
+    pub fn set_shuffle_seed(&mut self, seed: u64) {
+        self.b2b.shuffle_seed = seed;
+        self.seal();
+    }
+
+    /// Side of the square tiles of `Layout::Tiled`, in pixels. Kept in the shuffle seed field, as no layout needs both,
+    /// so the header needs no extra field
+    pub fn tile_size(&self) -> u64 { self.b2b.shuffle_seed }
+
+    pub fn set_tile_size(&mut self, tile_size: u32) {
+        self.b2b.shuffle_seed = tile_size as u64;
+        self.seal();
+    }
+
+    /// What the layout needs to arrange and restore the pixels besides the shape of the bitmap: the seed of
+    /// `Layout::Shuffled` or the tile size of `Layout::Tiled` (see `Layout::arrange`)
+    pub fn layout_parameter(&self) -> u64 { self.b2b.shuffle_seed }
+
+    /// Range of bytes in the original file covered by the digest and CRC
+    pub fn hash_range(&self) -> Range<u64> { self.b2b.hash_start as u64..self.b2b.hash_end as u64 }
+
+    /// Restricts the digest and CRC to a range of the original file. The range must be valid for the file size
+    /// (see `check_hash_range`), and must be set before the digests are computed
+    pub fn set_hash_range(&mut self, range: Range<u64>) {
+        self.b2b.hash_start = range.start as u32;
+        self.b2b.hash_end = range.end as u32;
+        self.seal();
+    }
+
+    pub fn width(&self) -> u32 { self.bmp.width }
+
+    pub fn height(&self) -> u32 { self.bmp.height }
+
+    /// Size of each pixel, from the bit depth in the bitmap header. Bitmaps written by b2b are always 32 bit, so this
+    /// is `BYTES_PER_PIXEL` unless the header came from elsewhere
+    pub fn bytes_per_pixel(&self) -> u32 { self.bmp.bpp as u32 / 8 }
+
+    /// The b2b signature, which is `B2B_SIGNATURE` for bitmaps created by b2b (see `check_signature`)
+    pub fn signature(&self) -> u128 { self.b2b.signature }
+
+    /// Whether the header is of the first format, which lacks every field after the digest (see `LEGACY_B2B_SIGNATURE`)
+    pub fn is_legacy(&self) -> bool { self.b2b.is_legacy() }
+
+    /// The stored digest of the original file, truncated to 127 bits, or `None` if it was created without one
+    pub fn digest(&self) -> Option<u128> { self.b2b.od.get() }
+
+    /// The stored CRC32 of the original file. It is only stored alongside a digest, so is `None` without one, and
+    /// headers of the first format have none at all
+    pub fn crc(&self) -> Option<u32> { self.digest().filter(|_| !self.is_legacy()).map(|_| self.b2b.crc) }
+
+    /// The stored digest of the whole bitmap, or `None` if it was created without one (see `--bitmap-digest`)
+    pub fn bitmap_digest(&self) -> Option<u128> {
+        match self.bmp.bitmap_digest {
+            0 => None,
+            digest => Some(digest),
+        }
+    }
+
+    /// Where the digest of the whole bitmap is stored. These bytes are hashed as zeros, so the digest can cover itself
+    pub fn bitmap_digest_range() -> Range<u64> { header_field_range("bmp.bitmap_digest") }
+
+    /// The stored digest for display, as hex or "none"
+    pub fn digest_display(&self) -> impl std::fmt::Display { self.b2b.od }
+
+    /// Replaces the stored digest and CRC. Passing `None` removes them, so the bitmap can no longer be verified
+    pub fn set_digests(&mut self, optional_digests: Option<(u128, u32)>) {
+        self.b2b.od = CompactOptionalDigest::new(optional_digests.map(|(digest, _)| digest));
+        self.b2b.crc = optional_digests.map_or(0, |(_, crc)| crc);
+        self.seal();
+    }
+
+    /// CRC32 of the serialized b2b header, never zero so that it can't be mistaken for a bitmap without one
+    fn b2b_crc(&self) -> u32 {
+        let mut crc = Crc32::new();
+
+        crc.update(&self.b2b_header_serialized());
+
+        crc.finalize().max(1)
+    }
+
+    /// Stores the CRC of the b2b header, which must be done whenever it changes
+    fn seal(&mut self) {
+        self.bmp.header_crc = self.b2b_crc();
+    }
+
+    /// Whether the b2b header still matches the CRC stored when it was written, i.e. the stored digest and the other
+    /// fields have not been changed since. `None` for bitmaps written before the CRC was stored
+    pub fn header_crc_matches(&self) -> Option<bool> {
+        match self.bmp.header_crc {
+            0 => None,
+            stored => Some(stored == self.b2b_crc()),
+        }
+    }
+
+    /// Serializes the header as JSON, for tooling. The digest is written as a hex string, or null if there is none
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Reads a header from JSON produced by `to_json`
+    #[allow(dead_code)]
+    pub fn from_json(json: &str) -> Result<Self> {
+        Ok(serde_json::from_str(json)?)
+    }
+
     /// If this check passes, then this means that there is a high chance that:
     /// a) the bitmap header is correct
     /// b) the b2b header is correct
@@ -163,7 +808,7 @@ impl Header {
     /// Point b) implies that the bitmap was created by b2b.
     /// Of course there is a small chance that a V5 bitmap may contain the signature in that particular position
     pub fn check_signature(&self) -> Result<()> {
-        if self.b2b.signature != B2B_SIGNATURE {
+        if !is_b2b_signature(self.signature()) {
             Err(Error::new(ErrorKind::InvalidB2BSignature, ""))
         } else {
             Ok(())
@@ -186,40 +831,322 @@ impl Header {
         }
     }
 
-    ///Returns a (verified, error) pair
-    pub fn verify(&self, other_digest: u128) -> (bool, bool) {
-        match self.b2b.od.get() {
-            None => {
-                //If the bitmap was created without the -v command, no digest was added. So verifying the created bitmap is not possible
-                (false, true)
-            }
-            Some(_) => {
-                (self.b2b.od.compare(other_digest), false)
+    /// The bitmap must be small enough for its size to fit in the bitmap header, and the front of the original must be
+    /// stored after the bitmap header. Otherwise the offsets used to find the stored bytes would overflow
+    pub fn check_offsets(&self) -> Result<()> {
+        let bitmap_len = self.pixmap_size() as u64 + Self::bitmap_header_size() as u64;
+
+        if bitmap_len > u32::MAX as u64 {
+            return Err(Error::new(ErrorKind::OffsetOverflow, format!("a pixmap of {} bytes makes a bitmap larger than {} bytes", self.pixmap_size(), u32::MAX)));
+        }
+
+        match self.checked_front_offset() {
+            Some(offset) if offset >= Self::bitmap_header_size() as u64 => Ok(()),
+            _ => Err(Error::new(ErrorKind::OffsetOverflow, format!("{} bytes of padding and {} bytes of the original don't fit in a pixmap of {} bytes", self.padding_size(), self.header_size().min(self.original_file_size()), self.pixmap_size()))),
+        }
+    }
+
+    /// The header must be at least as large as the header we read, and must fit inside the bitmap. The pixels must
+    /// start either straight after the bitmap header or straight after the b2b header
+    pub fn check_header_size(&self) -> Result<()> {
+        let gap = self.pixel_gap();
+
+        if (self.header_size() as u64) < self.serialized_len() || self.header_size() as u64 > self.pixmap_size() as u64 + Self::bitmap_header_size() as u64
+            || self.bmp.offset < Self::bitmap_header_size() || (gap != 0 && gap != self.header_size() - Self::bitmap_header_size()) {
+            Err(Error::new(ErrorKind::BadHeaderSize, ""))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The pixels in the bitmap header must be exactly `height` rows of `width` pixels, with each row padded to a
+    /// multiple of four bytes. Rows of 32 bit pixels are always a multiple of four bytes long, so they are never padded
+    pub fn check_pixmap_alignment(&self) -> Result<()> {
+        let row_size = (self.width() as u64 * self.bytes_per_pixel() as u64).div_ceil(4) * 4;
+        let expected = row_size * self.height() as u64;
+
+        if self.bmp.pixmap_size as u64 != expected {
+            Err(Error::new(ErrorKind::BadPixmapSize, format!("{} bytes of pixels in a {}x{} bitmap, which should hold {}", self.bmp.pixmap_size, self.width(), self.height(), expected)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The b2b header, the original file and the padding must fill the pixmap exactly. Otherwise the stored size of
+    /// the original can't be trusted, and decoding would take the front of the file from the wrong place or cut the
+    /// file to the wrong length
+    pub fn check_original_file_size(&self) -> Result<()> {
+        let b2b_region = self.header_size() as u64 - Self::bitmap_header_size() as u64;
+        let expected = self.pixmap_size() as u64;
+        let actual = b2b_region + self.original_file_size() as u64 + self.padding_size() as u64;
+
+        if actual != expected {
+            Err(Error::new(ErrorKind::BadOriginalFileSize, format!("a file of {} bytes with {} bytes of padding does not fill a pixmap of {} bytes", self.original_file_size(), self.padding_size(), expected - b2b_region)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// A hash range must lie within the file it covers
+    pub fn check_hash_range(range: &Range<u64>, file_size: u64) -> Result<()> {
+        if range.start > range.end || range.end > file_size {
+            Err(Error::new(ErrorKind::BadHashRange, format!("{}:{} is outside a file of {} bytes", range.start, range.end, file_size)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Neither dimension of the bitmap may be larger than `max_dimension`, as some viewers reject such images
+    pub fn check_max_dimension(&self, max_dimension: u32) -> Result<()> {
+        if self.width() > max_dimension || self.height() > max_dimension {
+            Err(Error::new(ErrorKind::DimensionTooLarge, format!("the file needs a {}x{} bitmap, which is more than {} pixels in at least one direction. Split the file into smaller parts, or raise --max-dimension", self.width(), self.height(), max_dimension)))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Checks that the header agrees with the length of the file it was read from. The bitmap
+    /// header records the total file size, and a bitmap made by b2b is always exactly the
+    /// bitmap header followed by the pixmap
+    pub fn check_file_size(&self, file_len: u64) -> Result<()> {
+        let expected = self.pixmap_size() as u64 + Self::bitmap_header_size() as u64;
+
+        if self.bmp.file_size as u64 == expected && file_len < expected && self.recoverable_truncation(file_len).is_some() {
+            Err(Error::new(ErrorKind::BadFileSize, "bitmap has been truncated, use --recover-truncated to recover what remains"))
+        } else if self.bmp.file_size as u64 != expected || file_len != expected {
+            Err(Error::new(ErrorKind::BadFileSize, ""))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Runs every header check in turn, returning the first failure. Use this before modifying a
+    /// bitmap, so that a file that was not created by b2b (or has been corrupted) is left untouched
+    pub fn validate_all(&self, file_len: u64) -> Result<()> {
+        self.check_id()?;
+
+        self.check_signature()?;
+
+        self.check_padding_size()?;
+
+        self.check_offsets()?;
+
+        self.check_header_size()?;
+
+        self.check_pixmap_alignment()?;
+
+        self.check_original_file_size()?;
+
+        Self::check_hash_range(&self.hash_range(), self.original_file_size() as u64)?;
+
+        self.check_file_size(file_len)?;
+
+        Ok(())
+    }
+
+    /// Checks `other_digest`, the digest of the decoded file, against the stored one. If they don't match, the CRC
+    /// of the header tells whether the file or the header was changed
+    pub fn verify(&self, other_digest: u128) -> VerifyOutcome {
+        match self.digest() {
+            //If the bitmap was created without the -v command, no digest was added. So verifying the created bitmap is not possible
+            None => VerifyOutcome::NoDigest,
+            Some(_) => self.outcome(self.b2b.od.compare(other_digest)),
+        }
+    }
+
+    /// Like `verify`, using the stored CRC32 rather than the digest
+    pub fn verify_crc(&self, other_crc: u32) -> VerifyOutcome {
+        match self.crc() {
+            //The CRC is only stored alongside a digest
+            None => VerifyOutcome::NoDigest,
+            Some(crc) => self.outcome(crc == other_crc),
+        }
+    }
+
+    fn outcome(&self, matches: bool) -> VerifyOutcome {
+        match self.header_crc_matches() {
+            Some(false) => VerifyOutcome::HeaderTampered,
+            _ if matches => VerifyOutcome::Verified,
+            Some(true) => VerifyOutcome::PayloadCorrupt,
+            None => VerifyOutcome::Mismatch,
+        }
+    }
+
+    /// Offset in the bitmap file of the first `header_size` bytes of the original, which are stored after the rest of it.
+    /// Files smaller than the header region have no rest, so only `original_file_size` bytes are stored there
+    /// Only meaningful for headers that pass `check_offsets`
+    pub fn front_offset(&self) -> u64 {
+        self.checked_front_offset().unwrap_or(0)
+    }
+
+    /// `front_offset`, or `None` if the fields of the header would put it before the start of the file
+    fn checked_front_offset(&self) -> Option<u64> {
+        let front_len = self.header_size().min(self.original_file_size());
+
+        (self.pixmap_size() as u64 + Self::bitmap_header_size() as u64)
+            .checked_sub(front_len as u64)?
+            .checked_sub(self.padding_size() as u64)
+    }
+
+    /// If a bitmap of `file_len` bytes is this header's bitmap with its end cut off, but with the rest of the original
+    /// (everything after the first `header_size` bytes) still intact, returns the range of the original that was lost.
+    /// The range is empty if only padding was lost. Returns `None` if the bitmap is not truncated, or is truncated too
+    /// far to recover, or uses a layout other than `Layout::Row` (which spreads the stored bytes across the bitmap)
+    pub fn recoverable_truncation(&self, file_len: u64) -> Option<Range<u64>> {
+        let expected = self.pixmap_size() as u64 + Self::bitmap_header_size() as u64;
+        let front_offset = self.front_offset();
+        let front_len = (self.header_size() as u64).min(self.original_file_size() as u64);
+
+        if file_len >= expected || file_len < front_offset || self.layout() != Layout::Row {
+            None
+        } else {
+            Some((file_len - front_offset).min(front_len)..front_len)
+        }
+    }
+
+    /// Translates `len` bytes of the original file starting at `start` into the parts of the bitmap file that hold
+    /// them, as `(offset, length)` pairs in original file order. The first `header_size` bytes of the original are
+    /// stored at the end of the payload, so a range crossing that boundary comes back in two parts. Layouts other than
+    /// `Layout::Row` scatter the data by pixel, giving a part for each run of pixels that stayed next to each other.
+    /// The range is clipped to the original file
+    pub fn map_range(&self, start: u64, len: u64) -> Vec<(u64, u64)> {
+        let parts = self.map_range_in_file_order(start, len);
+
+        let header_size = self.header_size() as u64;
+        let pixel = self.bytes_per_pixel() as u64;
+        let region_len = (self.pixmap_size() + Self::bitmap_header_size()) as u64 - header_size;
+
+        let order = match self.layout().order(self.width() as usize, (self.header_bytes_in_pixels() as u64 / pixel) as usize, (region_len / pixel) as usize, self.layout_parameter()) {
+            Some(order) => order,
+            None => return parts,
+        };
+
+        let mut mapped: Vec<(u64, u64)> = Vec::new();
+
+        for (offset, length) in parts {
+            let mut position = offset;
+
+            //A pixel at a time, or what is left of one, as the layouts only move whole pixels
+            while position < offset + length {
+                let in_region = position - header_size;
+                let within = in_region % pixel;
+                let take = (pixel - within).min(offset + length - position);
+
+                //Bytes after the last whole pixel stay where they are
+                let stored = order.get((in_region / pixel) as usize).map_or(position, |&to| header_size + to as u64 * pixel + within);
+
+                match mapped.last_mut() {
+                    Some((start, len)) if *start + *len == stored => *len += take,
+                    _ => mapped.push((stored, take)),
+                }
+
+                position += take;
             }
         }
+
+        mapped
+    }
+
+    /// The parts of the bitmap file that hold `len` bytes of the original file starting at `start` (see `map_range`)
+    /// before any layout other than `Layout::Row` is applied
+    fn map_range_in_file_order(&self, start: u64, len: u64) -> Vec<(u64, u64)> {
+        let original_file_size = self.original_file_size() as u64;
+        let header_size = self.header_size() as u64;
+
+        let start = start.min(original_file_size);
+        let end = start.saturating_add(len).min(original_file_size);
+
+        let front_offset = self.front_offset();
+
+        let mut parts = Vec::with_capacity(2);
+
+        if start < header_size.min(end) {
+            parts.push((front_offset + start, header_size.min(end) - start));
+        }
+
+        if end > header_size.max(start) {
+            parts.push((header_size.max(start), end - header_size.max(start)));
+        }
+
+        parts
     }
+
     /// Given the size of the file, calculate a suitable width and height for a pixmap (large enough to contain the file data but not so large as to
-    /// have too much padding). Then calculate the padding required.
-    fn get_properties(file_size: u64) -> (u32, u32, u32, u32) {
+    /// have too much padding), with neither smaller than `min_dimension`. Then calculate the padding required. The pixmap
+    /// holds the `b2b_region` bytes following the bitmap header too, which are only drawn as pixels if `honor_offset` is not set.
+    /// If the width would be larger than `max_dimension`, the pixmap is made as wide as allowed and as tall as it needs to be
+    /// instead, which may still be taller than `max_dimension` (see `check_max_dimension`)
+    fn get_properties(file_size: u64, min_dimension: u32, max_dimension: Option<u32>, honor_offset: bool, b2b_region: u32) -> Result<(u32, u32, u32, u32)> {
+        let bytes_per_pixel = BYTES_PER_PIXEL as u64;
 
-        let total_data_size = file_size as f32 + Self::b2b_header_size() as f32;
+        let total_data_size = if honor_offset {
+            file_size
+        } else {
+            file_size + b2b_region as u64
+        };
+
+        //Smallest width whose square holds every pixel, in integers so large files aren't rounded down
+        let pixels = total_data_size.div_ceil(bytes_per_pixel);
+
+        let root = pixels.isqrt();
+
+        let mut width = (if root * root < pixels { root + 1 } else { root }).max(min_dimension as u64);
 
-        let width = (total_data_size / Self::bytes_per_pixel() as f32).sqrt().ceil() as u32;
+        if let Some(max_dimension) = max_dimension {
+            width = width.min(max_dimension.max(min_dimension) as u64);
+        }
+
+        let height = total_data_size.div_ceil(width * bytes_per_pixel).max(min_dimension as u64);
+
+        let too_large = || Error::new(ErrorKind::FileTooLarge, format!("a file of {} bytes needs a {}x{} bitmap", file_size, width, height));
 
-        let height = (total_data_size / (width as f32 * Self::bytes_per_pixel() as f32)).ceil() as u32;
+        let pixmap_size = width * height * bytes_per_pixel + if honor_offset { b2b_region as u64 } else { 0 };
 
-        let pixmap_size = width * height * Self::bytes_per_pixel();
+        let pixmap_size = u32::try_from(pixmap_size).ok()
+            .filter(|pixmap_size| *pixmap_size <= u32::MAX - Self::bitmap_header_size())
+            .ok_or_else(too_large)?;
 
-        let padding_size = pixmap_size - file_size as u32 - Self::b2b_header_size();
+        let padding_size = (pixmap_size as u64).checked_sub(file_size + b2b_region as u64)
+            .and_then(|padding_size| u32::try_from(padding_size).ok())
+            .ok_or_else(too_large)?;
 
-        (width, height, pixmap_size, padding_size)
+        //Both fit in a u32, as the pixmap does
+        Ok((width as u32, height as u32, pixmap_size, padding_size))
     }
 
-    pub const fn total_header_size() -> u32 { Self::bitmap_header_size() + Self::b2b_header_size() }
+    /// Size of the bitmap `Header::new` would create for a file of `file_size` bytes, headers included
+    pub fn predicted_size(file_size: u64, min_dimension: u32, max_dimension: Option<u32>, honor_offset: bool, canonical: bool) -> Result<u64> {
+        Ok(Self::bitmap_header_size() as u64 + Self::new(file_size, None, min_dimension, max_dimension, honor_offset, canonical)?.pixmap_size() as u64)
+    }
 
     pub const fn bitmap_header_size() -> u32 { BITMAP_HEADER_SIZE }
 
     pub const fn b2b_header_size() -> u32 { B2B_HEADER_SIZE }
+}
 
-    pub const fn bytes_per_pixel() -> u32 { 4 }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pixmap_holds_files_around_the_float_precision_boundary() {
+        //Past 2^24 bytes not every size is an f32, so sizes here used to be rounded down to a pixmap too small to hold them
+        for file_size in (1 << 24) - 300..(1 << 24) + 300 {
+            for (honor_offset, canonical) in [(false, false), (true, false), (false, true)] {
+                let header = Header::new(file_size, None, 0, None, honor_offset, canonical).unwrap();
+
+                let b2b_region = header.header_size() - Header::bitmap_header_size();
+
+                assert_eq!(header.pixmap_size() as u64, file_size + b2b_region as u64 + header.padding_size() as u64, "{} bytes", file_size);
+                assert!(header.padding_size() < header.width() * BYTES_PER_PIXEL, "{} bytes", file_size);
+            }
+        }
+    }
+
+    #[test]
+    fn files_too_large_for_a_bitmap_are_rejected() {
+        let result = Header::new(u32::MAX as u64, None, 0, None, false, false);
+
+        assert!(matches!(result, Err(e) if e.to_string().starts_with(&ErrorKind::FileTooLarge.to_string())));
+    }
 }
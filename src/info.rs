@@ -0,0 +1,256 @@
+use crate::header::{self, Header, HEADER_FIELDS, LEGACY_HEADER_FIELDS, B2B_SIGNATURE, LEGACY_B2B_SIGNATURE};
+use crate::layout::{Layout, LAYOUT_NAMES};
+use crate::options::CONTAINER_NAMES;
+use crate::error::Result;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Largest size of the ascii preview, in characters
+const PREVIEW_MAX_COLUMNS: u32 = 48;
+const PREVIEW_MAX_ROWS: u32 = 16;
+
+/// Prints the fields of a b2b header in a human readable form
+pub fn print_info(header: &Header) {
+    println!("Dimensions:         {} x {}", header.width(), header.height());
+    println!("Pixmap size:        {} bytes", header.pixmap_size());
+    println!("Original file size: {} bytes", header.original_file_size());
+    println!("Padding size:       {} bytes", header.padding_size());
+    println!("Header size:        {} bytes{}", header.header_size(), if header.canonical() { " (canonical)" } else { "" });
+    println!("Digest:             {}", header.digest_display());
+
+    match header.crc() {
+        Some(crc) => println!("CRC32:              {:08x}", crc),
+        None => println!("CRC32:              none"),
+    }
+
+    match header.bitmap_digest() {
+        Some(digest) => println!("Bitmap digest:      {:032x}", digest),
+        None => println!("Bitmap digest:      none"),
+    }
+
+    let range = header.hash_range();
+
+    println!("Hash range:         {}:{}", range.start, range.end);
+
+    match header.layout() {
+        Layout::Shuffled => println!("Layout:             Shuffled (seed {})", header.shuffle_seed()),
+        Layout::Tiled => println!("Layout:             Tiled ({0}x{0} tiles)", header.tile_size()),
+        layout => println!("Layout:             {:?}", layout),
+    }
+
+    println!("Signature:          {:032x}", header.signature());
+}
+
+/// Prints the formats this build reads and writes, and the optional features it was built with. The b2b signature is
+/// the version of the header format, so a build decodes the bitmaps whose header has one of the signatures listed
+pub fn print_formats() {
+    let yes_no = |enabled| if enabled { "yes" } else { "no" };
+
+    println!("Header:         b2b signature {:032x}, {} bytes", B2B_SIGNATURE, Header::bitmap_header_size() + Header::b2b_header_size());
+    println!("Decodes:        b2b signature {:032x}, {} bytes (first format)", LEGACY_B2B_SIGNATURE, Header::bitmap_header_size() + header::LEGACY_B2B_HEADER_SIZE);
+    println!("Containers:     {}", CONTAINER_NAMES.join(", "));
+    println!("Layouts:        {}", LAYOUT_NAMES.join(", "));
+    println!();
+    println!("profile-memory: {}", yes_no(cfg!(feature = "profile-memory")));
+    println!("preallocate:    {}", yes_no(cfg!(target_os = "linux")));
+}
+
+/// Prints one line per bitmap with its path and the main fields of its header, in aligned columns
+pub fn print_header_table(headers: &[(PathBuf, Header)]) {
+    let width = headers.iter()
+        .map(|(path, _)| path.display().to_string().len())
+        .chain(std::iter::once("Path".len()))
+        .max()
+        .unwrap();
+
+    println!("{:<width$}  {:>11}  {:>13}  {:>8}  Digest", "Path", "Dimensions", "Original size", "Layout", width = width);
+
+    for (path, header) in headers {
+        let dimensions = format!("{}x{}", header.width(), header.height());
+        let layout = format!("{:?}", header.layout());
+
+        println!("{:<width$}  {:>11}  {:>13}  {:>8}  {}", path.display(), dimensions, header.original_file_size(), layout, header.digest_display(), width = width);
+    }
+}
+
+/// A bitmap found by a recursive `--info`, as it appears in the JSON output
+#[derive(Serialize)]
+struct HeaderEntry<'a> {
+    path: String,
+    header: &'a Header,
+}
+
+/// Formats the headers as a JSON array of objects, each holding the path of the bitmap and its header in the same
+/// form as `Header::to_json`
+pub fn headers_to_json(headers: &[(PathBuf, Header)]) -> Result<String> {
+    let entries: Vec<_> = headers.iter()
+        .map(|(path, header)| HeaderEntry { path: path.display().to_string(), header })
+        .collect();
+
+    Ok(serde_json::to_string_pretty(&entries)?)
+}
+
+/// Formats `bytes` as a hexdump, 16 bytes to a line, with each line starting with its offset in the original file
+/// (counting from `start`) and ending with the printable ascii characters
+pub fn hexdump(bytes: &[u8], start: u64) -> String {
+    let mut dump = String::new();
+
+    for (line, chunk) in bytes.chunks(16).enumerate() {
+        dump.push_str(&format!("{:08x}  ", start + line as u64 * 16));
+
+        for column in 0..16 {
+            match chunk.get(column) {
+                Some(byte) => dump.push_str(&format!("{:02x} ", byte)),
+                None => dump.push_str("   "),
+            }
+        }
+
+        dump.push(' ');
+
+        dump.extend(chunk.iter().map(|&byte| if byte.is_ascii_graphic() || byte == b' ' { byte as char } else { '.' }));
+
+        dump.push('\n');
+    }
+
+    dump
+}
+
+/// Formats the serialized headers at the start of a bitmap one field to a line, with the offset of each field, its name
+/// and its bytes in hex. Fields that `bytes` is too short to hold are cut short or left out, as are the fields that
+/// headers of the first format lack. Any bytes of the header region past the fields, such as the zeros filling the
+/// block of a canonical bitmap, follow as a hexdump, with lines repeating the one before shown as a single `*`
+pub fn hexdump_header(bytes: &[u8]) -> String {
+    let mut dump = String::new();
+
+    let mut offset = 0usize;
+
+    let fields = if header::b2b_signature(bytes) == Some(LEGACY_B2B_SIGNATURE) { LEGACY_HEADER_FIELDS } else { HEADER_FIELDS.len() };
+
+    for (name, size) in HEADER_FIELDS.iter().take(fields) {
+        if offset >= bytes.len() {
+            break;
+        }
+
+        let field = &bytes[offset..(offset + *size as usize).min(bytes.len())];
+
+        dump.push_str(&format!("{:08x}  {:<24}", offset, name));
+
+        dump.extend(field.iter().map(|byte| format!(" {:02x}", byte)));
+
+        dump.push('\n');
+
+        offset += *size as usize;
+    }
+
+    if offset < bytes.len() {
+        let mut previous = None;
+
+        for (chunk, line) in bytes[offset..].chunks(16).zip(hexdump(&bytes[offset..], offset as u64).lines()) {
+            if previous == Some(chunk) && chunk.len() == 16 {
+                if !dump.ends_with("*\n") {
+                    dump.push_str("*\n");
+                }
+            } else {
+                dump.push_str(line);
+                dump.push('\n');
+            }
+
+            previous = Some(chunk);
+        }
+    }
+
+    dump
+}
+
+/// Draws a box with the same aspect ratio as the bitmap, where `#` marks the cells holding the b2b header
+/// and file data and `.` marks the padding. Characters are roughly twice as tall as they are wide, so
+/// each pixel is drawn two columns wide. Bitmap rows are stored bottom up, so the data fills the box
+/// from the bottom row upwards, as it would appear in an image viewer
+pub fn ascii_preview(header: &Header) -> String {
+    let width = header.width().max(1) as f64 * 2.0;
+    let height = header.height().max(1) as f64;
+
+    let scale = (PREVIEW_MAX_COLUMNS as f64 / width).min(PREVIEW_MAX_ROWS as f64 / height);
+
+    let columns = ((width * scale).round() as usize).max(1);
+    let rows = ((height * scale).round() as usize).max(1);
+
+    let cells = columns * rows;
+
+    let padding_fraction = header.padding_size() as f64 / header.pixmap_size().max(1) as f64;
+
+    let data_cells = cells - (cells as f64 * padding_fraction).round() as usize;
+
+    let border = format!("+{}+\n", "-".repeat(columns));
+
+    let mut preview = border.clone();
+
+    for row in (0..rows).rev() {
+        preview.push('|');
+
+        for column in 0..columns {
+            preview.push(if row * columns + column < data_cells { '#' } else { '.' });
+        }
+
+        preview.push_str("|\n");
+    }
+
+    preview.push_str(&border);
+
+    preview.push_str(&format!("# data  . padding ({:.1}% padding)\n", padding_fraction * 100.0));
+
+    preview
+}
+
+/// Number of times each byte value occurs in the payload of a bitmap, as printed by `--inspect-pixels`
+pub struct ByteHistogram {
+    counts: [u64; 256],
+    total: u64,
+}
+
+impl ByteHistogram {
+    pub fn new() -> Self {
+        Self { counts: [0; 256], total: 0 }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.counts[byte as usize] += 1;
+        }
+
+        self.total += bytes.len() as u64;
+    }
+
+    /// Shannon entropy of the bytes counted, in bits per byte. Close to 8 for compressed or encrypted data, and much
+    /// lower for text or structured files. Zero if no bytes were counted
+    pub fn entropy(&self) -> f64 {
+        let total = self.total as f64;
+
+        self.counts.iter()
+            .filter(|&&count| count > 0)
+            .map(|&count| {
+                let p = count as f64 / total;
+                -p * p.log2()
+            })
+            .sum()
+    }
+}
+
+/// The size and entropy of the payload, then the count of each byte value, 16 values to a line
+impl std::fmt::Display for ByteHistogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Payload size: {} bytes", self.total)?;
+        writeln!(f, "Entropy:      {:.4} bits per byte", self.entropy())?;
+        write!(f, "Byte histogram:")?;
+
+        for (line, counts) in self.counts.chunks(16).enumerate() {
+            write!(f, "\n{:02x}:", line * 16)?;
+
+            for count in counts {
+                write!(f, " {}", count)?;
+            }
+        }
+
+        Ok(())
+    }
+}
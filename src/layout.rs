@@ -0,0 +1,150 @@
+use serde::{Serialize, Deserialize};
+
+use crate::random::SeededRng;
+use crate::error::{Result, Error, ErrorKind};
+
+/// Number of interleaved streams used by `Layout::Interleaved`
+pub const INTERLEAVE_STRIDE: usize = 8;
+
+/// Default side of the square tiles of `Layout::Tiled`, in pixels
+pub const DEFAULT_TILE_SIZE: u32 = 16;
+
+/// Controls how the data following the b2b header is arranged across the pixels of the bitmap.
+/// Every layout is a permutation of whole pixels, so it can be reversed exactly on decode
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Layout {
+    /// Data fills the bitmap row by row, exactly as it appears in the file
+    Row,
+    /// Data fills the bitmap column by column
+    Column,
+    /// Consecutive pixels are spread `INTERLEAVE_STRIDE` pixels apart, filling the bitmap in `INTERLEAVE_STRIDE` passes
+    Interleaved,
+    /// Pixels are placed in a pseudo-random order generated from a seed stored in the header, so that even very
+    /// structured files look like noise. This is only a visual scramble, not encryption: anyone can read the seed
+    Shuffled,
+    /// Data fills square tiles one after the other, each row by row, with the tiles themselves in rows. Runs of
+    /// similar bytes then clump into blocks rather than thin lines. The tile size is stored in the header
+    Tiled,
+}
+
+/// Names of the layouts on the command line, in the order of `Layout`
+pub const LAYOUT_NAMES: [&str; 5] = ["row", "column", "interleaved", "shuffled", "tiled"];
+
+impl std::str::FromStr for Layout {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "row" => Ok(Layout::Row),
+            "column" => Ok(Layout::Column),
+            "interleaved" => Ok(Layout::Interleaved),
+            "shuffled" => Ok(Layout::Shuffled),
+            "tiled" => Ok(Layout::Tiled),
+            _ => Err(Error::new(ErrorKind::InvalidOption, format!("unknown layout '{}', expected one of {}", name, LAYOUT_NAMES.join(", ")))),
+        }
+    }
+}
+
+impl Layout {
+    /// For each pixel of data (in file order), the index of the pixel it is stored in. `width` is the width of the
+    /// bitmap in pixels, `skipped` is the number of pixels taken up by the b2b header at the start of the pixmap and
+    /// `count` is the number of pixels of data following it. `parameter` is the seed of `Layout::Shuffled` or the tile
+    /// size of `Layout::Tiled`, and is unused by the other layouts. Returns `None` for `Layout::Row`, which stores data
+    /// in order
+    pub fn order(self, width: usize, skipped: usize, count: usize, parameter: u64) -> Option<Vec<usize>> {
+        match self {
+            Layout::Row => None,
+            Layout::Column => {
+                let total = skipped + count;
+                let height = total.div_ceil(width);
+
+                let mut order = Vec::with_capacity(count);
+
+                for column in 0..width {
+                    for row in 0..height {
+                        let position = row * width + column;
+
+                        if position >= skipped && position < total {
+                            order.push(position - skipped);
+                        }
+                    }
+                }
+
+                Some(order)
+            }
+            Layout::Interleaved => {
+                let mut order = Vec::with_capacity(count);
+
+                for pass in 0..INTERLEAVE_STRIDE.min(count) {
+                    order.extend((pass..count).step_by(INTERLEAVE_STRIDE));
+                }
+
+                Some(order)
+            }
+            Layout::Shuffled => {
+                let mut order: Vec<usize> = (0..count).collect();
+                let mut random = SeededRng::new(parameter);
+
+                //Fisher-Yates shuffle
+                for i in (1..count).rev() {
+                    order.swap(i, random.below(i as u64 + 1) as usize);
+                }
+
+                Some(order)
+            }
+            Layout::Tiled => {
+                let total = skipped + count;
+                let height = total.div_ceil(width);
+
+                //A damaged header can hold any tile size, so it is kept to one that works for any bitmap. Encoding
+                //and decoding clamp it the same way, so the layout still reverses
+                let tile = (parameter.min(width.max(height) as u64) as usize).max(1);
+
+                let mut order = Vec::with_capacity(count);
+
+                for tile_top in (0..height).step_by(tile) {
+                    for tile_left in (0..width).step_by(tile) {
+                        for row in tile_top..(tile_top + tile).min(height) {
+                            for column in tile_left..(tile_left + tile).min(width) {
+                                let position = row * width + column;
+
+                                if position >= skipped && position < total {
+                                    order.push(position - skipped);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                Some(order)
+            }
+        }
+    }
+
+    /// Rearranges `region`, the data following the b2b header, from file order into this layout. Any bytes
+    /// after the last whole pixel are left in place
+    pub fn arrange(self, width: u32, bytes_per_pixel: u32, header_bytes: u32, parameter: u64, region: &mut [u8]) {
+        let pixel = bytes_per_pixel as usize;
+
+        if let Some(order) = self.order(width as usize, header_bytes as usize / pixel, region.len() / pixel, parameter) {
+            let source = region.to_vec();
+
+            for (from, to) in order.into_iter().enumerate() {
+                region[to * pixel..(to + 1) * pixel].copy_from_slice(&source[from * pixel..(from + 1) * pixel]);
+            }
+        }
+    }
+
+    /// Reverses `arrange`, returning `region` to file order
+    pub fn restore(self, width: u32, bytes_per_pixel: u32, header_bytes: u32, parameter: u64, region: &mut [u8]) {
+        let pixel = bytes_per_pixel as usize;
+
+        if let Some(order) = self.order(width as usize, header_bytes as usize / pixel, region.len() / pixel, parameter) {
+            let source = region.to_vec();
+
+            for (to, from) in order.into_iter().enumerate() {
+                region[to * pixel..(to + 1) * pixel].copy_from_slice(&source[from * pixel..(from + 1) * pixel]);
+            }
+        }
+    }
+}
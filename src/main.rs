@@ -2,52 +2,340 @@
 
 mod header;
 mod error;
+mod crc;
+mod info;
+mod layout;
+mod report;
+mod options;
+mod watch;
+mod batch;
+mod png;
+mod config;
+mod payload;
+mod glob;
+mod random;
+mod stream;
+mod region;
+mod benchmark;
+mod thumbnail;
+mod memory;
+mod manifest;
 
-use header::{Header};
+use header::{Header, VerifyOutcome, DIGEST_STORE_BYTES, BITMAP_ID, DEFAULT_MIN_DIMENSION};
+use crc::Crc32;
+use layout::Layout;
+use report::{ConversionReport, BatchStats, EncodedCounts, ManifestCounts, FileStatus, Direction};
+use manifest::Manifest;
+use config::{Config, DEFAULT_CONFIG_FILE};
+use payload::PayloadReader;
+use options::{EncodeOptions, VerifyOptions, Container, DEFAULT_CHUNK_SIZE, WARN_EXPANSION, MAX_EXPANSION, MAX_EXPANSION_MIN_SIZE};
 use std::path::{Path, PathBuf};
+use std::ffi::{OsStr, OsString};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Instant, Duration, SystemTime};
+use std::ops::Range;
 
-use error::Result;
-use std::fs::OpenOptions;
-use std::io::{Seek, Read, SeekFrom, Write};
+use error::{Result, Error, ErrorKind, EXIT_IO, EXIT_USAGE};
+use std::fs::{File, OpenOptions};
+use std::io::{Seek, Read, SeekFrom, Write, Cursor, BufReader, BufWriter};
 
-use clap::{Arg, App, crate_authors, crate_version, crate_description};
+use clap::{Arg, App, AppSettings, crate_authors, crate_version, crate_description};
 
 use blake_hash::{Blake256, Digest};
+use blake_hash::digest::generic_array::typenum::Unsigned;
 
-use std::convert::TryInto;
+/// Returns `ErrorKind::Cancelled` if `cancel` has been set
+fn check_cancelled(cancel: &AtomicBool) -> Result<()> {
+    if cancel.load(Ordering::Relaxed) {
+        Err(Error::new(ErrorKind::Cancelled, ""))
+    } else {
+        Ok(())
+    }
+}
+
+/// Set when a report is written to stdout, so the progress printed while converting goes to stderr instead of being
+/// mixed into it
+static PROGRESS_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Prints a line of progress, such as a file being converted or verified, to stdout unless `PROGRESS_TO_STDERR` is set
+macro_rules! progress {
+    ($($arg:tt)*) => {
+        if PROGRESS_TO_STDERR.load(Ordering::Relaxed) {
+            eprintln!($($arg)*)
+        } else {
+            println!($($arg)*)
+        }
+    };
+}
+
+/// Number of times an operation interrupted by a signal is retried before giving up
+const MAX_INTERRUPTED_RETRIES: usize = 8;
+
+/// Runs `operation`, retrying it if it is interrupted by a signal. `write_all` and `read_exact` already retry, but
+/// calls such as `set_len` and `rename` do not. Any other error is returned straight away
+fn retry_interrupted<T, F: FnMut() -> std::io::Result<T>>(mut operation: F) -> std::io::Result<T> {
+    let mut attempts = 0;
+
+    loop {
+        match operation() {
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted && attempts < MAX_INTERRUPTED_RETRIES => attempts += 1,
+            result => return result,
+        }
+    }
+}
+
+/// Wait before the first retry of a conversion that failed with a transient error, doubled for each further retry
+const RETRY_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Runs `operation` on `path`, trying it again up to `retries` more times if it fails with a transient error (see
+/// `Error::is_transient`). Other errors, such as a corrupt header, are returned straight away
+fn retry_transient<T, F: FnMut() -> Result<T>>(path: &Path, retries: u32, mut operation: F) -> Result<T> {
+    let mut backoff = RETRY_BACKOFF;
+
+    for _ in 0..retries {
+        match operation() {
+            Err(e) if e.is_transient() => {
+                eprintln!("Failed to convert {}: {}, retrying in {} ms", path.display(), e, backoff.as_millis());
+
+                std::thread::sleep(backoff);
+
+                backoff *= 2;
+            }
+            result => return result,
+        }
+    }
+
+    operation()
+}
+
+/// Moves the file at `from` to `to`, like `rename`, which can't move files between filesystems. In that case the file
+/// is copied next to `to` first and renamed over it, so `to` is still only replaced once it is complete, and `from`
+/// is only removed after that
+fn move_file(from: &Path, to: &Path) -> Result<()> {
+    match retry_interrupted(|| std::fs::rename(from, to)) {
+        Err(e) if e.kind() == std::io::ErrorKind::CrossesDevices => move_across_devices(from, to),
+        result => Ok(result?),
+    }
+}
+
+/// The fallback of `move_file` for a `to` on a different filesystem to `from`
+fn move_across_devices(from: &Path, to: &Path) -> Result<()> {
+    let temporary = bitmap_path(to, OsStr::new("tmp"));
+
+    let result = std::fs::copy(from, &temporary)
+        .and_then(|_| OpenOptions::new().write(true).open(&temporary)?.sync_all())
+        .and_then(|_| retry_interrupted(|| std::fs::rename(&temporary, to)));
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+
+    result?;
+
+    Ok(std::fs::remove_file(from)?)
+}
 
-fn get_file_hash<P: AsRef<Path>>(path: P) -> Result<u128> {
+/// Reads the file at `path` in chunks of up to `chunk_size` bytes, passing each chunk to `process`. `cancel` is
+/// checked before each chunk is read
+fn read_chunks<P: AsRef<Path>, F: FnMut(&[u8]) -> Result<()>>(path: P, chunk_size: usize, cancel: &AtomicBool, process: F) -> Result<()> {
+    read_range_chunks(path, 0..u64::MAX, chunk_size, cancel, process)
+}
+
+/// Like `read_chunks`, but only reads the bytes of the file within `range`
+fn read_range_chunks<P: AsRef<Path>, F: FnMut(&[u8]) -> Result<()>>(path: P, range: Range<u64>, chunk_size: usize, cancel: &AtomicBool, mut process: F) -> Result<()> {
     let mut file = OpenOptions::new()
         .read(true)
         .open(path.as_ref())?;
 
-    let mut hash = Blake256::new();
+    file.seek(SeekFrom::Start(range.start))?;
 
-    let mut buff = [0u8; 1024];
+    let mut remaining = range.end.saturating_sub(range.start);
 
-    loop {
-        let opn = file.read(& mut buff)?;
+    let mut buff = vec![0u8; chunk_size];
+
+    while remaining > 0 {
+        check_cancelled(cancel)?;
+
+        let opn = file.read(& mut buff[..remaining.min(chunk_size as u64) as usize])?;
         if opn == 0 {
             break
         }
-        hash.update(&buff[..opn]);
+        process(&buff[..opn])?;
+
+        remaining -= opn as u64;
+    }
+
+    Ok(())
+}
+
+/// Wraps `process` so that it is only given the bytes of a stream that fall within `range`, where the
+/// first byte passed to the returned closure is at offset 0
+fn restrict_to_range<F: FnMut(&[u8]) -> Result<()>>(range: Range<u64>, mut process: F) -> impl FnMut(&[u8]) -> Result<()> {
+    let mut offset = 0u64;
 
+    move |chunk: &[u8]| {
+        let chunk_start = offset;
+        offset += chunk.len() as u64;
+
+        let start = range.start.clamp(chunk_start, offset) - chunk_start;
+        let end = range.end.clamp(chunk_start, offset) - chunk_start;
+
+        if start < end {
+            process(&chunk[start as usize..end as usize])
+        } else {
+            Ok(())
+        }
     }
+}
+
+const _: () = assert!(<<Blake256 as Digest>::OutputSize as Unsigned>::USIZE >= DIGEST_STORE_BYTES, "Blake256 output is too short to store");
 
+/// Truncates the Blake256 digest to the `DIGEST_STORE_BYTES` kept in the header
+fn finalize_hash(hash: Blake256) -> u128 {
     let fin = hash.finalize();
 
-    Ok(u128::from_be_bytes((&(fin.as_slice())[..16]).try_into().unwrap()))
+    let mut stored = [0u8; DIGEST_STORE_BYTES];
+
+    stored.copy_from_slice(&fin.as_slice()[..DIGEST_STORE_BYTES]);
+
+    u128::from_be_bytes(stored)
+}
+
+fn get_file_hash<P: AsRef<Path>>(path: P, range: Range<u64>, cancel: &AtomicBool) -> Result<u128> {
+    let mut hash = Blake256::new();
+
+    read_range_chunks(path, range, DEFAULT_CHUNK_SIZE, cancel, |chunk| {
+        hash.update(chunk);
+        Ok(())
+    })?;
+
+    Ok(finalize_hash(hash))
+}
+
+fn get_file_crc<P: AsRef<Path>>(path: P, range: Range<u64>, cancel: &AtomicBool) -> Result<u32> {
+    let mut crc = Crc32::new();
+
+    read_range_chunks(path, range, DEFAULT_CHUNK_SIZE, cancel, |chunk| {
+        crc.update(chunk);
+        Ok(())
+    })?;
+
+    Ok(crc.finalize())
+}
+
+/// Computes both the Blake256 digest and the CRC32 of the bytes of a file within `range`, in a single pass
+fn get_file_digests<P: AsRef<Path>>(path: P, range: Range<u64>, cancel: &AtomicBool) -> Result<(u128, u32)> {
+    let mut hash = Blake256::new();
+    let mut crc = Crc32::new();
+
+    read_range_chunks(path, range, DEFAULT_CHUNK_SIZE, cancel, |chunk| {
+        hash.update(chunk);
+        crc.update(chunk);
+        Ok(())
+    })?;
+
+    Ok((finalize_hash(hash), crc.finalize()))
+}
+
+/// Returns `ErrorKind::FileChanged` if the file at `path` is no longer `expected_len` bytes long, or has been
+/// modified since `modified` (if the platform reports modification times)
+fn check_unchanged<P: AsRef<Path>>(path: P, expected_len: u64, modified: Option<SystemTime>) -> Result<()> {
+    let metadata = std::fs::metadata(path.as_ref())?;
+
+    if metadata.len() != expected_len || metadata.modified().ok() != modified {
+        Err(Error::new(ErrorKind::FileChanged, ""))
+    } else {
+        Ok(())
+    }
+}
+
+/// Creates the header for the file at `path` according to `options`, computing its digests if requested. Returns the
+/// header along with the time spent hashing
+fn prepare_header<P: AsRef<Path>>(path: P, options: &EncodeOptions, cancel: &AtomicBool) -> Result<(Header, Duration)> {
+    let file_size = std::fs::metadata(path.as_ref())?.len();
+
+    build_header(file_size, options, |range| get_file_digests(&path, range, cancel))
 }
 
-fn bin_to_bmp<P: AsRef<Path>>(path: P, rename: bool, create_digest: bool) -> Result<()> {
+/// Creates the header for a file of `file_size` bytes as described by `options`. `digests` computes the digest and
+/// CRC of a range of the file, and is only called if `options` asks for a digest. Also returns the time spent in `digests`
+fn build_header<F: FnOnce(Range<u64>) -> Result<(u128, u32)>>(file_size: u64, options: &EncodeOptions, digests: F) -> Result<(Header, Duration)> {
+    let hash_range = match options.hash_range() {
+        Some(range) => {
+            Header::check_hash_range(&range, file_size)?;
+            range
+        }
+        None => 0..file_size,
+    };
+
+    let hash_start = Instant::now();
+
+    //Randomised layouts without a seed take one from the digest, so it is needed even if it isn't stored
+    let derive_seed = options.layout() == Layout::Shuffled && options.seed().is_none();
 
-    //If create_digest is set, we get a digest of the input file
-    let od = if create_digest {
-        Some(get_file_hash(&path)?)
+    //If a digest is requested, we get a digest and CRC of the input file
+    let digests = if options.digest() || derive_seed {
+        Some(digests(hash_range.clone())?)
     } else {
         None
     };
 
+    let hash_time = hash_start.elapsed();
+
+    let od = if options.digest() { digests } else { None };
+
+    //Create the bitmap and b2b headers
+    let mut header = Header::new(file_size, od, options.min_dimension(), options.max_dimension(), options.honor_offset(), options.canonical())?;
+
+    header.set_layout(options.layout());
+
+    if options.layout() == Layout::Tiled {
+        header.set_tile_size(options.tile_size());
+    } else {
+        header.set_shuffle_seed(match (options.seed(), digests) {
+            (Some(seed), _) => seed,
+            (None, Some((digest, _))) if derive_seed => random::seed_from_digest(digest),
+            _ => 0,
+        });
+    }
+
+    header.set_hash_range(hash_range);
+
+    if let Some(max_dimension) = options.max_dimension() {
+        header.check_max_dimension(max_dimension)?;
+    }
+
+    Ok((header, hash_time))
+}
+
+/// The header that encoding a file of `file_size` bytes as described by `options` would write, without reading the
+/// file. No digest is computed, so the header has none, and a shuffled layout without a seed gets seed 0 rather than
+/// one taken from the digest
+fn compute_header(file_size: u64, options: &EncodeOptions) -> Result<Header> {
+    let options = options.clone()
+        .with_digest(false)
+        .with_seed(Some(options.seed().unwrap_or(0)));
+
+    //Neither a digest nor a seed is needed, so the file is never read
+    build_header(file_size, &options, |_| Ok((0, 0))).map(|(header, _)| header)
+}
+
+/// Converts the file at `path` into a bitmap in place, then moves it to `output` (which may be the same as `path`).
+/// Setting `cancel` aborts the conversion with `ErrorKind::Cancelled`, as long as the file has not yet been modified
+fn bin_to_bmp<P: AsRef<Path>, Q: AsRef<Path>>(path: P, output: Q, options: &EncodeOptions, cancel: &AtomicBool) -> Result<ConversionReport> {
+
+    let modified = std::fs::metadata(path.as_ref())?.modified().ok();
+
+    let (header, hash_time) = prepare_header(&path, options, cancel)?;
+
+    //Last chance to cancel before the file is modified
+    check_cancelled(cancel)?;
+
+    //The header and digest are only valid if the file hasn't changed since they were computed
+    check_unchanged(&path, header.original_file_size() as u64, modified)?;
+
+    let io_start = Instant::now();
+
     let mut file = OpenOptions::new()
         .read(true)
         .write(true)
@@ -56,145 +344,2982 @@ fn bin_to_bmp<P: AsRef<Path>>(path: P, rename: bool, create_digest: bool) -> Res
 
     let file_size = file.stream_len()?;
 
-    //Create the bitmap and b2b headers
-    let header = Header::new(file_size, od);
+    let snapshot = if options.restore_on_error() {
+        //Other layouts rewrite the whole file, which they hold in memory to do so anyway
+        let saved = if header.layout() == Layout::Row { header.header_size() as u64 } else { file_size };
+
+        Some(Snapshot::take(& mut file, saved)?)
+    } else {
+        None
+    };
+
+    if let Err(e) = encode_in_place(& mut file, &header, file_size, options.chunk_size(), options.sparse()) {
+        return Err(restore_snapshot(& mut file, snapshot, e));
+    }
+
+    if path.as_ref() != output.as_ref() {
+        move_file(path.as_ref(), output.as_ref())?;
+    }
+
+    Ok(ConversionReport {
+        input_bytes: file_size,
+        output_bytes: (header.pixmap_size() + Header::bitmap_header_size()) as u64,
+        hash_time,
+        io_time: io_start.elapsed(),
+        verification: None,
+    })
+}
+
+/// Reserves the disk space for `file` to grow to `len` bytes, without changing its length. The blocks can then be
+/// allocated together rather than as the file grows, and the padding added by `set_len` is allocated instead of being
+/// left as a hole. Filesystems that can't preallocate are left to allocate the blocks as the file grows
+#[cfg(target_os = "linux")]
+fn preallocate(file: &File, len: u64) -> Result<()> {
+    use std::os::unix::io::AsRawFd;
 
-    // If the file is smaller than the combined bmp and b2b headers, then expand it
-    if file_size < Header::total_header_size() as u64 {
-        file.set_len(Header::total_header_size() as u64)?
+    let result = retry_interrupted(|| match unsafe { libc::fallocate(file.as_raw_fd(), libc::FALLOC_FL_KEEP_SIZE, 0, len as libc::off_t) } {
+        0 => Ok(()),
+        _ => Err(std::io::Error::last_os_error()),
+    });
+
+    match result {
+        Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => Ok(()),
+        result => Ok(result?),
     }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn preallocate(_file: &File, _len: u64) -> Result<()> {
+    Ok(())
+}
 
-    // Make a copy of the beginning of the file
-    let mut buffer: [u8; Header::total_header_size() as usize] = [0u8; Header::total_header_size() as usize];
+/// Turns `file`, the original file of `file_size` bytes, into the bitmap described by `header`. If `sparse` is set,
+/// no space is reserved for the padding, which `set_len` leaves as a hole
+fn encode_in_place(file: &mut File, header: &Header, file_size: u64, chunk_size: usize, sparse: bool) -> Result<()> {
+    let header_len = header.header_size() as u64;
 
-    file.read(& mut buffer)?;
+    if !sparse {
+        preallocate(file, (header.pixmap_size() + Header::bitmap_header_size()) as u64)?;
+    }
 
-    // Add these copied bytes to the end of the file
-    file.seek(SeekFrom::End(0))?;
+    //A file smaller than the header region is moved whole to the end of the region, growing the file. Only its own
+    //bytes are moved, as whatever lies past its end is not part of the original
+    let front_len = if header.needs_expansion(file_size) { file_size } else { header_len };
 
-    file.write_all(& buffer)?;
+    // Copy the beginning of the file to the end
+    region::copy_region(file, 0, file_size.max(header_len), front_len, & mut vec![0u8; chunk_size.min(front_len as usize)])?;
 
     //Copy the header to the beginning
     file.seek(SeekFrom::Start(0))?;
 
-    bincode::serialize_into(& mut file, & header)?;
+    bincode::serialize_into(& mut *file, header)?;
 
-    //Resize to add padding
-    file.set_len((header.pixmap_size() + Header::bitmap_header_size()) as u64)?;
+    //Zero the rest of the header region, which is only larger than the header in canonical mode
+    file.write_all(&vec![0u8; (header_len - header.serialized_len()) as usize])?;
 
-    if rename {
-        let renamed = PathBuf::from(format!("{}.bmp", path.as_ref().to_str().unwrap()));
+    //Resize to add padding
+    retry_interrupted(|| file.set_len((header.pixmap_size() + Header::bitmap_header_size()) as u64))?;
 
-        std::fs::rename(path.as_ref(), renamed)?;
+    if header.layout() != Layout::Row {
+        rearrange_region(file, header, false)?;
     }
 
     Ok(())
 }
 
-fn bmp_to_bin<P: AsRef<Path>>(path: P, rename: bool, verify: bool) -> Result<()> {
-    let header = {
-        let mut file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open(path.as_ref())?;
+/// The first bytes of a file and its length, saved before converting it in place so they can be put back if the
+/// conversion fails (see `--restore-on-error`). Conversions only overwrite the start of the file and append to it,
+/// except for layouts other than `Layout::Row`, for which the whole file must be saved
+struct Snapshot {
+    front: Vec<u8>,
+    len: u64,
+}
 
-        // Load combined bitmap and b2b header
-        let header: Header = bincode::deserialize_from(&file)?;
+impl Snapshot {
+    /// Saves the first `front_len` bytes of `file`, or all of it if it is shorter
+    fn take(file: &mut File, front_len: u64) -> Result<Self> {
+        let len = file.stream_len()?;
+
+        let mut front = vec![0u8; front_len.min(len) as usize];
 
-        header.check_id()?;
+        file.seek(SeekFrom::Start(0))?;
 
-        header.check_padding_size()?;
+        file.read_exact(& mut front)?;
 
-        header.check_signature()?;
+        Ok(Self { front, len })
+    }
 
-        //Create a buffer for the data at the end of the file (i.e. beginning of original file)
-        let mut buffer: [u8; Header::total_header_size() as usize] = [0u8; Header::total_header_size() as usize];
+    fn restore(&self, file: &mut File) -> Result<()> {
+        file.seek(SeekFrom::Start(0))?;
 
-        file.seek(SeekFrom::End(-(Header::total_header_size() as i64) - header.padding_size() as i64))?;
+        file.write_all(&self.front)?;
 
-        file.read(&mut buffer)?;
+        retry_interrupted(|| file.set_len(self.len))?;
 
-        //Copy this buffer to the beginning
-        file.seek(SeekFrom::Start(0))?;
+        Ok(file.sync_all()?)
+    }
+}
 
-        file.write_all(&buffer)?;
+/// Puts `snapshot` back into `file`, if one was taken, after converting it in place failed with `error`. Returns
+/// the error to report, which is still `error` even if the file was restored
+fn restore_snapshot(file: &mut File, snapshot: Option<Snapshot>, error: Error) -> Error {
+    match snapshot.map(|snapshot| snapshot.restore(file)) {
+        Some(Ok(())) => eprintln!("The conversion failed, so the file was restored to how it was before."),
+        Some(Err(e)) => eprintln!("The conversion failed, and the file could not be restored: {}", e),
+        None => {}
+    }
 
-        //Resize the file back to its original size
-        file.set_len(header.original_file_size() as u64)?;
+    error
+}
 
-        header
-    };
+/// Writes the bitmap for `input` to `output` in a single pass: the headers, then the input after its first
+/// `header_size` bytes, then those first bytes, then the padding. This produces exactly the same bitmap as
+/// converting in place, but never reads the payload back. The layout is not applied, so for layouts other than
+/// `Layout::Row` the written region must be rearranged afterwards (see `rearrange_region`). The input is copied in
+/// chunks of up to `chunk_size` bytes. If `pad` is not set, the padding is left for the caller to add, so the output
+/// stops short of the size of the bitmap. Returns the number of bytes read from `input`, which should match the original
+/// file size in `header`
+fn encode_to_writer<R: Read, W: Write>(input: &mut R, output: &mut W, header: &Header, chunk_size: usize, pad: bool, cancel: &AtomicBool) -> Result<u64> {
+    let mut remaining = (header.pixmap_size() + Header::bitmap_header_size()) as u64;
 
+    //Writes as much of `data` as fits in the bitmap. For files smaller than the header, the front bytes are cut short
+    fn emit<W: Write>(output: &mut W, remaining: &mut u64, data: &[u8]) -> Result<()> {
+        let length = (*remaining).min(data.len() as u64);
 
-    if verify {
-        let get_new_digest = get_file_hash(&path)?;
+        output.write_all(&data[..length as usize])?;
 
-        let (verified, error) = header.verify(get_new_digest);
+        *remaining -= length;
 
-        if error {
-            println!("Unable to verify as bitmap doesn't contain digest. \n\nTo properly use verify, the -v flag must be passed when converting from binary to bitmap (this loads the bitmap with a hash) AS WELL AS when converting from bitmap to binary (to perform the actual verification)")
-        } else {
-            if verified {
-                println!("Verification successful.")
-            } else {
-                println!("Verification failed.\n\nB2B was able to convert the file back to binary, but the output file may not match the original. \n\nIt is also important to mention that a failed verification does not always mean that the original file does not match (it could be that the only stored digest itself was modified)")
-            }
+        Ok(())
+    }
+
+    // Keep the beginning of the file to write after the rest of it
+    let mut front = vec![0u8; header.header_size() as usize];
+
+    let mut filled = 0;
+
+    while filled < front.len() {
+        let opn = input.read(& mut front[filled..])?;
+        if opn == 0 {
+            break
         }
+        filled += opn;
     }
 
-    if rename {
-        let path_str = path.as_ref().to_str().unwrap();
+    let mut read = filled as u64;
+
+    let mut header_region = bincode::serialize(header)?;
+
+    header_region.resize(header.header_size() as usize, 0);
 
-        let renamed = PathBuf::from(&path_str[..path_str.len() - 4]);
+    emit(output, & mut remaining, &header_region)?;
 
-        std::fs::rename(path.as_ref(), renamed)?;
+    let mut buff = vec![0u8; chunk_size];
+
+    loop {
+        check_cancelled(cancel)?;
+
+        let opn = input.read(& mut buff)?;
+        if opn == 0 {
+            break
+        }
+        read += opn as u64;
+
+        emit(output, & mut remaining, &buff[..opn])?;
     }
 
-    Ok(())
+    emit(output, & mut remaining, &front)?;
+
+    // Zero padding fills the rest of the pixmap
+    let zeros = vec![0u8; chunk_size.min(remaining as usize)];
+
+    while pad && remaining > 0 {
+        let length = remaining.min(zeros.len() as u64) as usize;
+
+        emit(output, & mut remaining, &zeros[..length])?;
+    }
+
+    Ok(read)
 }
 
-fn main() {
-    let matches = App::new("B2B")
-        .version(crate_version!())
-        .author(crate_authors!())
-        .about(crate_description!())
-        .arg(Arg::new("path")
-            .about("Path to a binary or bitmap file to convert. Converts non-bitmaps into bitmaps, and bitmaps back into non-bitmaps")
-            .takes_value(true)
-            .required(true)
-            .validator(|path| {
-                let path = Path::new(path);
+/// Converts the file at `path` into a new bitmap at `output` without modifying the original. Fails if `output`
+/// already exists, and removes `output` again if the conversion fails or is cancelled
+fn bin_to_bmp_copy<P: AsRef<Path>, Q: AsRef<Path>>(path: P, output: Q, options: &EncodeOptions, cancel: &AtomicBool) -> Result<ConversionReport> {
 
-                if path.exists() {
-                    if OpenOptions::new()
-                        .read(true)
-                        .write(true)
-                        .open(path)
-                        .is_ok() {
-                        Ok(())
-                    } else {
-                        Err(String::from("Cannot read or write to specified file."))
-                    }
-                } else {
-                    Err(String::from("The specified path does not exist."))
-                }
-            }))
-        .arg(Arg::new("fast")
-            .about("Uses fast mode, which skips the verification stage")
-            .takes_value(false)
-            .required(false)
-            .short('f')
-            .long("fast")
-        )
-        .get_matches();
+    let modified = std::fs::metadata(path.as_ref())?.modified().ok();
 
-    let path = matches.value_of("path").unwrap();
+    let (header, hash_time) = prepare_header(&path, options, cancel)?;
 
-    let extension = &path[path.len() - 4..];
+    let io_start = Instant::now();
 
-    if extension == ".bmp" {
-        bmp_to_bin(path, true, !matches.is_present("fast")).unwrap();
+    let mut input = File::open(path.as_ref())?;
+
+    write_new_bitmap(& mut input, output, &header, options, cancel, || check_unchanged(&path, header.original_file_size() as u64, modified))?;
+
+    Ok(ConversionReport {
+        input_bytes: header.original_file_size() as u64,
+        output_bytes: (header.pixmap_size() + Header::bitmap_header_size()) as u64,
+        hash_time,
+        io_time: io_start.elapsed(),
+        verification: None,
+    })
+}
+
+/// Writes the bitmap for `input` described by `header` to a new file at `output`, then applies the layout. `check` is
+/// run once everything has been written, before the layout is applied. Fails if `output` already exists, and removes
+/// `output` again if anything fails
+fn write_new_bitmap<R: Read, Q: AsRef<Path>, F: FnOnce() -> Result<()>>(input: &mut R, output: Q, header: &Header, options: &EncodeOptions, cancel: &AtomicBool, check: F) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(output.as_ref())?;
+
+    let result = {
+        let mut writer = BufWriter::new(& mut file);
+
+        encode_to_writer(input, & mut writer, header, options.chunk_size(), !options.sparse(), cancel)
+            .and_then(|read| if read != header.original_file_size() as u64 {
+                Err(Error::new(ErrorKind::FileChanged, ""))
+            } else {
+                Ok(())
+            })
+            .and_then(|_| writer.flush().map_err(Error::from))
+            //Without the padding written out, extending the file leaves it as a hole
+            .and_then(|_| if options.sparse() {
+                retry_interrupted(|| writer.get_ref().set_len((header.pixmap_size() + Header::bitmap_header_size()) as u64)).map_err(Error::from)
+            } else {
+                Ok(())
+            })
+            .and_then(|_| check())
+    }.and_then(|_| if header.layout() != Layout::Row {
+        rearrange_region(& mut file, header, false)
+    } else {
+        Ok(())
+    });
+
+    drop(file);
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(output.as_ref());
+    }
+
+    result
+}
+
+/// Digest and CRC of `range` of `data`
+fn slice_digests(data: &[u8], range: Range<u64>) -> (u128, u32) {
+    let mut hash = Blake256::new();
+    let mut crc = Crc32::new();
+
+    hash.update(&data[range.start as usize..range.end as usize]);
+    crc.update(&data[range.start as usize..range.end as usize]);
+
+    (finalize_hash(hash), crc.finalize())
+}
+
+/// Decodes `bitmap` in memory, returning its header and the original file
+fn decode_in_memory(bitmap: Vec<u8>, cancel: &AtomicBool) -> Result<(Header, Vec<u8>)> {
+    let stored: Header = bincode::deserialize(&bitmap)?;
+
+    let mut payload = Vec::with_capacity(stored.original_file_size() as usize);
+
+    decode_reader_to_writer(& mut Cursor::new(bitmap), & mut payload, cancel)?;
+
+    Ok((stored, payload))
+}
+
+/// Decodes the bitmap (or png) at `path` in memory and encodes the original file again as described by `options`, replacing
+/// the bitmap with one at `output` (which may be the same as `path`). The decoded file is checked against the stored
+/// digest first, if there is one, so a corrupt bitmap is never re-encoded. Never writes the original file to disk
+fn reencode<P: AsRef<Path>, Q: AsRef<Path>>(path: P, output: Q, options: &EncodeOptions, cancel: &AtomicBool) -> Result<ConversionReport> {
+    let io_start = Instant::now();
+
+    let input_bytes = std::fs::metadata(path.as_ref())?.len();
+
+    //Pngs are turned back into the bitmap they contain in memory
+    let bitmap = match read_b2b_container(path.as_ref())? {
+        Some(bitmap) => bitmap,
+        None => std::fs::read(path.as_ref())?,
+    };
+
+    let (stored, payload) = decode_in_memory(bitmap, cancel)?;
+
+    let mut io_time = io_start.elapsed();
+
+    let hash_start = Instant::now();
+
+    let outcome = stored.verify(slice_digests(&payload, stored.hash_range()).0);
+
+    if outcome.verified() == Some(false) {
+        return Err(Error::new(ErrorKind::VerificationFailed, format!("{}, so was not re-encoded", outcome)));
+    }
+
+    let mut hash_time = hash_start.elapsed();
+
+    let (header, header_hash_time) = build_header(payload.len() as u64, options, |range| Ok(slice_digests(&payload, range)))?;
+
+    hash_time += header_hash_time;
+
+    let write_start = Instant::now();
+
+    //Written next to the output then moved over it, so the bitmap is only replaced once the new one is complete
+    let temporary = bitmap_path(output.as_ref(), OsStr::new("tmp"));
+
+    write_new_bitmap(& mut payload.as_slice(), &temporary, &header, options, cancel, || Ok(()))?;
+
+    let mut output_bytes = (header.pixmap_size() + Header::bitmap_header_size()) as u64;
+
+    let result = if options.bitmap_digest() {
+        seal_bitmap_file(&temporary, cancel).map(|_| None)
     } else {
-        bin_to_bmp(path, true, !matches.is_present("fast")).unwrap();
+        apply_container(&temporary, options.container())
+    };
+
+    match result {
+        Ok(Some(size)) => output_bytes = size,
+        Ok(None) => {}
+        Err(e) => {
+            let _ = std::fs::remove_file(&temporary);
+
+            return Err(e);
+        }
+    }
+
+    move_file(&temporary, output.as_ref())?;
+
+    if path.as_ref() != output.as_ref() {
+        std::fs::remove_file(path.as_ref())?;
+    }
+
+    io_time += write_start.elapsed();
+
+    Ok(ConversionReport {
+        input_bytes,
+        output_bytes,
+        hash_time,
+        io_time,
+        verification: Some(outcome),
+    })
+}
+
+/// Size of the converted file for a file of `file_size` bytes encoded as described by `options`
+fn predicted_size(file_size: u64, options: &EncodeOptions) -> Result<u64> {
+    Ok(match options.container() {
+        Container::Bitmap => Header::predicted_size(file_size, options.min_dimension(), options.max_dimension(), options.honor_offset(), options.canonical())?,
+        Container::Png => {
+            let header = Header::new(file_size, None, options.min_dimension(), options.max_dimension(), options.honor_offset(), options.canonical())?;
+
+            png::encoded_size(header.width(), header.height())
+        }
+        Container::Raw => Header::predicted_size(file_size, options.min_dimension(), options.max_dimension(), options.honor_offset(), options.canonical())? - Header::bitmap_header_size() as u64,
+    })
+}
+
+/// Prints the predicted size of the converted file, warning if it is much larger than the file. Both go to stderr, so
+/// they don't end up in reports written to stdout. Fails if the expansion is past `MAX_EXPANSION` and would use a lot of
+/// space, unless `options` forces the conversion
+fn check_expansion(file_size: u64, options: &EncodeOptions) -> Result<()> {
+    let predicted = predicted_size(file_size, options)?;
+
+    let expansion = predicted as f64 / file_size.max(1) as f64;
+
+    eprintln!("Predicted size: {} bytes ({:.2}x)", predicted, expansion);
+
+    if expansion > MAX_EXPANSION && predicted - file_size >= MAX_EXPANSION_MIN_SIZE && !options.force() {
+        return Err(Error::new(ErrorKind::ExpansionTooLarge, format!("{} bytes would become {} ({:.2}x), pass --force to convert anyway", file_size, predicted, expansion)));
+    }
+
+    if expansion > WARN_EXPANSION {
+        eprintln!("Warning: the bitmap will be {:.2} times the size of the file, mostly padding", expansion);
+    }
+
+    Ok(())
+}
+
+/// Converts the file at `path` into a bitmap at `output` as described by `options`, either in place or by writing a new file
+fn encode_with_options<P: AsRef<Path>, Q: AsRef<Path>>(path: P, output: Q, options: &EncodeOptions, cancel: &AtomicBool) -> Result<ConversionReport> {
+    check_expansion(std::fs::metadata(path.as_ref())?.len(), options)?;
+
+    let report = if options.in_place() {
+        bin_to_bmp(path, output.as_ref(), options, cancel)?
+    } else {
+        bin_to_bmp_copy(path, output.as_ref(), options, cancel)?
+    };
+
+    let container_start = Instant::now();
+
+    let result = if options.bitmap_digest() {
+        seal_bitmap_file(output.as_ref(), cancel).map(|_| None)
+    } else {
+        apply_container(output.as_ref(), options.container())
+    };
+
+    //Don't leave a bitmap with the container's name behind
+    if result.is_err() && !options.in_place() {
+        let _ = std::fs::remove_file(output.as_ref());
+    }
+
+    Ok(match result? {
+        Some(size) => ConversionReport {
+            output_bytes: size,
+            io_time: report.io_time + container_start.elapsed(),
+            ..report
+        },
+        None => report,
+    })
+}
+
+/// Rewrites the bitmap at `path` in `container`, returning the new size of the file, or `None` if it is left as a bitmap
+fn apply_container<P: AsRef<Path>>(path: P, container: Container) -> Result<Option<u64>> {
+    if container == Container::Bitmap {
+        return Ok(None);
+    }
+
+    let bitmap = std::fs::read(path.as_ref())?;
+
+    let header: Header = bincode::deserialize(&bitmap)?;
+
+    header.validate_all(bitmap.len() as u64)?;
+
+    let converted = container_bytes(bitmap, &header, container);
+
+    std::fs::write(path.as_ref(), &converted)?;
+
+    Ok(Some(converted.len() as u64))
+}
+
+/// Converts `bitmap`, described by `header`, to `container`. A png holds the same pixels as the bitmap, which
+/// `png_to_bitmap` turns back into the bitmap. A raw file is the bitmap without its bitmap header, leaving the b2b
+/// header, payload and padding, which `raw_to_bitmap` turns back into the bitmap
+fn container_bytes(mut bitmap: Vec<u8>, header: &Header, container: Container) -> Vec<u8> {
+    match container {
+        Container::Bitmap => bitmap,
+        Container::Png => png::encode(header.width(), header.height(), &bitmap[Header::bitmap_header_size() as usize..]),
+        Container::Raw => bitmap.split_off(Header::bitmap_header_size() as usize),
+    }
+}
+
+/// If `raw` is a raw b2b file (see `Container::Raw`), rebuilds the bitmap it came from. The bitmap header is recreated
+/// with the most nearly square dimensions that fit, which only matter to the row layout if they differ from the
+/// original ones. Returns `None` for anything else
+fn raw_to_bitmap(raw: &[u8]) -> Result<Option<Vec<u8>>> {
+    let rebuilt = match (header::stored_lengths(raw), Header::from_pixmap_len(raw.len() as u64)) {
+        (Some(_), Some(rebuilt)) => rebuilt,
+        _ => return Ok(None),
+    };
+
+    let mut bitmap = header::bitmap_header_bytes(rebuilt.width(), rebuilt.height())?;
+
+    bitmap.extend_from_slice(raw);
+
+    Ok(Some(bitmap))
+}
+
+/// If `path` is a raw b2b file, rebuilds the bitmap it came from in memory (see `raw_to_bitmap`). Returns `None` for any other file
+fn read_b2b_raw<P: AsRef<Path>>(path: P) -> Result<Option<Vec<u8>>> {
+    if !header::is_b2b_header(&read_prefix(path.as_ref(), Header::b2b_header_size() as u64)?) {
+        return Ok(None);
+    }
+
+    raw_to_bitmap(&std::fs::read(path.as_ref())?)
+}
+
+/// If `path` is a png or raw file created by b2b, rebuilds the bitmap it holds in memory. Returns `None` for any other file
+fn read_b2b_container<P: AsRef<Path>>(path: P) -> Result<Option<Vec<u8>>> {
+    match read_b2b_png(path.as_ref())? {
+        Some(bitmap) => Ok(Some(bitmap)),
+        None => read_b2b_raw(path),
+    }
+}
+
+/// If `png` is a png created by b2b (see `Container::Png`), rebuilds the bitmap it holds. Returns `None` for anything
+/// else, including pngs that were not created by b2b
+fn png_to_bitmap(png: &[u8]) -> Result<Option<Vec<u8>>> {
+    let (width, height, pixels) = match png::decode(png) {
+        Ok(image) => image,
+        Err(_) => return Ok(None),
+    };
+
+    let mut bitmap = header::bitmap_header_bytes(width, height)?;
+
+    bitmap.extend_from_slice(&pixels);
+
+    let header: Header = match bincode::deserialize(&bitmap) {
+        Ok(header) => header,
+        Err(_) => return Ok(None),
+    };
+
+    if header.check_id().is_ok() && header.check_signature().is_ok() {
+        Ok(Some(bitmap))
+    } else {
+        Ok(None)
+    }
+}
+
+/// If `path` is a png created by b2b, rebuilds the bitmap it holds in memory (see `png_to_bitmap`). Returns `None`
+/// for any other file
+fn read_b2b_png<P: AsRef<Path>>(path: P) -> Result<Option<Vec<u8>>> {
+    let mut signature = [0u8; 8];
+
+    if File::open(path.as_ref())?.read_exact(& mut signature).is_err() || signature != png::PNG_SIGNATURE {
+        return Ok(None);
+    }
+
+    png_to_bitmap(&std::fs::read(path.as_ref())?)
+}
+
+/// Turns `file`, the bitmap described by `header`, back into the original file
+fn decode_in_place(file: &mut File, header: &Header) -> Result<()> {
+    if header.layout() != Layout::Row {
+        rearrange_region(file, header, true)?;
+    }
+
+    //Copy the data at the end of the file (i.e. beginning of original file) to the beginning. The size
+    //comes from the header, as the bitmap may have been written with a different header size
+    let front_len = header.header_size().min(header.original_file_size()) as u64;
+
+    region::copy_region(file, header.front_offset(), 0, front_len, & mut vec![0u8; DEFAULT_CHUNK_SIZE.min(front_len as usize)])?;
+
+    //Resize the file back to its original size. This only happens once the front of the file is back in place,
+    //and validate_all has checked that the original size agrees with the rest of the header, so nothing but the
+    //moved front and the padding is cut off
+    retry_interrupted(|| file.set_len(header.original_file_size() as u64))?;
+
+    Ok(())
+}
+
+/// Converts the bitmap at `path` back into the original file in place, then moves it to `output` (which may be the same as `path`).
+/// Setting `cancel` aborts the conversion with `ErrorKind::Cancelled`, as long as the file has not yet been modified. If
+/// `restore_on_error` is set, the bitmap is put back as it was if the conversion fails
+fn bmp_to_bin<P: AsRef<Path>, Q: AsRef<Path>>(path: P, output: Q, verify: Option<VerifyOptions>, restore_on_error: bool, cancel: &AtomicBool) -> Result<ConversionReport> {
+    let io_start = Instant::now();
+
+    let (header, bitmap_size) = {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path.as_ref())?;
+
+        // Load combined bitmap and b2b header
+        let header: Header = bincode::deserialize_from(&file)?;
+
+        let bitmap_size = file.stream_len()?;
+
+        header.validate_all(bitmap_size)?;
+
+        check_cancelled(cancel)?;
+
+        let snapshot = if restore_on_error {
+            let saved = if header.layout() == Layout::Row { header.header_size() as u64 } else { bitmap_size };
+
+            Some(Snapshot::take(& mut file, saved)?)
+        } else {
+            None
+        };
+
+        if let Err(e) = decode_in_place(& mut file, &header) {
+            return Err(restore_snapshot(& mut file, snapshot, e));
+        }
+
+        (header, bitmap_size)
+    };
+
+    let mut io_time = io_start.elapsed();
+
+    let hash_start = Instant::now();
+
+    let mut verification = None;
+
+    if verify.is_some_and(|verify| verify.quick()) {
+        let outcome = header.verify_crc(get_file_crc(&path, header.hash_range(), cancel)?);
+
+        match outcome {
+            VerifyOutcome::NoDigest => progress!("Unable to quick verify as bitmap doesn't contain a CRC. \n\nA CRC is stored alongside the digest, so the bitmap must have been created without -f for quick verification to work"),
+            VerifyOutcome::Verified => progress!("Quick verification (CRC32) successful."),
+            _ => progress!("Quick verification (CRC32) failed: {}.", outcome),
+        }
+
+        verification = Some(outcome);
+    } else if verify.is_some() {
+        let outcome = header.verify(get_file_hash(&path, header.hash_range(), cancel)?);
+
+        match outcome {
+            VerifyOutcome::NoDigest => progress!("Unable to verify as bitmap doesn't contain digest. \n\nTo properly use verify, the -v flag must be passed when converting from binary to bitmap (this loads the bitmap with a hash) AS WELL AS when converting from bitmap to binary (to perform the actual verification)"),
+            VerifyOutcome::Verified => progress!("Verification successful."),
+            _ => progress!("Verification failed: {}.", outcome),
+        }
+
+        verification = Some(outcome);
+    }
+
+    let hash_time = hash_start.elapsed();
+
+    let rename_start = Instant::now();
+
+    if path.as_ref() != output.as_ref() {
+        move_file(path.as_ref(), output.as_ref())?;
+    }
+
+    io_time += rename_start.elapsed();
+
+    Ok(ConversionReport {
+        input_bytes: bitmap_size,
+        output_bytes: header.original_file_size() as u64,
+        hash_time,
+        io_time,
+        verification,
+    })
+}
+
+/// Returns true if `path` is a bitmap, whether or not it was created by b2b: it starts with the bitmap ID, followed by
+/// the size of the file. Other files that happen to start with the ID are not bitmaps
+fn is_bitmap<P: AsRef<Path>>(path: P) -> Result<bool> {
+    let mut file = File::open(path.as_ref())?;
+
+    //The bitmap ID and the size of the file
+    let mut start = [0u8; 6];
+
+    match file.read_exact(& mut start) {
+        Ok(()) => Ok(u16::from_le_bytes([start[0], start[1]]) == BITMAP_ID
+            && u32::from_le_bytes([start[2], start[3], start[4], start[5]]) as u64 == file.metadata()?.len()),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Returns true if `path` is a bitmap with a b2b signature. Files too short to hold the signature are not b2b bitmaps,
+/// and a bitmap with the signature whose header cannot be read is a corrupt b2b bitmap, so is an error rather than a
+/// file to encode
+fn is_b2b_bitmap<P: AsRef<Path>>(path: P) -> Result<bool> {
+    let prefix = read_prefix(path.as_ref(), (header::BITMAP_HEADER_SIZE + header::LEGACY_B2B_HEADER_SIZE) as u64)?;
+
+    if !header::has_b2b_signature(&prefix) {
+        return Ok(false);
+    }
+
+    let _: Header = bincode::deserialize_from(File::open(path.as_ref())?)?;
+
+    Ok(true)
+}
+
+/// Reads and validates the header of the bitmap at `path` without modifying the file
+fn read_header<P: AsRef<Path>>(path: P) -> Result<Header> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .open(path.as_ref())?;
+
+    let header: Header = bincode::deserialize_from(&file)?;
+
+    header.validate_all(file.stream_len()?)?;
+
+    Ok(header)
+}
+
+/// Reads the headers of every b2b bitmap under `dir` accepted by `filter`, in path order. Files that are not b2b
+/// bitmaps are skipped, and bitmaps with invalid headers are reported and skipped
+fn read_headers(dir: &Path, filter: &batch::Filter) -> Result<Vec<(PathBuf, Header)>> {
+    let mut headers = Vec::new();
+
+    for file in batch::files_in(dir, filter)? {
+        if let Ok(false) = is_b2b_bitmap(&file) {
+            continue;
+        }
+
+        match read_header(&file) {
+            Ok(header) => headers.push((file, header)),
+            Err(e) => eprintln!("Skipping {}: {}", file.display(), e),
+        }
+    }
+
+    Ok(headers)
+}
+
+/// Digest of the original file stored in the b2b bitmap at `path`. The digest in the header is used if it covers the
+/// whole file, otherwise the payload is hashed
+fn original_digest(path: &Path, cancel: &AtomicBool) -> Result<u128> {
+    let mut file = File::open(path)?;
+
+    let header: Header = bincode::deserialize_from(& mut file)?;
+
+    header.validate_all(file.stream_len()?)?;
+
+    match header.digest() {
+        Some(digest) if header.hash_range() == (0..header.original_file_size() as u64) => Ok(digest),
+        _ => {
+            let mut hash = Blake256::new();
+
+            read_payload(& mut file, &header, DEFAULT_CHUNK_SIZE, cancel, |chunk| {
+                hash.update(chunk);
+                Ok(())
+            })?;
+
+            Ok(finalize_hash(hash))
+        }
+    }
+}
+
+/// Name of `path` relative to `base` as used in a manifest, with `/` between the parts
+fn manifest_name(path: &Path, base: &Path) -> String {
+    let relative = path.strip_prefix(base).unwrap_or(path);
+
+    relative.components().map(|part| part.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/")
+}
+
+/// Checks every b2b bitmap at `path`, a directory or a single bitmap, against the expected digests in `manifest`,
+/// printing a line for each. A bitmap is looked up by the name of the original file it holds, then by its own name.
+/// When `path` is a directory, files listed in the manifest with no bitmap are reported as missing
+fn check_manifest(path: &Path, manifest: &Manifest, filter: &batch::Filter, cancel: &AtomicBool) -> Result<ManifestCounts> {
+    let (base, files) = if path.is_dir() {
+        (path, batch::files_in(path, filter)?)
+    } else {
+        (path.parent().unwrap_or_else(|| Path::new("")), vec![path.to_path_buf()])
+    };
+
+    let mut counts = ManifestCounts::default();
+    let mut found = std::collections::HashSet::new();
+
+    for file in files {
+        check_cancelled(cancel)?;
+
+        if let Ok(false) = is_b2b_bitmap(&file) {
+            continue;
+        }
+
+        let original = manifest_name(&binary_path(&file), base);
+        let bitmap = manifest_name(&file, base);
+
+        let (name, expected) = match manifest.expected(&original) {
+            Some(expected) => (original, Some(expected)),
+            None => (bitmap.clone(), manifest.expected(&bitmap)),
+        };
+
+        let expected = match expected {
+            Some(expected) => expected,
+            None => {
+                println!("UNLISTED   {}", bitmap);
+                counts.unlisted += 1;
+                continue;
+            }
+        };
+
+        found.insert(name.clone());
+
+        match original_digest(&file, cancel) {
+            Ok(digest) if manifest::digests_match(digest, expected) => {
+                println!("OK         {}", name);
+                counts.matched += 1;
+            }
+            Ok(digest) => {
+                println!("MISMATCH   {} (expected {:032x}, found {:032x})", name, expected, digest);
+                counts.mismatched += 1;
+            }
+            Err(e) => {
+                println!("UNREADABLE {} ({})", bitmap, e);
+                counts.failures += 1;
+            }
+        }
+    }
+
+    if path.is_dir() {
+        for name in manifest.names().filter(|name| !found.contains(*name)) {
+            println!("MISSING    {}", name);
+            counts.missing += 1;
+        }
+    }
+
+    Ok(counts)
+}
+
+/// Writes a thumbnail of the b2b bitmap, png or raw file at `path` to a new bitmap at `output`, no larger than
+/// `max_dimension` in either direction (see `thumbnail::thumbnail`). Returns the size of the thumbnail
+fn write_thumbnail(path: &Path, output: &Path, max_dimension: u32) -> Result<(u32, u32)> {
+    let (header, thumbnail) = if is_b2b_bitmap(path)? {
+        let header = read_header(path)?;
+
+        let mut file = BufReader::new(File::open(path)?);
+
+        file.seek(SeekFrom::Start((Header::bitmap_header_size() + header.pixel_gap()) as u64))?;
+
+        let thumbnail = thumbnail::thumbnail(&header, file, max_dimension)?;
+
+        (header, thumbnail)
+    } else {
+        let bitmap = read_b2b_container(path)?.ok_or_else(|| Error::new(ErrorKind::InvalidB2BSignature, format!("{} is not a b2b file", path.display())))?;
+
+        let header: Header = bincode::deserialize(&bitmap)?;
+
+        let thumbnail = thumbnail::thumbnail(&header, &bitmap[(Header::bitmap_header_size() + header.pixel_gap()) as usize..], max_dimension)?;
+
+        (header, thumbnail)
+    };
+
+    OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(output)?
+        .write_all(&thumbnail)?;
+
+    Ok(thumbnail::thumbnail_dimensions(header.width(), header.height(), max_dimension))
+}
+
+/// Writes the pixmap of the bitmap at `path`, i.e. everything after the bitmap header (the b2b header, payload and
+/// padding), to a new file at `output`. The bitmap is not modified and any layout is left as it is. Returns the number
+/// of bytes written
+fn export_pixmap<P: AsRef<Path>, Q: AsRef<Path>>(path: P, output: Q, cancel: &AtomicBool) -> Result<u64> {
+    let header = read_header(path.as_ref())?;
+
+    let start = Header::bitmap_header_size() as u64;
+    let end = start + header.pixmap_size() as u64;
+
+    let mut destination = BufWriter::new(OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(output.as_ref())?);
+
+    let result = read_range_chunks(path, start..end, DEFAULT_CHUNK_SIZE, cancel, |chunk| {
+        destination.write_all(chunk)?;
+        Ok(())
+    }).and_then(|_| Ok(destination.flush()?));
+
+    drop(destination);
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(output.as_ref());
+    }
+
+    result.map(|_| end - start)
+}
+
+/// Reads up to the first `len` bytes of the file at `path`, fewer if the file is shorter. The file is not modified
+fn read_prefix<P: AsRef<Path>>(path: P, len: u64) -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+
+    File::open(path.as_ref())?.take(len).read_to_end(& mut bytes)?;
+
+    Ok(bytes)
+}
+
+/// Reads the header region at the start of the bitmap at `path`, as long as its header declares: shorter for headers of
+/// the first format, and a whole block for canonical bitmaps. The header is parsed but not validated, so the headers of
+/// damaged bitmaps can still be read
+fn read_header_region<P: AsRef<Path>>(path: P) -> Result<Vec<u8>> {
+    let header: Header = bincode::deserialize_from(File::open(path.as_ref())?)?;
+
+    read_prefix(path, header.header_size() as u64)
+}
+
+/// Reads the trailer of the bitmap at `path`, i.e. the first `header_size` bytes of the original file, which are stored
+/// after the rest of it. Returns the offset and length of the trailer in the bitmap, and its first `count` bytes
+/// (restored to file order for layouts other than `Layout::Row`). The bitmap is not modified
+fn read_trailer<P: AsRef<Path>>(path: P, count: u64) -> Result<(u64, u64, Vec<u8>)> {
+    let mut file = File::open(path.as_ref())?;
+
+    let header: Header = bincode::deserialize_from(&file)?;
+
+    header.validate_all(file.stream_len()?)?;
+
+    let offset = header.front_offset();
+    let total = (header.header_size() as u64).min(header.original_file_size() as u64);
+    let length = total.min(count) as usize;
+
+    let trailer = if header.layout() == Layout::Row {
+        let mut trailer = vec![0u8; length];
+
+        file.seek(SeekFrom::Start(offset))?;
+
+        file.read_exact(& mut trailer)?;
+
+        trailer
+    } else {
+        read_restored_bitmap(& mut file, &header)?[offset as usize..offset as usize + length].to_vec()
+    };
+
+    Ok((offset, total, trailer))
+}
+
+/// Recovers what remains of the original file from a bitmap whose end has been cut off, writing it to a new file at
+/// `output`. The first `header_size` bytes of the original are stored at the end of the bitmap, so some of those may
+/// have been lost; they are replaced with zeros. Returns the range of the original that was lost, which is empty if
+/// the whole original was recovered
+fn recover_truncated<P: AsRef<Path>, Q: AsRef<Path>>(path: P, output: Q, cancel: &AtomicBool) -> Result<Range<u64>> {
+    let mut file = File::open(path.as_ref())?;
+
+    let header: Header = bincode::deserialize_from(&file)?;
+
+    header.check_id()?;
+    header.check_signature()?;
+    header.check_padding_size()?;
+    header.check_offsets()?;
+    header.check_header_size()?;
+
+    let lost = header.recoverable_truncation(file.stream_len()?)
+        .ok_or_else(|| Error::new(ErrorKind::BadFileSize, "bitmap is not truncated, or too little of it remains to recover"))?;
+
+    //The front bytes are read up to the point where the bitmap was cut off
+    let mut front = vec![0u8; lost.end as usize];
+
+    file.seek(SeekFrom::Start(header.front_offset()))?;
+
+    file.read_exact(& mut front[..lost.start as usize])?;
+
+    let mut destination = BufWriter::new(OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(output.as_ref())?);
+
+    let header_size = header.header_size() as u64;
+
+    let result = destination.write_all(&front).map_err(Error::from)
+        .and_then(|_| read_range_chunks(path.as_ref(), header_size..header_size.max(header.original_file_size() as u64), DEFAULT_CHUNK_SIZE, cancel, |chunk| {
+            destination.write_all(chunk)?;
+            Ok(())
+        }))
+        .and_then(|_| Ok(destination.flush()?));
+
+    drop(destination);
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(output.as_ref());
+    }
+
+    result.map(|_| lost)
+}
+
+/// Strips trailing zero bytes from `bytes`
+fn trim_trailing_zeros(bytes: &[u8]) -> &[u8] {
+    let len = bytes.iter().rposition(|&byte| byte != 0).map_or(0, |last| last + 1);
+
+    &bytes[..len]
+}
+
+/// Recovers the original file from `path`, either a bitmap whose bitmap header is damaged or a bare pixmap (see
+/// `export_pixmap`), writing it to a new file at `output`. The length of the original comes from the b2b header at the
+/// start of the pixmap. If that is damaged too and `trim` is set, the length is guessed by stripping the zero padding
+/// from the end, which also strips any zeros the first `header_size` bytes of the original happened to end with and so
+/// misplaces them. A damaged b2b header is assumed to have been the default size and the layout `Layout::Row`.
+/// Returns the size of the recovered file
+fn recover_headerless<P: AsRef<Path>, Q: AsRef<Path>>(path: P, output: Q, trim: bool) -> Result<u64> {
+    let bytes = std::fs::read(path.as_ref())?;
+
+    let bitmap_header_size = Header::bitmap_header_size() as usize;
+
+    //Look for an intact b2b header after a bitmap header or at the start. Failing that, a bitmap starts with the
+    //bitmap ID and a pixmap with the b2b header
+    let skip = [bitmap_header_size, 0].iter().copied()
+        .find(|&skip| bytes.get(skip..).and_then(header::stored_lengths).is_some())
+        .unwrap_or(if bytes.len() >= bitmap_header_size && bytes[..2] == BITMAP_ID.to_le_bytes() { bitmap_header_size } else { 0 });
+
+    let pixmap = &bytes[skip..];
+
+    let (length, header_size) = match header::stored_lengths(pixmap) {
+        Some(lengths) => lengths,
+        None if trim => {
+            let data = pixmap.get(Header::b2b_header_size() as usize..).unwrap_or(&[]);
+
+            (trim_trailing_zeros(data).len() as u64, (Header::bitmap_header_size() + Header::b2b_header_size()) as u64)
+        }
+        None => return Err(Error::new(ErrorKind::InvalidB2BSignature, "the b2b header is damaged, so the size of the original file is unknown. Pass --trim to guess it")),
+    };
+
+    let data = pixmap.get((header_size - Header::bitmap_header_size() as u64) as usize..).unwrap_or(&[]);
+
+    if (data.len() as u64) < length {
+        return Err(Error::new(ErrorKind::BadFileSize, "pixmap is too short to hold the original file"));
+    }
+
+    //Everything after the first `header_size` bytes of the original, which are stored after it
+    let rest = (length - header_size.min(length)) as usize;
+
+    let mut destination = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(output.as_ref())?;
+
+    let result = destination.write_all(&data[rest..length as usize])
+        .and_then(|_| destination.write_all(&data[..rest]));
+
+    drop(destination);
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(output.as_ref());
+    }
+
+    result?;
+
+    Ok(length)
+}
+
+/// Arranges the data following the b2b header according to the header's layout, or restores it to file order
+/// if `restore` is set. The whole region is read into memory
+fn rearrange_region(file: &mut File, header: &Header, restore: bool) -> Result<()> {
+    let mut region = Vec::new();
+
+    file.seek(SeekFrom::Start(header.header_size() as u64))?;
+
+    file.read_to_end(& mut region)?;
+
+    let header_bytes = header.header_bytes_in_pixels();
+
+    if restore {
+        header.layout().restore(header.width(), header.bytes_per_pixel(), header_bytes, header.layout_parameter(), & mut region);
+    } else {
+        header.layout().arrange(header.width(), header.bytes_per_pixel(), header_bytes, header.layout_parameter(), & mut region);
+    }
+
+    file.seek(SeekFrom::Start(header.header_size() as u64))?;
+
+    file.write_all(&region)?;
+
+    Ok(())
+}
+
+/// Reads the whole bitmap into memory with the data following the b2b header restored to file order, as if it
+/// had been encoded with `Layout::Row`
+fn read_restored_bitmap<R: Read + Seek>(file: &mut R, header: &Header) -> Result<Vec<u8>> {
+    let mut bitmap = Vec::new();
+
+    file.seek(SeekFrom::Start(0))?;
+
+    file.read_to_end(& mut bitmap)?;
+
+    let header_bytes = header.header_bytes_in_pixels();
+
+    header.layout().restore(header.width(), header.bytes_per_pixel(), header_bytes, header.layout_parameter(), & mut bitmap[header.header_size() as usize..]);
+
+    Ok(bitmap)
+}
+
+/// Passes the bytes of the original file, as stored in the bitmap `file`, to `process` in order without
+/// modifying the bitmap, `chunk_size` bytes at a time. The bitmap must be in file order (see `read_restored_bitmap`)
+fn read_payload_chunks<R: Read + Seek, F: FnMut(&[u8]) -> Result<()>>(file: &mut R, header: &Header, chunk_size: usize, cancel: &AtomicBool, mut process: F) -> Result<()> {
+    let mut reader = PayloadReader::new(file, header);
+
+    let mut remaining = header.original_file_size() as u64;
+
+    let mut buff = vec![0u8; chunk_size];
+
+    while remaining > 0 {
+        check_cancelled(cancel)?;
+
+        let chunk = & mut buff[..remaining.min(chunk_size as u64) as usize];
+
+        reader.read_exact(chunk)?;
+
+        process(chunk)?;
+
+        remaining -= chunk.len() as u64;
+    }
+
+    Ok(())
+}
+
+/// Passes the bytes of the original file stored in the bitmap `file` to `process`, `chunk_size` bytes at a time,
+/// restoring the layout in memory if needed
+fn read_payload<R: Read + Seek, F: FnMut(&[u8]) -> Result<()>>(file: &mut R, header: &Header, chunk_size: usize, cancel: &AtomicBool, process: F) -> Result<()> {
+    if header.layout() == Layout::Row {
+        read_payload_chunks(file, header, chunk_size, cancel, process)
+    } else {
+        read_payload_chunks(& mut Cursor::new(read_restored_bitmap(file, header)?), header, chunk_size, cancel, process)
+    }
+}
+
+/// Counts the bytes of the original file stored in the bitmap at `path`, without modifying the bitmap
+fn inspect_payload<P: AsRef<Path>>(path: P, cancel: &AtomicBool) -> Result<info::ByteHistogram> {
+    let mut file = File::open(path.as_ref())?;
+
+    let header: Header = bincode::deserialize_from(& mut file)?;
+
+    header.validate_all(file.stream_len()?)?;
+
+    let mut histogram = info::ByteHistogram::new();
+
+    read_payload(& mut file, &header, DEFAULT_CHUNK_SIZE, cancel, |chunk| {
+        histogram.update(chunk);
+        Ok(())
+    })?;
+
+    Ok(histogram)
+}
+
+/// Decodes the bitmap at `path`, writing the original file to `output`. This is the read-only counterpart of
+/// `bmp_to_bin`: the bitmap is never modified, and nothing is renamed. Returns the number of bytes written
+fn decode_to_writer<P: AsRef<Path>, W: Write>(path: P, output: &mut W, cancel: &AtomicBool) -> Result<u64> {
+    decode_reader_to_writer(& mut File::open(path.as_ref())?, output, cancel)
+}
+
+/// Decodes the bitmap read from `file`, writing the original file to `output`. Returns the number of bytes written
+fn decode_reader_to_writer<R: Read + Seek, W: Write>(file: &mut R, output: &mut W, cancel: &AtomicBool) -> Result<u64> {
+    let header: Header = bincode::deserialize_from(& mut *file)?;
+
+    header.validate_all(file.stream_len()?)?;
+
+    let mut written = 0u64;
+
+    read_payload(file, &header, DEFAULT_CHUNK_SIZE, cancel, |chunk| {
+        output.write_all(chunk)?;
+        written += chunk.len() as u64;
+        Ok(())
+    })?;
+
+    Ok(written)
+}
+
+/// Decodes the bitmap at `path` into memory, returning the original file without modifying the bitmap
+fn decode_to_vec<P: AsRef<Path>>(path: P, cancel: &AtomicBool) -> Result<Vec<u8>> {
+    let mut payload = Vec::new();
+
+    decode_to_writer(path, & mut payload, cancel)?;
+
+    Ok(payload)
+}
+
+/// Decodes two bitmaps into memory and compares the original files they contain. Returns `None` if they are
+/// identical, otherwise the offset of the first differing byte (or the length of the shorter file, if one is a
+/// prefix of the other)
+fn compare_bitmaps<P: AsRef<Path>, Q: AsRef<Path>>(first: P, second: Q, cancel: &AtomicBool) -> Result<Option<u64>> {
+    let first = decode_to_vec(first, cancel)?;
+    let second = decode_to_vec(second, cancel)?;
+
+    Ok(first_difference(&first, &second))
+}
+
+/// Decodes the bitmap at `path` into memory and compares it against the file at `original`. Returns `None` if the
+/// bitmap reproduces `original` exactly, otherwise the offset of the first differing byte. Neither file is modified
+fn verify_against<P: AsRef<Path>, Q: AsRef<Path>>(path: P, original: Q, cancel: &AtomicBool) -> Result<Option<u64>> {
+    let decoded = decode_to_vec(path, cancel)?;
+    let original = std::fs::read(original)?;
+
+    Ok(first_difference(&decoded, &original))
+}
+
+/// Offset of the first byte that differs between `first` and `second`, or `None` if they are equal
+fn first_difference(first: &[u8], second: &[u8]) -> Option<u64> {
+    match first.iter().zip(second.iter()).position(|(a, b)| a != b) {
+        Some(offset) => Some(offset as u64),
+        None if first.len() != second.len() => Some(first.len().min(second.len()) as u64),
+        None => None,
+    }
+}
+
+/// Computes the digest and CRC of the original file stored in the bitmap at `path`, and writes them into the
+/// header in place so the bitmap can be verified when converted back. The payload is not touched
+fn add_digest<P: AsRef<Path>>(path: P, cancel: &AtomicBool) -> Result<()> {
+    let mut file = File::open(path.as_ref())?;
+
+    let mut header: Header = bincode::deserialize_from(&file)?;
+
+    header.validate_all(file.stream_len()?)?;
+
+    let mut hash = Blake256::new();
+    let mut crc = Crc32::new();
+
+    read_payload(& mut file, &header, DEFAULT_CHUNK_SIZE, cancel, restrict_to_range(header.hash_range(), |chunk| {
+        hash.update(chunk);
+        crc.update(chunk);
+        Ok(())
+    }))?;
+
+    header.set_digests(Some((finalize_hash(hash), crc.finalize())));
+
+    drop(file);
+
+    update_header_in_place(path, &header, cancel)
+}
+
+/// Replaces the header of the b2b bitmap at `path` with `header`. This is shared by everything that only changes header
+/// fields, such as the stored digest. While the header region stays the same size, only the header is rewritten and
+/// the payload and padding are left untouched. The region is as large as the front of the original file kept at the
+/// end of the bitmap, so if `header` gives it a different size the payload has to move: the original file is written
+/// out again around the new header into a temporary file next to the bitmap, which then replaces it. `header` must
+/// describe a bitmap of the same original file. A stored bitmap digest covers the header, so it is brought up to date
+fn update_header_in_place<P: AsRef<Path>>(path: P, header: &Header, cancel: &AtomicBool) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(path.as_ref())?;
+
+    let current: Header = bincode::deserialize_from(&file)?;
+
+    if current.header_size() != header.header_size() {
+        current.validate_all(file.stream_len()?)?;
+
+        drop(file);
+
+        return rewrite_with_header(path.as_ref(), &current, header, cancel);
+    }
+
+    file.seek(SeekFrom::Start(0))?;
+
+    bincode::serialize_into(& mut file, header)?;
+
+    drop(file);
+
+    if header.bitmap_digest().is_some() {
+        seal_bitmap_file(path, cancel)?;
+    }
+
+    Ok(())
+}
+
+/// The fallback of `update_header_in_place` for a `header` whose header region is a different size to that of
+/// `current`, the header of the bitmap at `path`
+fn rewrite_with_header(path: &Path, current: &Header, header: &Header, cancel: &AtomicBool) -> Result<()> {
+    if header.original_file_size() != current.original_file_size() {
+        return Err(Error::new(ErrorKind::BadOriginalFileSize, format!("the new header is for a file of {} bytes, but the bitmap holds {} bytes", header.original_file_size(), current.original_file_size())));
+    }
+
+    header.validate_all((header.pixmap_size() + Header::bitmap_header_size()) as u64)?;
+
+    let temporary = bitmap_path(path, OsStr::new("tmp"));
+
+    let mut file = File::open(path)?;
+
+    let options = EncodeOptions::default();
+
+    //The payload is read straight from the bitmap in the row layout, other layouts are restored in memory first
+    if current.layout() == Layout::Row {
+        write_new_bitmap(& mut PayloadReader::new(& mut file, current), &temporary, header, &options, cancel, || Ok(()))?;
+    } else {
+        let restored = read_restored_bitmap(& mut file, current)?;
+
+        write_new_bitmap(& mut PayloadReader::new(Cursor::new(restored), current), &temporary, header, &options, cancel, || Ok(()))?;
+    }
+
+    drop(file);
+
+    let result = if header.bitmap_digest().is_some() {
+        seal_bitmap_file(&temporary, cancel)
+    } else {
+        Ok(())
+    }.and_then(|_| move_file(&temporary, path));
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+
+    result
+}
+
+/// Passes `chunk`, found at `position` in a bitmap, to `hash`, with any bytes of the stored bitmap digest zeroed
+fn hash_bitmap_chunk(hash: &mut Blake256, position: u64, chunk: &[u8]) {
+    let field = Header::bitmap_digest_range();
+    let end = position + chunk.len() as u64;
+
+    if position >= field.end || end <= field.start {
+        hash.update(chunk);
+    } else {
+        let mut chunk = chunk.to_vec();
+
+        let start = field.start.saturating_sub(position) as usize;
+        let stop = (field.end - position).min(chunk.len() as u64) as usize;
+
+        chunk[start..stop].fill(0);
+
+        hash.update(&chunk);
+    }
+}
+
+/// Digest of the whole bitmap at `path`, as stored by `--bitmap-digest`. Never zero, so it can't be mistaken for a
+/// bitmap without one
+fn get_bitmap_digest<P: AsRef<Path>>(path: P, cancel: &AtomicBool) -> Result<u128> {
+    let mut hash = Blake256::new();
+    let mut position = 0u64;
+
+    read_chunks(path, DEFAULT_CHUNK_SIZE, cancel, |chunk| {
+        hash_bitmap_chunk(& mut hash, position, chunk);
+        position += chunk.len() as u64;
+        Ok(())
+    })?;
+
+    Ok(finalize_hash(hash).max(1))
+}
+
+/// Stores the digest of the whole of `bitmap` in its header. It must be the last change made to the bitmap
+fn seal_bitmap(bitmap: &mut [u8]) {
+    let mut hash = Blake256::new();
+
+    hash_bitmap_chunk(& mut hash, 0, bitmap);
+
+    let field = Header::bitmap_digest_range();
+
+    bitmap[field.start as usize..field.end as usize].copy_from_slice(&finalize_hash(hash).max(1).to_le_bytes());
+}
+
+/// Stores the digest of the whole bitmap at `path` in its header, like `seal_bitmap` without reading it into memory
+fn seal_bitmap_file<P: AsRef<Path>>(path: P, cancel: &AtomicBool) -> Result<()> {
+    let digest = get_bitmap_digest(&path, cancel)?;
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .open(path.as_ref())?;
+
+    file.seek(SeekFrom::Start(Header::bitmap_digest_range().start))?;
+
+    file.write_all(&digest.to_le_bytes())?;
+
+    Ok(())
+}
+
+/// Checks the bitmap at `path` against the digest of the whole bitmap stored in its header, returning `None` if it
+/// doesn't have one. The header is not validated first, as any change to it should show up as a mismatch
+fn check_bitmap<P: AsRef<Path>>(path: P, cancel: &AtomicBool) -> Result<Option<bool>> {
+    let header: Header = bincode::deserialize_from(File::open(path.as_ref())?)?;
+
+    match header.bitmap_digest() {
+        Some(stored) => Ok(Some(stored == get_bitmap_digest(path, cancel)?)),
+        None => Ok(None),
+    }
+}
+
+/// Removes the digest and CRC from the bitmap at `path` by rewriting its header in place. The payload is not touched
+fn strip_digest<P: AsRef<Path>>(path: P, cancel: &AtomicBool) -> Result<()> {
+    let mut header = read_header(path.as_ref())?;
+
+    header.set_digests(None);
+
+    update_header_in_place(path, &header, cancel)
+}
+
+/// Copies `path` to a new file at `output`, `chunk_size` bytes at a time, then converts the copy, leaving the original
+/// untouched. Fails if `output` already exists, and removes `output` again if the conversion fails or is cancelled
+fn convert_copy<F>(path: &Path, output: &Path, chunk_size: usize, cancel: &AtomicBool, convert: F) -> Result<ConversionReport>
+    where F: FnOnce(&Path) -> Result<ConversionReport> {
+
+    convert_new_file(output, |destination| read_chunks(path, chunk_size, cancel, |chunk| {
+        destination.write_all(chunk)?;
+        Ok(())
+    }), convert)
+}
+
+/// Creates a new file at `output`, fills it using `write` then converts it. Fails if `output` already exists,
+/// and removes `output` again if writing or the conversion fails
+fn convert_new_file<W, F>(output: &Path, write: W, convert: F) -> Result<ConversionReport>
+    where W: FnOnce(&mut File) -> Result<()>, F: FnOnce(&Path) -> Result<ConversionReport> {
+
+    let copy_start = Instant::now();
+
+    let mut destination = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(output)?;
+
+    let result = write(& mut destination);
+
+    drop(destination);
+
+    let copy_time = copy_start.elapsed();
+
+    //Copying the file is part of the conversion's I/O
+    let result = result.and_then(|_| convert(output)).map(|report| ConversionReport {
+        io_time: report.io_time + copy_time,
+        ..report
+    });
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(output);
+    }
+
+    result
+}
+
+/// Name given to a file converted into a bitmap, i.e. `path` with `.` and `extension` appended
+fn bitmap_path(path: &Path, extension: &OsStr) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+
+    name.push(".");
+    name.push(extension);
+
+    PathBuf::from(name)
+}
+
+/// Suffix given to a bitmap without an extension when it is converted back, as removing the extension would leave its
+/// own name (see `binary_path`)
+const DECODED_SUFFIX: &str = "out";
+
+/// Name given to a bitmap converted back into a file, i.e. `path` without its extension. A bitmap without an extension
+/// has `.out` appended instead, so the file is not written over the bitmap
+fn binary_path(path: &Path) -> PathBuf {
+    let output = path.with_extension("");
+
+    if output == path {
+        bitmap_path(path, OsStr::new(DECODED_SUFFIX))
+    } else {
+        output
+    }
+}
+
+/// Parses a `START:END` byte range
+fn parse_range(range: &str) -> std::result::Result<Range<u64>, String> {
+    let mut parts = range.splitn(2, ':');
+
+    let start = parts.next().unwrap_or("").trim().parse::<u64>().map_err(|e| format!("Invalid start of range: {}", e))?;
+    let end = parts.next().ok_or_else(|| String::from("Range must be of the form START:END"))?.trim().parse::<u64>().map_err(|e| format!("Invalid end of range: {}", e))?;
+
+    if start > end {
+        Err(String::from("Start of range must not be after the end"))
+    } else {
+        Ok(start..end)
+    }
+}
+
+/// What `convert_file` does with files that were not created by b2b
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlainFiles {
+    /// Encodes them into bitmaps, except for ordinary bitmaps, which are refused as bitmaps that b2b can't decode
+    Encode,
+    /// Encodes them, unless they are ordinary bitmaps, which are reported and left alone
+    SkipBitmaps,
+    /// Reports them and leaves them alone, so only files created by b2b are converted
+    Skip,
+}
+
+/// Converts the file at `path` into a bitmap, or a bitmap created by b2b back into the original file, writing a new file
+/// unless `options` asks for an in-place conversion. Returns `None` without converting anything if `path` was not
+/// created by b2b and `plain_files` says to skip it
+fn convert_file(path: &Path, options: &EncodeOptions, extension: &OsStr, verify: Option<VerifyOptions>, plain_files: PlainFiles, cancel: &AtomicBool) -> Result<Option<ConversionReport>> {
+    if is_b2b_bitmap(path)? {
+        let output = binary_path(path);
+
+        if options.in_place() {
+            bmp_to_bin(path, output, verify, options.restore_on_error(), cancel).map(Some)
+        } else {
+            //Check the header before creating the output, so nothing is written for a corrupt bitmap
+            read_header(path)?;
+
+            convert_copy(path, &output, options.chunk_size(), cancel, |output| bmp_to_bin(output, output, verify, false, cancel)).map(Some)
+        }
+    } else if let Some(bitmap) = read_b2b_container(path)? {
+        let output = binary_path(path);
+
+        if options.in_place() {
+            std::fs::write(path, &bitmap)?;
+
+            bmp_to_bin(path, output, verify, options.restore_on_error(), cancel).map(Some)
+        } else {
+            convert_new_file(&output, |destination| Ok(destination.write_all(&bitmap)?), |output| bmp_to_bin(output, output, verify, false, cancel)).map(Some)
+        }
+    } else {
+        if plain_files == PlainFiles::Skip {
+            progress!("Skipped {}, it is not a B2B file.", path.display());
+
+            return Ok(None);
+        }
+
+        if is_bitmap(path)? {
+            if plain_files == PlainFiles::SkipBitmaps {
+                progress!("{} is not a B2B bitmap, leaving it untouched.", path.display());
+
+                return Ok(None);
+            }
+
+            return Err(Error::new(ErrorKind::InvalidB2BSignature, "pass --tolerate-non-b2b-bmp to skip bitmaps that were not created by b2b"));
+        }
+
+        encode_with_options(path, bitmap_path(path, extension), options, cancel).map(Some)
+    }
+}
+
+/// Whether `path` was created by b2b, as a bitmap, png or raw file, and so would be decoded rather than encoded
+fn is_b2b_file(path: &Path) -> Result<bool> {
+    Ok(is_b2b_bitmap(path)? || read_b2b_container(path)?.is_some())
+}
+
+/// Counts the b2b files among `files` without modifying any of them. With `verbose`, each file is listed as it is checked
+fn count_encoded(files: &[PathBuf], verbose: bool) -> EncodedCounts {
+    let mut counts = EncodedCounts::default();
+
+    for file in files {
+        match is_b2b_file(file) {
+            Ok(true) => {
+                counts.encoded += 1;
+
+                if verbose {
+                    println!("b2b    {}", file.display());
+                }
+            }
+            Ok(false) => {
+                counts.plain += 1;
+
+                if verbose {
+                    println!("plain  {}", file.display());
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to read {}: {}", file.display(), e);
+
+                counts.failures += 1;
+            }
+        }
+    }
+
+    counts
+}
+
+/// Writes the status of every file converted in a batch to `output` as a JSON array, so scripts can tell which files
+/// failed and why. `-` writes it to stdout
+fn write_statuses(output: &Path, statuses: &[FileStatus]) -> Result<()> {
+    let json = serde_json::to_string_pretty(statuses)?;
+
+    if output == Path::new("-") {
+        println!("{}", json);
+    } else {
+        std::fs::write(output, json + "\n")?;
+    }
+
+    Ok(())
+}
+
+/// Returns the value of `result`, or prints the error and exits with its exit code
+fn exit_on_error<T>(result: Result<T>) -> T {
+    match result {
+        Ok(value) => value,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+
+            std::process::exit(err.exit_code())
+        }
+    }
+}
+
+/// Parses a `START:LEN` byte span
+fn parse_span(span: &str) -> std::result::Result<(u64, u64), String> {
+    let mut parts = span.splitn(2, ':');
+
+    let start = parts.next().unwrap_or("").trim().parse::<u64>().map_err(|e| format!("Invalid start: {}", e))?;
+    let len = parts.next().ok_or_else(|| String::from("Span must be of the form START:LEN"))?.trim().parse::<u64>().map_err(|e| format!("Invalid length: {}", e))?;
+
+    Ok((start, len))
+}
+
+/// Parses bytes written as pairs of hex digits, such as `48656c6c6f`
+fn parse_hex(hex: &str) -> std::result::Result<Vec<u8>, String> {
+    let hex = hex.trim();
+
+    if !hex.len().is_multiple_of(2) {
+        return Err(String::from("Hex data must have an even number of digits"));
+    }
+
+    (0..hex.len()).step_by(2)
+        .map(|i| hex.get(i..i + 2)
+            .and_then(|pair| u8::from_str_radix(pair, 16).ok())
+            .ok_or_else(|| format!("Invalid hex digits at offset {}", i)))
+        .collect()
+}
+
+/// Parses the command line `args` with `app`. Invalid arguments are reported and exit with `EXIT_USAGE`, while `--help`
+/// and `--version` print and exit successfully
+fn matches_or_exit<I: IntoIterator<Item = T>, T: Into<OsString> + Clone>(app: App, args: I) -> clap::ArgMatches {
+    app.try_get_matches_from(args).unwrap_or_else(|e| {
+        if !e.use_stderr() {
+            e.exit();
+        }
+
+        //The message already ends in a newline
+        eprint!("{}", e);
+
+        std::process::exit(EXIT_USAGE)
+    })
+}
+
+fn main() {
+    let default_min_dimension = DEFAULT_MIN_DIMENSION.to_string();
+    let default_chunk_size = DEFAULT_CHUNK_SIZE.to_string();
+    let default_memory_limit = stream::DEFAULT_MEMORY_LIMIT.to_string();
+    let default_thumbnail_size = thumbnail::DEFAULT_THUMBNAIL_SIZE.to_string();
+    let default_tile_size = layout::DEFAULT_TILE_SIZE.to_string();
+
+    let app = App::new("B2B")
+        .version(crate_version!())
+        .author(crate_authors!())
+        .about(crate_description!())
+        .setting(AppSettings::AllowInvalidUtf8)
+        //Config file defaults are passed as arguments before the real ones, which then replace them
+        .setting(AppSettings::AllArgsOverrideSelf)
+        .arg(Arg::new("config")
+            .about("Reads defaults for options from this toml file instead of b2b.toml in the working directory. Options given on the command line take precedence")
+            .takes_value(true)
+            .value_name("FILE")
+            .required(false)
+            .long("config")
+        )
+        .arg(Arg::new("path")
+            .about("Path to a binary or bitmap file to convert. Converts files into bitmaps, and bitmaps created by b2b back into the original files. If this is a directory, every file in it and its subdirectories is converted")
+            .takes_value(true)
+            .required_unless_present_any(["watch", "stdin", "benchmark", "list-formats", "size", "data", "data-file"])
+            .validator_os(|path| {
+                let path = Path::new(path);
+
+                if path.is_dir() {
+                    Ok(())
+                } else if path.exists() {
+                    if OpenOptions::new()
+                        .read(true)
+                        .write(true)
+                        .open(path)
+                        .is_ok() {
+                        Ok(())
+                    } else {
+                        Err(String::from("Cannot read or write to specified file."))
+                    }
+                } else {
+                    Err(String::from("The specified path does not exist."))
+                }
+            }))
+        .arg(Arg::new("fast")
+            .about("Uses fast mode, which skips the verification stage")
+            .takes_value(false)
+            .required(false)
+            .short('f')
+            .long("fast")
+        )
+        .arg(Arg::new("canonical")
+            .about("Pads the headers to a whole 4 KiB block, so the file data is stored at the same block aligned offsets as in the file and bitmaps of similar files share more blocks. Useful for backups that deduplicate by block")
+            .takes_value(false)
+            .required(false)
+            .long("canonical")
+            .conflicts_with_all(&["layout", "shuffle"])
+        )
+        .arg(Arg::new("force")
+            .about("Converts the file even if the bitmap would be many times its size")
+            .takes_value(false)
+            .required(false)
+            .long("force")
+        )
+        .arg(Arg::new("in-place")
+            .about("Converts the file in place, modifying and renaming the original instead of creating a new file")
+            .takes_value(false)
+            .required(false)
+            .short('i')
+            .long("in-place")
+            .alias("overwrite-source")
+        )
+        .arg(Arg::new("restore-on-error")
+            .about("With --in-place, saves the start of each file before converting it, and puts it back if the conversion fails partway, so the file is left as it was instead of half converted. Layouts other than row save the whole file")
+            .takes_value(false)
+            .required(false)
+            .long("restore-on-error")
+            .alias("zero-on-error")
+        )
+        .arg(Arg::new("sparse")
+            .about("Leaves the padding of new bitmaps as a hole instead of writing or reserving it, on filesystems that support holes, to use less disk space. The bitmap reads back the same, but the padding is only allocated when written to, for example by a copy that doesn't keep holes")
+            .takes_value(false)
+            .required(false)
+            .long("sparse")
+            .conflicts_with_all(&["stdin", "data", "data-file"])
+        )
+        .arg(Arg::new("info")
+            .about("Prints the header of a bitmap created by b2b without converting it")
+            .takes_value(false)
+            .required(false)
+            .long("info")
+        )
+        .arg(Arg::new("bitmap-digest")
+            .about("Also stores a digest of the whole bitmap, after the layout has been applied, so that a change to any of its bytes can be detected with --check-bitmap. Only supported by the bmp container")
+            .takes_value(false)
+            .required(false)
+            .long("bitmap-digest")
+        )
+        .arg(Arg::new("check-bitmap")
+            .about("Checks a bitmap created with --bitmap-digest against the digest of the whole bitmap stored in it, without converting it. Fails if any byte of the bitmap has changed")
+            .takes_value(false)
+            .required(false)
+            .long("check-bitmap")
+            .conflicts_with_all(&["info", "inspect-pixels", "count", "reencode", "in-place"])
+        )
+        .arg(Arg::new("manifest")
+            .about("Checks the b2b bitmaps in a directory against a manifest of expected digests, one 'DIGEST NAME' line per file, without converting them. Reports bitmaps whose digest does not match and files in the manifest with no bitmap")
+            .takes_value(true)
+            .value_name("FILE")
+            .required(false)
+            .long("manifest")
+            .conflicts_with_all(&["info", "inspect-pixels", "check-bitmap", "count", "reencode", "in-place"])
+        )
+        .arg(Arg::new("inspect-pixels")
+            .about("Prints the Shannon entropy and byte histogram of the file stored in a bitmap created by b2b, without converting it. Compressed or encrypted files have an entropy close to 8 bits per byte")
+            .takes_value(false)
+            .required(false)
+            .long("inspect-pixels")
+            .conflicts_with_all(&["info", "count", "reencode", "in-place"])
+        )
+        .arg(Arg::new("thumbnail")
+            .about("When encoding a single file, also writes a small preview of the bitmap to a new bitmap at PATH, for browsing a collection. The b2b bitmap is not affected")
+            .takes_value(true)
+            .value_name("PATH")
+            .required(false)
+            .long("thumbnail")
+            .conflicts_with_all(&["info", "count", "reencode", "watch", "stdin"])
+        )
+        .arg(Arg::new("thumbnail-size")
+            .about("Largest width or height of the preview written by --thumbnail, in pixels")
+            .takes_value(true)
+            .value_name("PIXELS")
+            .required(false)
+            .long("thumbnail-size")
+            .default_value(&default_thumbnail_size)
+            .validator(|n| match n.parse::<u32>() {
+                Ok(0) => Err(String::from("Thumbnail size must be at least 1")),
+                Ok(_) => Ok(()),
+                Err(e) => Err(e.to_string()),
+            })
+        )
+        .arg(Arg::new("locate")
+            .about("With --info, prints where the LEN bytes of the original file starting at START are stored in the bitmap")
+            .takes_value(true)
+            .value_name("START:LEN")
+            .required(false)
+            .long("locate")
+            .requires("info")
+            .validator(|span| parse_span(span).map(|_| ()))
+        )
+        .arg(Arg::new("trailer-bytes")
+            .about("Prints the location of the trailer of a bitmap created by b2b (the first bytes of the original file, which are stored at the end) and a hexdump of its first N bytes, without converting it")
+            .takes_value(true)
+            .value_name("N")
+            .required(false)
+            .long("trailer-bytes")
+            .conflicts_with_all(&["strip-digest", "add-digest"])
+            .validator(|n| n.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(Arg::new("hexdump-header")
+            .about("Prints the bitmap and b2b headers at the start of the file as hex, one field to a line, followed by the rest of the header region the header declares, without converting it")
+            .takes_value(false)
+            .required(false)
+            .long("hexdump-header")
+            .conflicts_with_all(&["strip-digest", "add-digest", "trailer-bytes"])
+        )
+        .arg(Arg::new("json")
+            .about("With --info, prints the header as JSON")
+            .takes_value(false)
+            .required(false)
+            .long("json")
+            .requires("info")
+        )
+        .arg(Arg::new("recursive")
+            .about("With --info and a directory, prints the headers of every b2b bitmap in the directory and its subdirectories, skipping other files")
+            .takes_value(false)
+            .required(false)
+            .long("recursive")
+            .alias("all")
+            .requires("info")
+        )
+        .arg(Arg::new("ascii-preview")
+            .about("With --info, draws the shape of the bitmap and the proportion of it taken up by padding")
+            .takes_value(false)
+            .required(false)
+            .long("ascii-preview")
+            .requires("info")
+        )
+        .arg(Arg::new("strip-digest")
+            .about("Removes the stored digest from a bitmap created by b2b, without converting it. The bitmap can no longer be verified")
+            .takes_value(false)
+            .required(false)
+            .long("strip-digest")
+            .conflicts_with("info")
+        )
+        .arg(Arg::new("add-digest")
+            .about("Computes and stores a digest in a bitmap created by b2b without one, without converting it")
+            .takes_value(false)
+            .required(false)
+            .long("add-digest")
+            .conflicts_with_all(&["info", "strip-digest"])
+        )
+        .arg(Arg::new("tolerate-non-b2b-bmp")
+            .about("If the file is an ordinary bitmap that was not created by b2b, reports it and exits successfully instead of failing. The file is never modified")
+            .takes_value(false)
+            .required(false)
+            .long("tolerate-non-b2b-bmp")
+        )
+        .arg(Arg::new("decode-all")
+            .about("Only converts files created by b2b back into the original files. Anything else, including ordinary bitmaps, is reported and left untouched, so this is safe to point at a directory of other images")
+            .takes_value(false)
+            .required(false)
+            .long("decode-all")
+            .alias("decode-only")
+            .conflicts_with_all(&["watch", "reencode", "stdin"])
+        )
+        .arg(Arg::new("ext")
+            .about("Extension appended to files converted into bitmaps. Bitmaps are recognised by their contents, so any extension can be converted back")
+            .takes_value(true)
+            .value_name("EXT")
+            .required(false)
+            .long("ext")
+            .default_value("bmp")
+        )
+        .arg(Arg::new("layout")
+            .about("How the file data is arranged across the pixels of the bitmap. Layouts other than row are rearranged in memory, so need enough memory to hold the whole file")
+            .takes_value(true)
+            .required(false)
+            .long("layout")
+            .alias("pack")
+            .validator(|name| name.parse::<Layout>().map(|_| ()).map_err(|e| e.to_string()))
+            .default_value("row")
+        )
+        .arg(Arg::new("tile-size")
+            .about("Side of the square tiles of the tiled layout, in pixels. The size is stored in the bitmap, so converting back doesn't need it")
+            .takes_value(true)
+            .value_name("PIXELS")
+            .required(false)
+            .long("tile-size")
+            .default_value(&default_tile_size)
+            .validator(|n| match n.parse::<u32>() {
+                Ok(0) => Err(String::from("Tile size must be at least 1")),
+                Ok(_) => Ok(()),
+                Err(e) => Err(e.to_string()),
+            })
+        )
+        .arg(Arg::new("container")
+            .about("File format to store the bitmap in. A png holds exactly the same pixels as the bitmap, without compression, and is converted back the same way. The extension defaults to png. A raw file is the bitmap without its bitmap header (the b2b header, file data and padding), with the extension b2b, and only supports the row layout")
+            .takes_value(true)
+            .required(false)
+            .long("container")
+            .alias("output-format")
+            .validator(|name| name.parse::<Container>().map(|_| ()).map_err(|e| e.to_string()))
+            .default_value("bmp")
+        )
+        .arg(Arg::new("shuffle")
+            .about("Scatters the file data across the bitmap in a pseudo-random order generated from SEED, so that it looks like noise. The seed is stored in the bitmap and the order is reversed automatically when converting back. This is not encryption")
+            .takes_value(true)
+            .value_name("SEED")
+            .required(false)
+            .long("shuffle")
+            .validator(|seed| seed.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+            .conflicts_with("seed")
+        )
+        .arg(Arg::new("seed")
+            .about("Seed of the pseudo-random features, currently the shuffled layout, stored in the bitmap so converting back reverses them. The same seed always gives the same bitmap. Without a seed, one is derived from the digest of the file")
+            .takes_value(true)
+            .value_name("SEED")
+            .required(false)
+            .long("seed")
+            .validator(|seed| seed.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(Arg::new("honor-offset")
+            .about("Points the pixel offset in the bitmap header past the b2b header, so viewers that honour it only draw the file data. Only supported by the bmp container")
+            .takes_value(false)
+            .required(false)
+            .long("honor-offset")
+        )
+        .arg(Arg::new("reencode")
+            .about("Decodes a bitmap or png created by b2b in memory and encodes the file again using the options given (layout, shuffle, container and so on), replacing the bitmap. The file is never written to disk")
+            .takes_value(false)
+            .required(false)
+            .long("reencode")
+            .conflicts_with_all(&["info", "strip-digest", "add-digest", "in-place"])
+        )
+        .arg(Arg::new("count")
+            .about("Counts how many files are already B2B bitmaps, pngs or raw files, and how many are not, without converting anything. Honours --include and --exclude for directories")
+            .takes_value(false)
+            .required(false)
+            .long("count")
+            .conflicts_with_all(&["info", "strip-digest", "add-digest", "reencode", "in-place", "compare", "verify-against", "pixmap-only", "recover-headerless", "recover-truncated"])
+        )
+        .arg(Arg::new("benchmark")
+            .about("Converts in-memory buffers of several sizes to bitmaps and back, using the options given (layout, chunk size and so on), and prints the encode, decode and hash throughput. Nothing is read from or written to disk")
+            .takes_value(false)
+            .required(false)
+            .long("benchmark")
+            .conflicts_with_all(&["path", "watch", "stdin"])
+        )
+        .arg(Arg::new("compute-only")
+            .about("Prints the header that encoding the file with the options given would write, as JSON, then exits. Only the size of the file is used, its contents are not read, so the header has no digest")
+            .takes_value(false)
+            .required(false)
+            .long("compute-only")
+            .conflicts_with_all(&["info", "watch", "stdin", "benchmark", "data", "data-file"])
+        )
+        .arg(Arg::new("size")
+            .about("With --compute-only, computes the header for a file of N bytes instead of the file given")
+            .takes_value(true)
+            .value_name("N")
+            .required(false)
+            .long("size")
+            .requires("compute-only")
+            .conflicts_with("path")
+            .validator(|n| n.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(Arg::new("list-formats")
+            .about("Prints the header format, containers and layouts this build can encode and decode, and which optional features it was built with")
+            .takes_value(false)
+            .required(false)
+            .long("list-formats")
+            .conflicts_with_all(&["path", "watch", "stdin", "benchmark"])
+        )
+        .arg(Arg::new("verbose")
+            .about("With --count, lists every file and whether it is a B2B file")
+            .takes_value(false)
+            .required(false)
+            .long("verbose")
+            .requires("count")
+        )
+        .arg(Arg::new("compare")
+            .about("Decodes this bitmap and the bitmap at path in memory, and reports whether the files they contain are identical. Neither bitmap is modified")
+            .takes_value(true)
+            .value_name("OTHER")
+            .required(false)
+            .long("compare")
+        )
+        .arg(Arg::new("verify-against")
+            .about("Decodes the bitmap at path in memory, and reports whether it reproduces ORIGINAL exactly. Neither file is modified")
+            .takes_value(true)
+            .value_name("ORIGINAL")
+            .required(false)
+            .long("verify-against")
+            .conflicts_with("compare")
+        )
+        .arg(Arg::new("pixmap-only")
+            .about("Writes the raw pixmap of a bitmap created by b2b (everything after the bitmap header, including the b2b header and padding) to OUTPUT, without converting it")
+            .takes_value(true)
+            .value_name("OUTPUT")
+            .required(false)
+            .long("pixmap-only")
+            .conflicts_with_all(&["info", "strip-digest", "add-digest", "compare", "verify-against"])
+        )
+        .arg(Arg::new("recover-headerless")
+            .about("Recovers the original file from a bitmap with a damaged bitmap header, or from a raw pixmap (see --pixmap-only), writing it to OUTPUT. The input is not modified")
+            .takes_value(true)
+            .value_name("OUTPUT")
+            .required(false)
+            .long("recover-headerless")
+            .conflicts_with_all(&["info", "strip-digest", "add-digest", "compare", "verify-against", "pixmap-only", "recover-truncated"])
+        )
+        .arg(Arg::new("trim")
+            .about("With --recover-headerless, if the b2b header is damaged too, guesses the size of the original by stripping trailing zeros. Any zeros at the end of the header region (the first bytes of the original, which are stored after the rest of it) are lost or misplaced")
+            .takes_value(false)
+            .required(false)
+            .long("trim")
+            .requires("recover-headerless")
+        )
+        .arg(Arg::new("recover-truncated")
+            .about("Recovers what remains of the original file from a bitmap created by b2b whose end has been cut off. Lost bytes are replaced with zeros and reported. The bitmap is not modified")
+            .takes_value(false)
+            .required(false)
+            .long("recover-truncated")
+            .conflicts_with_all(&["info", "strip-digest", "add-digest", "compare", "verify-against", "pixmap-only"])
+        )
+        .arg(Arg::new("hash-range")
+            .about("Only includes the bytes from START up to (but not including) END in the digest, so changes outside this range are not detected by verification")
+            .takes_value(true)
+            .value_name("START:END")
+            .required(false)
+            .long("hash-range")
+            .validator(|range| parse_range(range).map(|_| ()))
+        )
+        .arg(Arg::new("min-dimension")
+            .about("Smallest width and height of the bitmap in pixels. Small files are padded out to this size")
+            .takes_value(true)
+            .value_name("N")
+            .required(false)
+            .long("min-dimension")
+            .default_value(&default_min_dimension)
+            .validator(|n| match n.parse::<u32>() {
+                Ok(0) => Err(String::from("Minimum dimension must be at least 1")),
+                Ok(_) => Ok(()),
+                Err(e) => Err(e.to_string()),
+            })
+        )
+        .arg(Arg::new("max-dimension")
+            .about("Largest width and height of the bitmap in pixels, for viewers that reject larger images. Fails if the file doesn't fit in a bitmap this size")
+            .takes_value(true)
+            .value_name("N")
+            .required(false)
+            .long("max-dimension")
+            .validator(|n| match n.parse::<u32>() {
+                Ok(0) => Err(String::from("Maximum dimension must be at least 1")),
+                Ok(_) => Ok(()),
+                Err(e) => Err(e.to_string()),
+            })
+        )
+        .arg(Arg::new("chunk-size")
+            .about("Size in bytes of the buffer used when copying file data into a new file. Larger chunks can be faster, smaller chunks use less memory")
+            .takes_value(true)
+            .value_name("BYTES")
+            .required(false)
+            .long("chunk-size")
+            .default_value(&default_chunk_size)
+            .validator(|n| match n.parse::<usize>() {
+                Ok(0) => Err(String::from("Chunk size must be at least 1")),
+                Ok(_) => Ok(()),
+                Err(e) => Err(e.to_string()),
+            })
+        )
+        .arg(Arg::new("report")
+            .about("Prints the sizes, timings and throughput of the conversion")
+            .takes_value(false)
+            .required(false)
+            .long("report")
+        )
+        .arg(Arg::new("collect-errors")
+            .about("When converting a directory, writes a JSON array to FILE with an object for each file giving its path, the direction it was converted in, whether it succeeded, and if not the error and the exit code it would give on its own. Pass - to write it to stdout, which sends the progress printed while converting to stderr")
+            .takes_value(true)
+            .value_name("FILE")
+            .required(false)
+            .long("collect-errors")
+        )
+        .arg(Arg::new("retry")
+            .about("When converting a directory, tries a file again up to N times, waiting a little longer each time, if converting it fails with an error that may be temporary, such as a busy file or a network timeout. Corrupt files are not retried")
+            .takes_value(true)
+            .value_name("N")
+            .required(false)
+            .long("retry")
+            .default_value("0")
+            .validator(|n| n.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(Arg::new("stats")
+            .about("When converting a directory, prints the total number of files and bytes converted, the overall expansion and the number of failures")
+            .takes_value(false)
+            .required(false)
+            .long("stats")
+        )
+        .arg(Arg::new("quick-verify")
+            .about("When converting a bitmap back to binary, verifies the output using the stored CRC32 instead of the full digest. Much faster for large files, but a weaker check")
+            .takes_value(false)
+            .required(false)
+            .short('q')
+            .long("quick-verify")
+        )
+        .arg(Arg::new("strict")
+            .about("Treats a bitmap that can't be verified, as it has no digest, as a failed verification, exiting with a nonzero code. By default this only prints a warning")
+            .takes_value(false)
+            .required(false)
+            .long("strict")
+            .conflicts_with("fast")
+        )
+        .arg(Arg::new("include")
+            .about("When converting a directory, only converts files matching this glob. Globs without a / are matched against the file name, others against the path relative to the directory. Can be given more than once")
+            .takes_value(true)
+            .value_name("GLOB")
+            .required(false)
+            .long("include")
+            .multiple_occurrences(true)
+            .validator(|pattern| glob::Glob::new(pattern).map(|_| ()))
+        )
+        .arg(Arg::new("exclude")
+            .about("When converting a directory, skips files matching this glob, even if they match --include. Can be given more than once")
+            .takes_value(true)
+            .value_name("GLOB")
+            .required(false)
+            .long("exclude")
+            .multiple_occurrences(true)
+            .validator(|pattern| glob::Glob::new(pattern).map(|_| ()))
+        )
+        .arg(Arg::new("watch")
+            .about("Watches a directory and converts files into bitmaps as they are added to it, once they have stopped changing. Files already in the directory and B2B bitmaps are ignored")
+            .takes_value(true)
+            .value_name("DIR")
+            .required(false)
+            .long("watch")
+            .alias("follow")
+            .conflicts_with_all(&["path", "info", "strip-digest", "add-digest", "compare", "verify-against", "pixmap-only", "recover-truncated"])
+        )
+        .arg(Arg::new("stdin")
+            .about("Reads the file to convert from stdin instead of a path, and writes the result to stdout. B2B bitmaps, pngs and raw files are converted back, anything else is converted into a bitmap. Requires --stdout")
+            .takes_value(false)
+            .required(false)
+            .long("stdin")
+            .requires("stdout")
+            .conflicts_with_all(&["path", "watch", "in-place"])
+        )
+        .arg(Arg::new("stdout")
+            .about("Writes the converted file to stdout. Requires --stdin")
+            .takes_value(false)
+            .required(false)
+            .long("stdout")
+            .requires("stdin")
+        )
+        .arg(Arg::new("data")
+            .about("Encodes the bytes given as HEX (pairs of hex digits) and writes the bitmap to stdout, as if they were the contents of a file. Useful for making small test bitmaps")
+            .takes_value(true)
+            .value_name("HEX")
+            .required(false)
+            .long("data")
+            .validator(|hex| parse_hex(hex).map(|_| ()))
+            .conflicts_with_all(&["path", "watch", "stdin", "data-file", "in-place"])
+        )
+        .arg(Arg::new("data-file")
+            .about("Encodes the contents of FILE in memory and writes the bitmap to stdout, whatever the file holds. Unlike a path, b2b files are encoded again rather than converted back")
+            .takes_value(true)
+            .value_name("FILE")
+            .required(false)
+            .long("data-file")
+            .conflicts_with_all(&["path", "watch", "stdin", "in-place"])
+        )
+        .arg(Arg::new("profile-memory")
+            .about("Prints the most memory used at once to stderr after converting, to check that large files are not read into memory whole. The heap allocations are only counted if b2b was built with the profile-memory feature")
+            .takes_value(false)
+            .required(false)
+            .long("profile-memory")
+        )
+        .arg(Arg::new("memory-limit")
+            .about("With --stdin, inputs up to this many bytes are converted in memory. Larger inputs are written to a temporary file first")
+            .takes_value(true)
+            .value_name("BYTES")
+            .required(false)
+            .long("memory-limit")
+            .default_value(&default_memory_limit)
+            .validator(|n| n.parse::<u64>().map(|_| ()).map_err(|e| e.to_string()))
+        )
+        .arg(Arg::new("watch-output")
+            .about("With --watch, directory that converted bitmaps are written to. Defaults to the watched directory")
+            .takes_value(true)
+            .value_name("DIR")
+            .required(false)
+            .long("watch-output")
+            .requires("watch")
+        );
+
+    let args: Vec<OsString> = std::env::args_os().collect();
+
+    let matches = matches_or_exit(app.clone(), &args);
+
+    let config = match matches.value_of_os("config") {
+        Some(path) => Some(exit_on_error(Config::load(path))),
+        None if Path::new(DEFAULT_CONFIG_FILE).is_file() => Some(exit_on_error(Config::load(DEFAULT_CONFIG_FILE))),
+        None => None,
+    };
+
+    //The config only gives defaults, for the options not given on the command line and not conflicting with them
+    let matches = match config {
+        Some(config) => {
+            let defaults = config.to_args(|key| matches.occurrences_of(key) > 0);
+
+            matches_or_exit(app, args.iter().take(1).cloned().chain(defaults).chain(args.iter().skip(1).cloned()))
+        }
+        None => matches,
+    };
+
+    exit_on_error(header::check_header_constants());
+
+    let strict = matches.is_present("strict");
+
+    let verify = if matches.is_present("fast") {
+        None
+    } else {
+        Some(VerifyOptions::default()
+            .with_quick(matches.is_present("quick-verify"))
+            .with_strict(strict))
+    };
+
+    let in_place = matches.is_present("in-place");
+
+    //--shuffle SEED is short for --layout shuffled --seed SEED
+    let seed = matches.value_of("shuffle").or_else(|| matches.value_of("seed")).map(|seed| seed.parse::<u64>().unwrap());
+
+    let layout = if matches.is_present("shuffle") {
+        Layout::Shuffled
+    } else {
+        matches.value_of("layout").unwrap().parse::<Layout>().unwrap()
+    };
+
+    let container = matches.value_of("container").unwrap().parse::<Container>().unwrap();
+
+    //Unless an extension is given, name pngs and raw files after their format
+    let extension = match container {
+        Container::Png if matches.occurrences_of("ext") == 0 => OsStr::new("png"),
+        Container::Raw if matches.occurrences_of("ext") == 0 => OsStr::new("b2b"),
+        _ => matches.value_of_os("ext").unwrap(),
+    };
+
+    if container != Container::Bitmap && matches.is_present("bitmap-digest") {
+        eprintln!("error: --bitmap-digest is only supported by the bmp container, as pngs and raw files don't keep the bitmap header");
+
+        std::process::exit(EXIT_USAGE);
+    }
+
+    if container != Container::Bitmap && matches.is_present("honor-offset") {
+        eprintln!("error: --honor-offset is only supported by the bmp container, as pngs and raw files don't keep the bitmap header");
+
+        std::process::exit(EXIT_USAGE);
+    }
+
+    if container == Container::Raw && layout != Layout::Row {
+        eprintln!("error: The raw container only supports the row layout, as it doesn't keep the width of the bitmap");
+
+        std::process::exit(EXIT_USAGE);
+    }
+
+    let encode_options = EncodeOptions::default()
+        .with_digest(verify.is_some())
+        .with_layout(layout)
+        .with_seed(seed)
+        .with_hash_range(matches.value_of("hash-range").map(|range| parse_range(range).unwrap()))
+        .with_min_dimension(matches.value_of("min-dimension").unwrap().parse::<u32>().unwrap())
+        .with_max_dimension(matches.value_of("max-dimension").map(|n| n.parse::<u32>().unwrap()))
+        .with_in_place(in_place)
+        .with_container(container)
+        .with_bitmap_digest(matches.is_present("bitmap-digest"))
+        .with_restore_on_error(matches.is_present("restore-on-error"))
+        .with_sparse(matches.is_present("sparse"))
+        .with_tile_size(matches.value_of("tile-size").unwrap().parse::<u32>().unwrap())
+        .with_honor_offset(matches.is_present("honor-offset"))
+        .with_force(matches.is_present("force"))
+        .with_canonical(matches.is_present("canonical"))
+        .with_chunk_size(matches.value_of("chunk-size").unwrap().parse::<usize>().unwrap());
+
+    //Never set by the command line tool, but threaded through so conversions can be cancelled
+    let cancel = AtomicBool::new(false);
+
+    if let Some(dir) = matches.value_of_os("watch") {
+        let dir = Path::new(dir);
+        let output_dir = Path::new(matches.value_of_os("watch-output").unwrap_or(dir.as_os_str()));
+        let mut watcher = exit_on_error(watch::Watcher::new(dir));
+
+        exit_on_error(watcher.run(&cancel, |path| {
+            //Our own output, if bitmaps are written to the watched directory
+            if is_b2b_bitmap(path).unwrap_or(true) || !matches!(read_b2b_container(path), Ok(None)) {
+                return;
+            }
+
+            let output = bitmap_path(&output_dir.join(path.file_name().unwrap()), extension);
+
+            match encode_with_options(path, &output, &encode_options, &cancel) {
+                Ok(report) => {
+                    println!("Converted {} to {}", path.display(), output.display());
+
+                    if matches.is_present("report") {
+                        println!("{}", report);
+                    }
+                }
+                Err(e) => eprintln!("Failed to convert {}: {}", path.display(), e),
+            }
+        }));
+
+        return;
+    }
+
+    if matches.is_present("compute-only") {
+        let file_size = match matches.value_of("size") {
+            Some(size) => size.parse::<u64>().unwrap(),
+            None => exit_on_error(std::fs::metadata(matches.value_of_os("path").unwrap()).map_err(Error::from)).len(),
+        };
+
+        println!("{}", exit_on_error(compute_header(file_size, &encode_options).and_then(|header| header.to_json())));
+
+        return;
+    }
+
+    if matches.is_present("list-formats") {
+        info::print_formats();
+
+        return;
+    }
+
+    if matches.is_present("benchmark") {
+        benchmark::print_results(&exit_on_error(benchmark::run(&encode_options, &cancel)));
+
+        return;
+    }
+
+    let profile_memory = matches.is_present("profile-memory");
+
+    if matches.is_present("data") || matches.is_present("data-file") {
+        let data = match matches.value_of("data") {
+            Some(hex) => parse_hex(hex).unwrap(),
+            None => exit_on_error(std::fs::read(matches.value_of_os("data-file").unwrap()).map_err(Error::from)),
+        };
+
+        let stdout = std::io::stdout();
+        let mut stdout = stdout.lock();
+
+        exit_on_error(stream::encode_bytes(&data, & mut stdout, &encode_options, &cancel));
+        exit_on_error(stdout.flush().map_err(Error::from));
+
+        if profile_memory {
+            memory::print_profile();
+        }
+
+        return;
+    }
+
+    if matches.is_present("stdin") {
+        let memory_limit = matches.value_of("memory-limit").unwrap().parse::<u64>().unwrap();
+
+        let stdout = std::io::stdout();
+
+        exit_on_error(stream::convert_stream(& mut std::io::stdin().lock(), & mut stdout.lock(), &encode_options, verify, memory_limit, &cancel));
+
+        if profile_memory {
+            memory::print_profile();
+        }
+
+        return;
+    }
+
+    let path = Path::new(matches.value_of_os("path").unwrap());
+
+    if matches.is_present("check-bitmap") {
+        match exit_on_error(check_bitmap(path, &cancel)) {
+            Some(true) => println!("Bitmap digest matches, the bitmap has not been modified."),
+            Some(false) => {
+                println!("Bitmap digest does not match, the bitmap has been modified.");
+
+                std::process::exit(Error::new(ErrorKind::VerificationFailed, "").exit_code());
+            }
+            None => {
+                println!("Unable to check as bitmap doesn't contain a bitmap digest. Pass --bitmap-digest when converting to store one.");
+
+                if strict {
+                    std::process::exit(Error::new(ErrorKind::VerificationFailed, "").exit_code());
+                }
+            }
+        }
+
+        return;
+    }
+
+    if matches.is_present("inspect-pixels") {
+        println!("{}", exit_on_error(inspect_payload(path, &cancel)));
+
+        return;
+    }
+
+    let globs = |name| matches.values_of(name).into_iter().flatten().map(|pattern| glob::Glob::new(pattern).unwrap()).collect();
+
+    let filter = batch::Filter::new(globs("include"), globs("exclude"));
+
+    if let Some(manifest) = matches.value_of_os("manifest") {
+        let manifest = exit_on_error(Manifest::load(manifest));
+
+        let counts = exit_on_error(check_manifest(path, &manifest, &filter, &cancel));
+
+        println!();
+        println!("{}", counts);
+
+        if !counts.passed() {
+            std::process::exit(Error::new(ErrorKind::VerificationFailed, "").exit_code());
+        }
+
+        return;
+    }
+
+    if matches.is_present("info") && matches.is_present("recursive") && path.is_dir() {
+        let headers = exit_on_error(read_headers(path, &filter));
+
+        if matches.is_present("json") {
+            println!("{}", exit_on_error(info::headers_to_json(&headers)));
+        } else {
+            info::print_header_table(&headers);
+        }
+
+        return;
+    }
+
+    if matches.is_present("info") {
+        let header = exit_on_error(read_header(path));
+
+        if matches.is_present("json") {
+            println!("{}", exit_on_error(header.to_json()));
+        } else {
+            info::print_info(&header);
+        }
+
+        if matches.is_present("ascii-preview") {
+            println!();
+            print!("{}", info::ascii_preview(&header));
+        }
+
+        if let Some(span) = matches.value_of("locate") {
+            let (start, len) = parse_span(span).unwrap();
+
+            println!();
+
+            for (offset, length) in header.map_range(start, len) {
+                println!("{} bytes at bitmap offset {} ({:#x})", length, offset, offset);
+            }
+        }
+
+        return;
+    }
+
+    if matches.is_present("hexdump-header") {
+        let bytes = exit_on_error(read_header_region(path));
+
+        print!("{}", info::hexdump_header(&bytes));
+
+        return;
+    }
+
+    if let Some(count) = matches.value_of("trailer-bytes") {
+        let (offset, length, trailer) = exit_on_error(read_trailer(path, count.parse::<u64>().unwrap()));
+
+        println!("Trailer: {} bytes at bitmap offset {} ({:#x})", length, offset, offset);
+        print!("{}", info::hexdump(&trailer, 0));
+
+        return;
+    }
+
+    if matches.is_present("strip-digest") {
+        exit_on_error(strip_digest(path, &cancel));
+
+        return;
+    }
+
+    if let Some(other) = matches.value_of_os("compare") {
+        match exit_on_error(compare_bitmaps(path, other, &cancel)) {
+            None => println!("Payloads are identical."),
+            Some(offset) => println!("Payloads differ at offset {}.", offset),
+        }
+
+        return;
+    }
+
+    if let Some(original) = matches.value_of_os("verify-against") {
+        match exit_on_error(verify_against(path, original, &cancel)) {
+            None => println!("Bitmap reproduces {}.", Path::new(original).display()),
+            Some(offset) => println!("Bitmap differs from {} at offset {}.", Path::new(original).display(), offset),
+        }
+
+        return;
+    }
+
+    if let Some(output) = matches.value_of_os("pixmap-only") {
+        let written = exit_on_error(export_pixmap(path, output, &cancel));
+
+        println!("Wrote {} bytes of pixmap to {}.", written, Path::new(output).display());
+
+        return;
+    }
+
+    if matches.is_present("count") {
+        let files = if path.is_dir() {
+            exit_on_error(batch::files_in(path, &filter))
+        } else {
+            vec![path.to_path_buf()]
+        };
+
+        let counts = count_encoded(&files, matches.is_present("verbose"));
+
+        println!("{}", counts);
+
+        if counts.failures > 0 {
+            std::process::exit(EXIT_IO);
+        }
+
+        return;
+    }
+
+    if matches.is_present("reencode") {
+        let output = bitmap_path(&binary_path(path), extension);
+
+        let report = exit_on_error(reencode(path, &output, &encode_options, &cancel));
+
+        println!("Re-encoded {} as {}.", path.display(), output.display());
+
+        if matches.is_present("report") {
+            println!("{}", report);
+        }
+
+        return;
+    }
+
+    if let Some(output) = matches.value_of_os("recover-headerless") {
+        let length = exit_on_error(recover_headerless(path, output, matches.is_present("trim")));
+
+        println!("Recovered {} bytes to {}.", length, Path::new(output).display());
+
+        return;
+    }
+
+    if matches.is_present("recover-truncated") {
+        let output = binary_path(path);
+
+        let lost = exit_on_error(recover_truncated(path, &output, &cancel));
+
+        if lost.is_empty() {
+            println!("Recovered the whole original file to {}.", output.display());
+        } else {
+            println!("Recovered {}, but bytes {} to {} of the original were lost and have been replaced with zeros.", output.display(), lost.start, lost.end);
+
+            std::process::exit(Error::new(ErrorKind::BadFileSize, "").exit_code());
+        }
+
+        return;
+    }
+
+    if matches.is_present("add-digest") {
+        exit_on_error(add_digest(path, &cancel));
+
+        return;
+    }
+
+    let plain_files = if matches.is_present("decode-all") {
+        PlainFiles::Skip
+    } else if matches.is_present("tolerate-non-b2b-bmp") {
+        PlainFiles::SkipBitmaps
+    } else {
+        PlainFiles::Encode
+    };
+
+    let thumbnail = matches.value_of_os("thumbnail").map(Path::new);
+
+    if thumbnail.is_some() && (path.is_dir() || exit_on_error(is_b2b_file(path))) {
+        eprintln!("error: --thumbnail is only supported when encoding a single file");
+
+        std::process::exit(EXIT_USAGE);
+    }
+
+    if path.is_dir() {
+        let retries = matches.value_of("retry").unwrap().parse::<u32>().unwrap();
+        let mut stats = BatchStats::default();
+        let mut statuses = Vec::new();
+        let mut exit_code = None;
+
+        if matches.value_of_os("collect-errors") == Some(OsStr::new("-")) {
+            PROGRESS_TO_STDERR.store(true, Ordering::Relaxed);
+        }
+
+        for file in exit_on_error(batch::files_in(path, &filter)) {
+            let direction = if is_b2b_file(&file).unwrap_or(false) { Direction::Decode } else { Direction::Encode };
+
+            let status = match retry_transient(&file, retries, || convert_file(&file, &encode_options, extension, verify, plain_files, &cancel)) {
+                Ok(Some(report)) => {
+                    progress!("Converted {}", file.display());
+
+                    if matches.is_present("report") {
+                        progress!("{}", report);
+                    }
+
+                    stats.add(&report, strict);
+
+                    if report.failed_verification(strict) {
+                        let error = Error::new(ErrorKind::VerificationFailed, "");
+
+                        FileStatus { path: file.display().to_string(), direction, ok: false, error: Some(error.to_string()), exit_code_hint: Some(error.exit_code()) }
+                    } else {
+                        FileStatus { path: file.display().to_string(), direction, ok: true, error: None, exit_code_hint: None }
+                    }
+                }
+                Ok(None) => {
+                    stats.skipped += 1;
+
+                    FileStatus { path: file.display().to_string(), direction: Direction::Skip, ok: true, error: None, exit_code_hint: None }
+                }
+                Err(e) => {
+                    eprintln!("Failed to convert {}: {}", file.display(), e);
+
+                    stats.failures += 1;
+                    exit_code.get_or_insert(e.exit_code());
+
+                    FileStatus { path: file.display().to_string(), direction, ok: false, error: Some(e.to_string()), exit_code_hint: Some(e.exit_code()) }
+                }
+            };
+
+            statuses.push(status);
+        }
+
+        if matches.is_present("stats") {
+            progress!("{}", stats);
+        }
+
+        if profile_memory {
+            memory::print_profile();
+        }
+
+        if let Some(output) = matches.value_of_os("collect-errors") {
+            exit_on_error(write_statuses(Path::new(output), &statuses));
+        }
+
+        if stats.verification_failures > 0 {
+            exit_code.get_or_insert(Error::new(ErrorKind::VerificationFailed, "").exit_code());
+        }
+
+        if let Some(code) = exit_code {
+            std::process::exit(code);
+        }
+
+        return;
+    }
+
+    let report = match exit_on_error(convert_file(path, &encode_options, extension, verify, plain_files, &cancel)) {
+        Some(report) => report,
+        None => return,
+    };
+
+    if matches.is_present("report") {
+        println!("{}", report);
+    }
+
+    if profile_memory {
+        memory::print_profile();
+    }
+
+    if let Some(thumbnail) = thumbnail {
+        let max_dimension = matches.value_of("thumbnail-size").unwrap().parse::<u32>().unwrap();
+
+        let (width, height) = exit_on_error(write_thumbnail(&bitmap_path(path, extension), thumbnail, max_dimension));
+
+        println!("Wrote a {}x{} thumbnail to {}.", width, height, thumbnail.display());
+    }
+
+    if report.failed_verification(strict) {
+        std::process::exit(Error::new(ErrorKind::VerificationFailed, "").exit_code());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic input of `len` bytes, the same as the one the integration tests use
+    fn input(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+
+        (0..len).map(|_| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (state >> 16) as u8
+        }).collect()
+    }
+
+    /// Empty directory for a single test, so the tests can run in parallel. `name` must be unique across the tests
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("b2b-unit-{}-{}", std::process::id(), name));
+
+        let _ = std::fs::remove_dir_all(&dir);
+
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn is_cancelled<T>(result: Result<T>) -> bool {
+        matches!(result, Err(e) if e.to_string() == ErrorKind::Cancelled.to_string())
+    }
+
+    #[test]
+    fn cancelled_conversion_leaves_the_input_intact() {
+        let dir = scratch_dir("cancel-set");
+
+        let data = input(20000, 2);
+
+        let cancel = AtomicBool::new(true);
+
+        for in_place in [false, true] {
+            std::fs::write(dir.join("input"), &data).unwrap();
+
+            let result = encode_with_options(dir.join("input"), dir.join("input.bmp"), &EncodeOptions::default().with_in_place(in_place), &cancel);
+
+            assert!(is_cancelled(result), "in place: {}", in_place);
+            assert_eq!(std::fs::read(dir.join("input")).unwrap(), data, "in place: {}", in_place);
+            assert!(!dir.join("input.bmp").exists(), "in place: {}", in_place);
+        }
+
+        //The same goes for converting a bitmap back
+        assert!(encode_with_options(dir.join("input"), dir.join("input.bmp"), &EncodeOptions::default(), &AtomicBool::new(false)).is_ok());
+
+        let bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+        std::fs::remove_file(dir.join("input")).unwrap();
+
+        assert!(is_cancelled(bmp_to_bin(dir.join("input.bmp"), dir.join("input"), Some(VerifyOptions::default()), false, &cancel)));
+
+        assert_eq!(std::fs::read(dir.join("input.bmp")).unwrap(), bitmap);
+        assert!(!dir.join("input").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn cancelling_partway_removes_the_output() {
+        let dir = scratch_dir("cancel-partway");
+
+        let data = input(20000, 3);
+
+        std::fs::write(dir.join("input"), &data).unwrap();
+
+        assert!(encode_with_options(dir.join("input"), dir.join("input.bmp"), &EncodeOptions::default(), &AtomicBool::new(false)).is_ok());
+
+        let cancel = AtomicBool::new(false);
+
+        //Cancelled once the bitmap has been copied, before the copy is converted back
+        let result = convert_copy(&dir.join("input.bmp"), &dir.join("copy"), 1024, &cancel, |output| {
+            cancel.store(true, Ordering::Relaxed);
+
+            bmp_to_bin(output, output, Some(VerifyOptions::default()), false, &cancel)
+        });
+
+        assert!(is_cancelled(result));
+        assert!(!dir.join("copy").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn is_file_changed<T>(result: Result<T>) -> bool {
+        matches!(result, Err(e) if e.to_string() == ErrorKind::FileChanged.to_string())
+    }
+
+    #[test]
+    fn changes_to_the_input_are_detected() {
+        let dir = scratch_dir("file-changed");
+
+        let path = dir.join("input");
+
+        std::fs::write(&path, input(2000, 4)).unwrap();
+
+        let modified = std::fs::metadata(&path).unwrap().modified().ok();
+
+        assert!(check_unchanged(&path, 2000, modified).is_ok());
+
+        //Shrunk
+        OpenOptions::new().write(true).open(&path).unwrap().set_len(1500).unwrap();
+
+        assert!(is_file_changed(check_unchanged(&path, 2000, modified)));
+
+        //The same length, but written to since
+        std::fs::write(&path, input(2000, 5)).unwrap();
+
+        OpenOptions::new().write(true).open(&path).unwrap().set_modified(SystemTime::UNIX_EPOCH).unwrap();
+
+        assert!(is_file_changed(check_unchanged(&path, 2000, modified)));
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn input_shrinking_while_encoding_is_detected() {
+        let dir = scratch_dir("file-shrunk");
+
+        let data = input(20000, 6);
+
+        std::fs::write(dir.join("input"), &data).unwrap();
+
+        let cancel = AtomicBool::new(false);
+
+        let (header, _) = prepare_header(dir.join("input"), &EncodeOptions::default(), &cancel).unwrap();
+
+        //The file shrinks after its header has been computed, before it is read, so less is read than the header
+        //describes, which the caller reports as FileChanged
+        OpenOptions::new().write(true).open(dir.join("input")).unwrap().set_len(15000).unwrap();
+
+        let read = encode_to_writer(& mut File::open(dir.join("input")).unwrap(), & mut Vec::new(), &header, 1024, true, &cancel).unwrap();
+
+        assert_eq!(read, 15000);
+        assert_ne!(read, header.original_file_size() as u64);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn interrupted_operations_are_retried() {
+        let mut attempts = 0;
+
+        let result = retry_interrupted(|| {
+            attempts += 1;
+
+            if attempts == 1 {
+                Err(std::io::Error::from(std::io::ErrorKind::Interrupted))
+            } else {
+                Ok(attempts)
+            }
+        });
+
+        assert_eq!(result.unwrap(), 2);
+
+        //Other errors are returned straight away
+        attempts = 0;
+
+        let result: std::io::Result<()> = retry_interrupted(|| {
+            attempts += 1;
+            Err(std::io::Error::from(std::io::ErrorKind::PermissionDenied))
+        });
+
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::PermissionDenied);
+        assert_eq!(attempts, 1);
+
+        //An operation that is always interrupted is eventually given up on
+        attempts = 0;
+
+        let result: std::io::Result<()> = retry_interrupted(|| {
+            attempts += 1;
+            Err(std::io::Error::from(std::io::ErrorKind::Interrupted))
+        });
+
+        assert_eq!(result.unwrap_err().kind(), std::io::ErrorKind::Interrupted);
+        assert_eq!(attempts, MAX_INTERRUPTED_RETRIES + 1);
+    }
+
+    /// Collects what is written to it, failing every other write with `ErrorKind::Interrupted` before it succeeds
+    #[derive(Default)]
+    struct InterruptingWriter {
+        written: Vec<u8>,
+        interrupt: bool,
+        interruptions: usize,
+    }
+
+    impl Write for InterruptingWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.interrupt = !self.interrupt;
+
+            if self.interrupt {
+                self.interruptions += 1;
+
+                return Err(std::io::Error::from(std::io::ErrorKind::Interrupted));
+            }
+
+            self.written.extend_from_slice(buf);
+
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn interrupted_writes_are_retried() {
+        let dir = scratch_dir("interrupted-writes");
+
+        let data = input(10000, 7);
+
+        std::fs::write(dir.join("input"), &data).unwrap();
+
+        let cancel = AtomicBool::new(false);
+
+        let (header, _) = prepare_header(dir.join("input"), &EncodeOptions::default(), &cancel).unwrap();
+
+        let mut expected = Vec::new();
+
+        encode_to_writer(& mut Cursor::new(&data), & mut expected, &header, 1024, true, &cancel).unwrap();
+
+        let mut writer = InterruptingWriter::default();
+
+        encode_to_writer(& mut Cursor::new(&data), & mut writer, &header, 1024, true, &cancel).unwrap();
+
+        assert!(writer.interruptions > 1);
+        assert_eq!(writer.written, expected);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    /// Bytes of `bitmap` at the parts `header` maps `len` bytes of the original starting at `start` to
+    fn located_bytes(bitmap: &[u8], header: &Header, start: u64, len: u64) -> Vec<u8> {
+        header.map_range(start, len).into_iter()
+            .flat_map(|(offset, length)| bitmap[offset as usize..(offset + length) as usize].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn ranges_are_located_in_every_layout() {
+        let dir = scratch_dir("locate");
+
+        let cancel = AtomicBool::new(false);
+
+        //Not a whole number of pixels, so the last bytes are left out of the layouts
+        let data = input(5003, 8);
+
+        std::fs::write(dir.join("input"), &data).unwrap();
+
+        let layouts = [Layout::Row, Layout::Column, Layout::Interleaved, Layout::Shuffled, Layout::Tiled];
+
+        for (i, layout) in layouts.iter().enumerate() {
+            let bitmap_path = dir.join(format!("input.{}.bmp", i));
+
+            encode_with_options(dir.join("input"), &bitmap_path, &EncodeOptions::default().with_layout(*layout).with_seed(Some(9)).with_tile_size(3), &cancel).unwrap();
+
+            let bitmap = std::fs::read(&bitmap_path).unwrap();
+            let header = read_header(&bitmap_path).unwrap();
+
+            let row = (header.width() * header.bytes_per_pixel()) as u64;
+            let header_size = header.header_size() as u64;
+
+            let ranges = [
+                (0, data.len() as u64),
+                (0, 1),
+                //Inside the front of the file, which is stored after the rest
+                (10, 50),
+                //Across the end of the header region, so partly from the end of the payload and partly in place
+                (header_size - 7, 20),
+                //Across the end of a row of pixels, starting partway into a pixel
+                (3 * row - 5, 11),
+                (header_size + 2 * row + 1, row + 2),
+                //The last bytes, which aren't a whole pixel, and a range running past the end
+                (5000, 3),
+                (4990, 100),
+            ];
+
+            for (start, len) in ranges.iter().copied() {
+                let end = (start + len).min(data.len() as u64);
+
+                assert_eq!(located_bytes(&bitmap, &header, start, len), &data[start as usize..end as usize], "{:?} {}:{}", layout, start, len);
+            }
+
+            //Nothing to locate past the end of the file
+            assert!(header.map_range(6000, 10).is_empty(), "{:?}", layout);
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn row_layout_ranges_are_split_at_the_header_region() {
+        let (header, _) = build_header(5000, &EncodeOptions::default().with_digest(false), |_| unreachable!()).unwrap();
+
+        let header_size = header.header_size() as u64;
+        let front_offset = header.front_offset();
+
+        assert_eq!(header.map_range(0, 10), [(front_offset, 10)]);
+        assert_eq!(header.map_range(header_size, 100), [(header_size, 100)]);
+        assert_eq!(header.map_range(header_size - 6, 10), [(front_offset + header_size - 6, 6), (header_size, 4)]);
+        assert_eq!(header.map_range(4990, 100), [(4990, 10)]);
+    }
+
+    #[test]
+    fn header_region_can_change_size() {
+        let dir = scratch_dir("header-region");
+
+        let cancel = AtomicBool::new(false);
+
+        let data = input(5000, 1);
+
+        std::fs::write(dir.join("input"), &data).unwrap();
+
+        for (i, options) in [EncodeOptions::default(), EncodeOptions::default().with_layout(Layout::Shuffled).with_seed(Some(3))].iter().enumerate() {
+            let bitmap = dir.join(format!("input.{}.bmp", i));
+
+            encode_with_options(dir.join("input"), &bitmap, options, &cancel).unwrap();
+
+            //Grow the header region to the canonical block and shrink it back again
+            for canonical in [true, false] {
+                let (header, _) = build_header(data.len() as u64, &options.clone().with_canonical(canonical), |range| Ok(slice_digests(&data, range))).unwrap();
+
+                update_header_in_place(&bitmap, &header, &cancel).unwrap();
+
+                assert_eq!(read_header(&bitmap).unwrap().header_size(), header.header_size());
+                assert_eq!(decode_to_vec(&bitmap, &cancel).unwrap(), data);
+                assert_eq!(std::fs::metadata(&bitmap).unwrap().len(), (header.pixmap_size() + Header::bitmap_header_size()) as u64);
+            }
+        }
+
+        assert!(!dir.join("input.0.bmp.tmp").exists() && !dir.join("input.1.bmp.tmp").exists());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn payload_is_read_in_chunks_of_the_given_size() {
+        let dir = scratch_dir("payload-chunks");
+
+        let data = input(5000, 8);
+
+        std::fs::write(dir.join("input"), &data).unwrap();
+
+        encode_with_options(dir.join("input"), dir.join("input.bmp"), &EncodeOptions::default(), &AtomicBool::new(false)).unwrap();
+
+        let header = read_header(dir.join("input.bmp")).unwrap();
+
+        for chunk_size in [1, 7, 1024, 1 << 20] {
+            let mut read = Vec::new();
+
+            read_payload(& mut File::open(dir.join("input.bmp")).unwrap(), &header, chunk_size, &AtomicBool::new(false), |chunk| {
+                assert!(chunk.len() <= chunk_size, "{} byte chunk", chunk_size);
+
+                read.extend_from_slice(chunk);
+                Ok(())
+            }).unwrap();
+
+            assert_eq!(read, data, "{} byte chunk", chunk_size);
+        }
+
+        let _ = std::fs::remove_dir_all(&dir);
     }
 }
@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::error::{Result, Error, ErrorKind};
+
+/// Bits of a digest that b2b stores and compares. The top bit marks whether a digest is present in the header, so
+/// it is not part of the digest
+const DIGEST_MASK: u128 = u128::MAX >> 1;
+
+/// Number of hex digits of a digest that are compared. Longer digests, such as a whole Blake256 digest, are cut short
+const DIGEST_HEX_DIGITS: usize = 32;
+
+fn invalid<E: Into<Box<dyn std::error::Error>>>(error: E) -> Error {
+    Error::new(ErrorKind::InvalidManifest, error)
+}
+
+/// Whether two digests are the same in the bits b2b stores
+pub fn digests_match(first: u128, second: u128) -> bool {
+    (first ^ second) & DIGEST_MASK == 0
+}
+
+/// Expected digests of the files in a backup, read from lines of the form `HEX NAME`, the same as the output of tools
+/// such as `sha256sum`. The digest is the Blake256 digest of the original file in hex, of which only the first 32
+/// digits are compared, so the digests printed by `--info` can be used as they are. Names are paths relative to the
+/// directory being checked, with `/` between the parts. Blank lines and lines starting with `#` are ignored
+#[derive(Debug, Default)]
+pub struct Manifest {
+    entries: BTreeMap<String, u128>,
+}
+
+impl Manifest {
+    /// Parses the text of a manifest
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut manifest = Self::default();
+
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (hex, name) = line.split_once(char::is_whitespace)
+                .ok_or_else(|| invalid(format!("Expected 'DIGEST NAME' on line {}", i + 1)))?;
+
+            //sha256sum marks files read in binary mode with a '*' before the name
+            let name = name.trim_start();
+            let name = name.strip_prefix('*').unwrap_or(name);
+
+            let digest = hex.get(..DIGEST_HEX_DIGITS)
+                .filter(|_| hex.chars().all(|c| c.is_ascii_hexdigit()))
+                .and_then(|digits| u128::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| invalid(format!("Expected a digest of at least {} hex digits on line {}", DIGEST_HEX_DIGITS, i + 1)))?;
+
+            if manifest.entries.insert(name.to_string(), digest).is_some() {
+                return Err(invalid(format!("'{}' is listed more than once (line {})", name, i + 1)));
+            }
+        }
+
+        Ok(manifest)
+    }
+
+    /// Reads and parses the manifest at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&std::fs::read_to_string(path.as_ref())?)
+    }
+
+    /// The expected digest of the file called `name`, if it is listed
+    pub fn expected(&self, name: &str) -> Option<u128> {
+        self.entries.get(name).copied()
+    }
+
+    /// Names of every file listed, in order
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.keys().map(String::as_str)
+    }
+}
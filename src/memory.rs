@@ -0,0 +1,76 @@
+//! Measurements of the memory used by the process, printed by `--profile-memory` to check that large files are
+//! converted in chunks rather than read into memory whole
+//!
+//! The peak resident set size comes from the kernel and is only available on Linux. The peak number of bytes
+//! allocated on the heap needs the counting allocator, which is only installed with the `profile-memory` feature
+
+#[cfg(feature = "profile-memory")]
+mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    static ALLOCATED: AtomicU64 = AtomicU64::new(0);
+    static PEAK: AtomicU64 = AtomicU64::new(0);
+
+    /// The system allocator, keeping count of the bytes allocated and the most there have been at once
+    pub struct CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let pointer = System.alloc(layout);
+
+            if !pointer.is_null() {
+                let allocated = ALLOCATED.fetch_add(layout.size() as u64, Ordering::Relaxed) + layout.size() as u64;
+
+                PEAK.fetch_max(allocated, Ordering::Relaxed);
+            }
+
+            pointer
+        }
+
+        unsafe fn dealloc(&self, pointer: *mut u8, layout: Layout) {
+            System.dealloc(pointer, layout);
+
+            ALLOCATED.fetch_sub(layout.size() as u64, Ordering::Relaxed);
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    pub fn peak_allocated() -> u64 {
+        PEAK.load(Ordering::Relaxed)
+    }
+}
+
+/// Most bytes allocated on the heap at once so far, or `None` without the `profile-memory` feature
+pub fn peak_allocated() -> Option<u64> {
+    #[cfg(feature = "profile-memory")]
+    return Some(counting::peak_allocated());
+
+    #[cfg(not(feature = "profile-memory"))]
+    None
+}
+
+/// Most memory the process has had resident at once so far, in bytes, or `None` if it can't be read
+pub fn peak_rss() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+
+    //The line looks like "VmHWM:     1234 kB"
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+
+    line["VmHWM:".len()..].trim().trim_end_matches("kB").trim().parse::<u64>().ok().map(|kib| kib * 1024)
+}
+
+/// Prints the peak memory use to stderr, so it doesn't mix with a converted file written to stdout
+pub fn print_profile() {
+    match peak_rss() {
+        Some(bytes) => eprintln!("Peak resident memory: {} bytes", bytes),
+        None => eprintln!("Peak resident memory: unknown on this platform"),
+    }
+
+    match peak_allocated() {
+        Some(bytes) => eprintln!("Peak heap allocated:  {} bytes", bytes),
+        None => eprintln!("Peak heap allocated:  not measured, build with --features profile-memory"),
+    }
+}
@@ -0,0 +1,247 @@
+use std::ops::Range;
+
+use crate::header::DEFAULT_MIN_DIMENSION;
+use crate::layout::{Layout, DEFAULT_TILE_SIZE};
+use crate::error::{Result, Error, ErrorKind};
+
+/// Default size of the buffer used to copy file data, 1 MiB
+pub const DEFAULT_CHUNK_SIZE: usize = 1 << 20;
+
+/// Expansion (output size over input size) above which a warning is printed before encoding
+pub const WARN_EXPANSION: f64 = 2.0;
+
+/// Expansion above which encoding is refused unless forced, as long as the bitmap is also at least
+/// `MAX_EXPANSION_MIN_SIZE` bytes larger than the file. Small files always expand a lot, but cost little
+pub const MAX_EXPANSION: f64 = 16.0;
+
+/// Smallest growth, in bytes, that can be refused for exceeding `MAX_EXPANSION`. 16 MiB
+pub const MAX_EXPANSION_MIN_SIZE: u64 = 16 << 20;
+
+/// File format the converted bitmap is stored in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    Bitmap,
+    /// An uncompressed png with the same pixels as the bitmap, for platforms that alter bitmaps but pass pngs through
+    Png,
+    /// The bitmap without its bitmap header: the b2b header, the file data and the padding. The b2b signature at the
+    /// start marks the format. Only supports `Layout::Row`, as the bitmap's width is not kept
+    Raw,
+}
+
+/// Names of the containers on the command line, in the order of `Container`
+pub const CONTAINER_NAMES: [&str; 3] = ["bmp", "png", "raw"];
+
+impl std::str::FromStr for Container {
+    type Err = Error;
+
+    fn from_str(name: &str) -> Result<Self> {
+        match name {
+            "bmp" => Ok(Container::Bitmap),
+            "png" => Ok(Container::Png),
+            "raw" => Ok(Container::Raw),
+            _ => Err(Error::new(ErrorKind::InvalidOption, format!("unknown container '{}', expected one of {}", name, CONTAINER_NAMES.join(", ")))),
+        }
+    }
+}
+
+/// Options controlling how a file is converted into a bitmap. Start from `EncodeOptions::default()` and
+/// chain the `with_` methods to change them
+#[derive(Debug, Clone)]
+pub struct EncodeOptions {
+    digest: bool,
+    layout: Layout,
+    seed: Option<u64>,
+    hash_range: Option<Range<u64>>,
+    min_dimension: u32,
+    max_dimension: Option<u32>,
+    in_place: bool,
+    container: Container,
+    chunk_size: usize,
+    honor_offset: bool,
+    force: bool,
+    canonical: bool,
+    bitmap_digest: bool,
+    restore_on_error: bool,
+    sparse: bool,
+    tile_size: u32,
+}
+
+impl Default for EncodeOptions {
+    fn default() -> Self {
+        Self {
+            digest: true,
+            layout: Layout::Row,
+            seed: None,
+            hash_range: None,
+            min_dimension: DEFAULT_MIN_DIMENSION,
+            max_dimension: None,
+            in_place: false,
+            container: Container::Bitmap,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            honor_offset: false,
+            force: false,
+            canonical: false,
+            bitmap_digest: false,
+            restore_on_error: false,
+            sparse: false,
+            tile_size: DEFAULT_TILE_SIZE,
+        }
+    }
+}
+
+impl EncodeOptions {
+    /// Store a digest and CRC of the file, so the bitmap can be verified when converted back
+    pub fn with_digest(mut self, digest: bool) -> Self {
+        self.digest = digest;
+        self
+    }
+
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Seed of the pseudo-random features, such as the pixel order of `Layout::Shuffled`. `None` derives one from the
+    /// digest of the file when it is needed
+    pub fn with_seed(mut self, seed: Option<u64>) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    /// Only include this range of the file in the digest. `None` covers the whole file
+    pub fn with_hash_range(mut self, hash_range: Option<Range<u64>>) -> Self {
+        self.hash_range = hash_range;
+        self
+    }
+
+    pub fn with_min_dimension(mut self, min_dimension: u32) -> Self {
+        self.min_dimension = min_dimension;
+        self
+    }
+
+    /// Largest width or height the bitmap may have. `None` leaves the dimensions uncapped
+    pub fn with_max_dimension(mut self, max_dimension: Option<u32>) -> Self {
+        self.max_dimension = max_dimension;
+        self
+    }
+
+    /// Convert the file in place rather than writing a new file
+    pub fn with_in_place(mut self, in_place: bool) -> Self {
+        self.in_place = in_place;
+        self
+    }
+
+    pub fn with_container(mut self, container: Container) -> Self {
+        self.container = container;
+        self
+    }
+
+    /// Size of the buffer used when copying file data. Must not be zero
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size;
+        self
+    }
+
+    /// Point the pixel offset in the bitmap header past the b2b header, so viewers don't draw it as pixels
+    pub fn with_honor_offset(mut self, honor_offset: bool) -> Self {
+        self.honor_offset = honor_offset;
+        self
+    }
+
+    /// Encode even if the bitmap would be more than `MAX_EXPANSION` times the size of the file
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+
+    /// Pad the header region to a whole block, so the file data is stored block aligned (see `Header::canonical`)
+    pub fn with_canonical(mut self, canonical: bool) -> Self {
+        self.canonical = canonical;
+        self
+    }
+
+    /// Store a digest of the whole bitmap, so that a change to any of its bytes can be detected with `--check-bitmap`.
+    /// Only bitmaps can hold one, not pngs or raw files
+    pub fn with_bitmap_digest(mut self, bitmap_digest: bool) -> Self {
+        self.bitmap_digest = bitmap_digest;
+        self
+    }
+
+    /// Save the start of the file before converting it in place, and put it back if the conversion fails, so the file
+    /// is left as it was. Costs a copy of the header region, or of the whole file for layouts other than row
+    pub fn with_restore_on_error(mut self, restore_on_error: bool) -> Self {
+        self.restore_on_error = restore_on_error;
+        self
+    }
+
+    /// Leave the padding as a hole rather than writing it out or reserving space for it, on filesystems that support
+    /// holes. The bitmap reads back the same, as a hole reads as zeros, but the padding has no blocks on disk until
+    /// something writes to it, so copying the bitmap with a tool that doesn't keep holes can run out of space
+    pub fn with_sparse(mut self, sparse: bool) -> Self {
+        self.sparse = sparse;
+        self
+    }
+
+    /// Side of the square tiles of `Layout::Tiled`, in pixels. Must not be zero
+    pub fn with_tile_size(mut self, tile_size: u32) -> Self {
+        self.tile_size = tile_size;
+        self
+    }
+
+    pub fn digest(&self) -> bool { self.digest }
+
+    pub fn layout(&self) -> Layout { self.layout }
+
+    pub fn seed(&self) -> Option<u64> { self.seed }
+
+    pub fn hash_range(&self) -> Option<Range<u64>> { self.hash_range.clone() }
+
+    pub fn min_dimension(&self) -> u32 { self.min_dimension }
+
+    pub fn max_dimension(&self) -> Option<u32> { self.max_dimension }
+
+    pub fn in_place(&self) -> bool { self.in_place }
+
+    pub fn container(&self) -> Container { self.container }
+
+    pub fn chunk_size(&self) -> usize { self.chunk_size }
+
+    pub fn honor_offset(&self) -> bool { self.honor_offset }
+
+    pub fn force(&self) -> bool { self.force }
+
+    pub fn canonical(&self) -> bool { self.canonical }
+
+    pub fn bitmap_digest(&self) -> bool { self.bitmap_digest }
+
+    pub fn restore_on_error(&self) -> bool { self.restore_on_error }
+
+    pub fn sparse(&self) -> bool { self.sparse }
+
+    pub fn tile_size(&self) -> u32 { self.tile_size }
+}
+
+/// Options controlling how a decoded file is checked against the digest stored in its bitmap
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VerifyOptions {
+    quick: bool,
+    strict: bool,
+}
+
+impl VerifyOptions {
+    /// Only compare the stored CRC32, which is much faster than the full digest but a weaker check
+    pub fn with_quick(mut self, quick: bool) -> Self {
+        self.quick = quick;
+        self
+    }
+
+    /// Treat a bitmap without a digest as a failed verification, instead of only warning about it
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    pub fn quick(&self) -> bool { self.quick }
+
+    pub fn strict(&self) -> bool { self.strict }
+}
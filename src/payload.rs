@@ -0,0 +1,78 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use crate::header::Header;
+
+/// Reads the original file back out of a bitmap, in order, without modifying the bitmap. The first `header_size`
+/// bytes of the original are stored after the rest of it, and are read from there transparently. The bitmap must
+/// be in file order, i.e. use `Layout::Row` or have been restored with `Layout::restore`, and its header should
+/// already have been validated
+pub struct PayloadReader<R: Read + Seek> {
+    inner: R,
+    /// Offset in the bitmap of the first `front_len` bytes of the original
+    front_offset: u64,
+    front_len: u64,
+    len: u64,
+    /// Position in the original file
+    position: u64,
+    /// Position of `inner`, if known, so consecutive reads don't seek
+    inner_position: Option<u64>,
+}
+
+impl<R: Read + Seek> PayloadReader<R> {
+    pub fn new(inner: R, header: &Header) -> Self {
+        let len = header.original_file_size() as u64;
+
+        Self {
+            inner,
+            front_offset: header.front_offset(),
+            front_len: (header.header_size() as u64).min(len),
+            len,
+            position: 0,
+            inner_position: None,
+        }
+    }
+
+    /// Offset in the bitmap of the byte at `self.position`, and how many bytes from there on are stored contiguously
+    fn stored_at(&self) -> (u64, u64) {
+        if self.position < self.front_len {
+            (self.front_offset + self.position, self.front_len - self.position)
+        } else {
+            //The rest of the original is stored in place after the headers
+            (self.position, self.len - self.position)
+        }
+    }
+}
+
+impl<R: Read + Seek> Read for PayloadReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.position >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let (offset, available) = self.stored_at();
+
+        if self.inner_position != Some(offset) {
+            self.inner.seek(SeekFrom::Start(offset))?;
+        }
+
+        let count = buf.len().min(available as usize);
+
+        let read = match self.inner.read(&mut buf[..count]) {
+            Ok(read) => read,
+            Err(e) => {
+                self.inner_position = None;
+
+                return Err(e);
+            }
+        };
+
+        if read == 0 {
+            return Err(std::io::Error::new(std::io::ErrorKind::UnexpectedEof, "Bitmap ends before the payload"));
+        }
+
+        self.position += read as u64;
+        self.inner_position = Some(offset + read as u64);
+
+        Ok(read)
+    }
+}
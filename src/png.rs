@@ -0,0 +1,224 @@
+use crate::crc::Crc32;
+use crate::error::{Result, Error, ErrorKind};
+use crate::header::BYTES_PER_PIXEL;
+
+pub const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+
+/// Largest amount of data in a single stored (uncompressed) deflate block
+const MAX_STORED_BLOCK: usize = 0xFFFF;
+
+/// 8 bits per channel RGBA, which matches the 4 bytes per pixel of the bitmap
+const BIT_DEPTH: u8 = 8;
+const COLOUR_TYPE_RGBA: u8 = 6;
+
+fn invalid<E: Into<Box<dyn std::error::Error>>>(error: E) -> Error {
+    Error::new(ErrorKind::InvalidPng, error)
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+
+    //Largest number of bytes that can be summed before b could overflow
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+
+        a %= 65521;
+        b %= 65521;
+    }
+
+    (b << 16) | a
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    let mut crc = Crc32::new();
+
+    crc.update(kind);
+    crc.update(data);
+
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&crc.finalize().to_be_bytes());
+}
+
+/// Splits `slice` at `at`, failing instead of panicking if it is too short
+fn take(slice: &[u8], at: usize) -> Result<(&[u8], &[u8])> {
+    if slice.len() < at {
+        Err(invalid("Unexpected end of data"))
+    } else {
+        Ok(slice.split_at(at))
+    }
+}
+
+fn be_u32(bytes: &[u8]) -> u32 {
+    u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Encodes `pixels`, given bottom row first as in a bitmap, as a `width` by `height` RGBA png. The image data is
+/// stored without compression, so the bytes of every pixel are kept exactly
+pub fn encode(width: u32, height: u32, pixels: &[u8]) -> Vec<u8> {
+    let row_size = (width * BYTES_PER_PIXEL) as usize;
+
+    //Each row starts with filter type 0 (none). Png rows run top to bottom, so the bitmap rows are reversed
+    let mut raw = Vec::with_capacity((row_size + 1) * height as usize);
+
+    for row in pixels.chunks(row_size).rev() {
+        raw.push(0);
+        raw.extend_from_slice(row);
+    }
+
+    //Zlib stream made of stored deflate blocks. There is always at least one row, so at least one block
+    let mut zlib = vec![0x78, 0x01];
+
+    let blocks = raw.chunks(MAX_STORED_BLOCK).count();
+
+    for (i, block) in raw.chunks(MAX_STORED_BLOCK).enumerate() {
+        //The first bit marks the final block, and the next two bits (zero) mark it as stored
+        zlib.push(if i + 1 == blocks { 1 } else { 0 });
+        zlib.extend_from_slice(&(block.len() as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(block.len() as u16)).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+
+    zlib.extend_from_slice(&adler32(&raw).to_be_bytes());
+
+    let mut ihdr = Vec::with_capacity(13);
+
+    ihdr.extend_from_slice(&width.to_be_bytes());
+    ihdr.extend_from_slice(&height.to_be_bytes());
+    ihdr.extend_from_slice(&[BIT_DEPTH, COLOUR_TYPE_RGBA, 0, 0, 0]);
+
+    let mut png = PNG_SIGNATURE.to_vec();
+
+    write_chunk(&mut png, b"IHDR", &ihdr);
+    write_chunk(&mut png, b"IDAT", &zlib);
+    write_chunk(&mut png, b"IEND", &[]);
+
+    png
+}
+
+/// Size of the png `encode` writes for a `width` by `height` image
+pub fn encoded_size(width: u32, height: u32) -> u64 {
+    let raw = (width as u64 * BYTES_PER_PIXEL as u64 + 1) * height as u64;
+
+    let blocks = raw.div_ceil(MAX_STORED_BLOCK as u64);
+
+    //Signature, then the IHDR, IDAT and IEND chunks with 12 bytes of length, type and CRC each
+    let zlib = 2 + blocks * 5 + raw + 4;
+
+    PNG_SIGNATURE.len() as u64 + (12 + 13) + (12 + zlib) + 12
+}
+
+/// Reads back a png written by `encode`, returning its width, height and pixels (bottom row first). Only the
+/// uncompressed, unfiltered image data that `encode` writes is supported, so a png that has been recompressed by
+/// another tool is rejected rather than misread
+pub fn decode(png: &[u8]) -> Result<(u32, u32, Vec<u8>)> {
+    let (signature, mut rest) = take(png, PNG_SIGNATURE.len())?;
+
+    if signature != PNG_SIGNATURE {
+        return Err(invalid("Missing png signature"));
+    }
+
+    let mut ihdr = None;
+    let mut zlib = Vec::new();
+
+    loop {
+        let (length, after) = take(rest, 4)?;
+        let (kind, after) = take(after, 4)?;
+        let (data, after) = take(after, be_u32(length) as usize)?;
+        let (crc, after) = take(after, 4)?;
+
+        rest = after;
+
+        let mut expected = Crc32::new();
+
+        expected.update(kind);
+        expected.update(data);
+
+        if expected.finalize() != be_u32(crc) {
+            return Err(invalid(format!("Bad CRC in {} chunk", String::from_utf8_lossy(kind))));
+        }
+
+        match kind {
+            b"IHDR" => ihdr = Some(data),
+            b"IDAT" => zlib.extend_from_slice(data),
+            b"IEND" => break,
+            _ => {}
+        }
+    }
+
+    let ihdr = ihdr.ok_or_else(|| invalid("Missing IHDR chunk"))?;
+
+    if ihdr.len() != 13 || ihdr[8..] != [BIT_DEPTH, COLOUR_TYPE_RGBA, 0, 0, 0] {
+        return Err(invalid("Only 8 bit RGBA pngs without interlacing are supported"));
+    }
+
+    let (width, height) = (be_u32(&ihdr[0..4]), be_u32(&ihdr[4..8]));
+
+    let row_size = width as u64 * BYTES_PER_PIXEL as u64;
+
+    if width == 0 || height == 0 {
+        return Err(invalid("Image is empty"));
+    }
+
+    if row_size * height as u64 > u32::MAX as u64 {
+        return Err(invalid("Image is too large"));
+    }
+
+    let (zlib_header, mut blocks) = take(&zlib, 2)?;
+
+    if zlib_header[0] & 0x0F != 8 || zlib_header[1] & 0x20 != 0 {
+        return Err(invalid("Unsupported zlib stream"));
+    }
+
+    let mut raw = Vec::with_capacity(((row_size + 1) * height as u64) as usize);
+
+    loop {
+        let (block_header, after) = take(blocks, 5)?;
+
+        if block_header[0] & 0x06 != 0 {
+            return Err(invalid("Compressed image data is not supported"));
+        }
+
+        let length = u16::from_le_bytes([block_header[1], block_header[2]]);
+
+        if length != !u16::from_le_bytes([block_header[3], block_header[4]]) {
+            return Err(invalid("Corrupt stored block"));
+        }
+
+        let (data, after) = take(after, length as usize)?;
+
+        raw.extend_from_slice(data);
+
+        blocks = after;
+
+        if block_header[0] & 0x01 != 0 {
+            break;
+        }
+    }
+
+    let (adler, _) = take(blocks, 4)?;
+
+    if adler32(&raw) != be_u32(adler) {
+        return Err(invalid("Bad Adler-32 checksum"));
+    }
+
+    if raw.len() as u64 != (row_size + 1) * height as u64 {
+        return Err(invalid("Image data does not match the image size"));
+    }
+
+    let mut pixels = Vec::with_capacity((row_size * height as u64) as usize);
+
+    for row in raw.chunks((row_size + 1) as usize).rev() {
+        if row[0] != 0 {
+            return Err(invalid("Filtered image data is not supported"));
+        }
+
+        pixels.extend_from_slice(&row[1..]);
+    }
+
+    Ok((width, height, pixels))
+}
@@ -0,0 +1,33 @@
+/// The pseudo-random generator behind every randomised feature, such as `Layout::Shuffled`. It is seeded from the
+/// seed stored in the header, so encoding and decoding always produce the same sequence
+///
+/// This is SplitMix64, a small generator that is plenty for scrambling pixels. Not suitable for anything security related
+pub struct SeededRng(u64);
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E3779B97F4A7C15);
+
+        let mut z = self.0;
+
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+
+        z ^ (z >> 31)
+    }
+
+    /// A number below `bound`, which must not be zero. The slight bias of the modulo doesn't matter for any of the uses
+    pub fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+/// Seed used when none is given, derived from the digest of the file so that encoding the same file always gives the
+/// same bitmap
+pub fn seed_from_digest(digest: u128) -> u64 {
+    (digest as u64) ^ ((digest >> 64) as u64)
+}
@@ -0,0 +1,32 @@
+use std::io::{Read, Write, Seek, SeekFrom, Error, ErrorKind};
+
+/// Copies the `len` bytes at `from` in `file` to `to`, through `buf`, so no more than `buf.len()` bytes are held at
+/// once. The ranges may overlap: like `copy_within`, the copy is done back to front when the destination is after the
+/// source, so every byte is read before it is overwritten. The file grows if the destination extends past its end
+pub fn copy_region<F: Read + Write + Seek>(file: &mut F, from: u64, to: u64, len: u64, buf: &mut [u8]) -> std::io::Result<()> {
+    if len > 0 && buf.is_empty() {
+        return Err(Error::new(ErrorKind::InvalidInput, "cannot copy a region through an empty buffer"));
+    }
+
+    let backwards = to > from && to < from + len;
+
+    let mut copied = 0;
+
+    while copied < len {
+        let chunk = (len - copied).min(buf.len() as u64);
+
+        let offset = if backwards { len - copied - chunk } else { copied };
+
+        let buf = &mut buf[..chunk as usize];
+
+        file.seek(SeekFrom::Start(from + offset))?;
+        file.read_exact(buf)?;
+
+        file.seek(SeekFrom::Start(to + offset))?;
+        file.write_all(buf)?;
+
+        copied += chunk;
+    }
+
+    Ok(())
+}
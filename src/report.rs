@@ -0,0 +1,174 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::header::VerifyOutcome;
+
+/// Sizes and timings of a single conversion
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ConversionReport {
+    /// Size of the file that was converted
+    pub input_bytes: u64,
+    /// Size of the file that was produced
+    pub output_bytes: u64,
+    /// Time spent computing digests, either to store in the bitmap or to verify against
+    pub hash_time: Duration,
+    /// Time spent copying, moving and resizing file data
+    pub io_time: Duration,
+    /// Result of checking the converted file against the stored digest or CRC, or `None` if it was not checked
+    pub verification: Option<VerifyOutcome>,
+}
+
+impl ConversionReport {
+    pub fn hash_ms(&self) -> u128 { self.hash_time.as_millis() }
+
+    pub fn io_ms(&self) -> u128 { self.io_time.as_millis() }
+
+    /// Whether the file was checked and failed. A missing digest only counts if `strict` is set
+    pub fn failed_verification(&self, strict: bool) -> bool {
+        self.verification.is_some_and(|outcome| outcome.is_failure(strict))
+    }
+
+    /// Input megabytes (10^6 bytes) converted per second, over the whole conversion
+    pub fn throughput_mb_s(&self) -> f64 {
+        let seconds = (self.hash_time + self.io_time).as_secs_f64();
+
+        if seconds > 0.0 {
+            self.input_bytes as f64 / 1_000_000.0 / seconds
+        } else {
+            0.0
+        }
+    }
+}
+
+impl std::fmt::Display for ConversionReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Input size:  {} bytes", self.input_bytes)?;
+        writeln!(f, "Output size: {} bytes", self.output_bytes)?;
+        writeln!(f, "Hashing:     {} ms", self.hash_ms())?;
+        writeln!(f, "I/O:         {} ms", self.io_ms())?;
+        write!(f, "Throughput:  {:.2} MB/s", self.throughput_mb_s())
+    }
+}
+
+/// Totals over every file converted in a batch
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BatchStats {
+    /// Number of files converted successfully
+    pub files: u64,
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    /// Number of files that were converted, but did not match their stored digest or CRC (or had none, in strict mode)
+    pub verification_failures: u64,
+    /// Number of files that could not be converted
+    pub failures: u64,
+    /// Number of files that were left untouched, as they were not created by b2b
+    pub skipped: u64,
+}
+
+impl BatchStats {
+    pub fn add(&mut self, report: &ConversionReport, strict: bool) {
+        self.files += 1;
+        self.input_bytes += report.input_bytes;
+        self.output_bytes += report.output_bytes;
+
+        if report.failed_verification(strict) {
+            self.verification_failures += 1;
+        }
+    }
+
+    /// Total output size as a multiple of the total input size
+    pub fn expansion_ratio(&self) -> f64 {
+        if self.input_bytes > 0 {
+            self.output_bytes as f64 / self.input_bytes as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+impl std::fmt::Display for BatchStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Files:                 {}", self.files)?;
+        writeln!(f, "Input size:            {} bytes", self.input_bytes)?;
+        writeln!(f, "Output size:           {} bytes", self.output_bytes)?;
+        writeln!(f, "Expansion:             {:.3}x", self.expansion_ratio())?;
+        writeln!(f, "Verification failures: {}", self.verification_failures)?;
+        writeln!(f, "Skipped files:         {}", self.skipped)?;
+        write!(f, "Failed conversions:    {}", self.failures)
+    }
+}
+
+/// Number of files in a tree that are already b2b bitmaps (or pngs or raw files), as counted by `--count`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EncodedCounts {
+    pub encoded: u64,
+    pub plain: u64,
+    /// Number of files that could not be read
+    pub failures: u64,
+}
+
+impl std::fmt::Display for EncodedCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "B2B files:   {}", self.encoded)?;
+        writeln!(f, "Other files: {}", self.plain)?;
+        write!(f, "Unreadable:  {}", self.failures)
+    }
+}
+
+/// Bitmaps in a tree checked against a manifest of expected digests with `--manifest`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ManifestCounts {
+    /// Number of bitmaps whose digest matches the manifest
+    pub matched: u64,
+    /// Number of bitmaps whose digest differs from the manifest
+    pub mismatched: u64,
+    /// Number of bitmaps not listed in the manifest
+    pub unlisted: u64,
+    /// Number of files listed in the manifest with no bitmap
+    pub missing: u64,
+    /// Number of bitmaps that could not be read
+    pub failures: u64,
+}
+
+impl ManifestCounts {
+    /// Whether every file listed was found and matched. Bitmaps that are not listed don't count against this
+    pub fn passed(&self) -> bool {
+        self.mismatched == 0 && self.missing == 0 && self.failures == 0
+    }
+}
+
+impl std::fmt::Display for ManifestCounts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Matched:    {}", self.matched)?;
+        writeln!(f, "Mismatched: {}", self.mismatched)?;
+        writeln!(f, "Missing:    {}", self.missing)?;
+        writeln!(f, "Unlisted:   {}", self.unlisted)?;
+        write!(f, "Unreadable: {}", self.failures)
+    }
+}
+
+/// Which way a file in a batch was converted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Converted into a bitmap, or would have been
+    Encode,
+    /// Converted from a b2b file back into the original, or would have been
+    Decode,
+    /// Left untouched, as it was not created by b2b
+    Skip,
+}
+
+/// Outcome of converting a single file in a batch, as listed by `--collect-errors`
+#[derive(Debug, Clone, Serialize)]
+pub struct FileStatus {
+    pub path: String,
+    pub direction: Direction,
+    /// Whether the file was converted (or skipped) and passed verification
+    pub ok: bool,
+    /// Why the file failed, if it did
+    pub error: Option<String>,
+    /// Code the process would exit with if this were the only file converted, if it failed
+    pub exit_code_hint: Option<i32>,
+}
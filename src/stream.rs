@@ -0,0 +1,290 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+
+use blake_hash::{Blake256, Digest};
+
+use crate::crc::Crc32;
+use crate::error::{Result, Error, ErrorKind};
+use crate::header::{self, Header, VerifyOutcome};
+use crate::layout::Layout;
+use crate::options::{EncodeOptions, VerifyOptions};
+
+/// Largest input converted entirely in memory when converting from stdin to stdout, unless `--memory-limit` is given
+pub const DEFAULT_MEMORY_LIMIT: u64 = 64 * 1024 * 1024;
+
+/// Path of a file in the temporary directory, which is removed when this is dropped if it was created
+struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    /// Names a file after this process and `name`, without creating it
+    fn new(name: &str) -> Self {
+        Self {
+            path: std::env::temp_dir().join(format!("b2b-{}-{}", std::process::id(), name)),
+        }
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Passes on what is read from `inner`, computing the digest and CRC of the bytes at `range` as they go by, so that
+/// data can be hashed in the same pass that encodes it
+struct HashingReader<R> {
+    inner: R,
+    range: Range<u64>,
+    position: u64,
+    hash: Blake256,
+    crc: Crc32,
+}
+
+impl<R: Read> HashingReader<R> {
+    fn new(inner: R, range: Range<u64>) -> Self {
+        Self {
+            inner,
+            range,
+            position: 0,
+            hash: Blake256::new(),
+            crc: Crc32::new(),
+        }
+    }
+
+    /// Digest and CRC of the bytes at `range`, which must all have been read
+    fn digests(self) -> (u128, u32) {
+        (crate::finalize_hash(self.hash), self.crc.finalize())
+    }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.inner.read(buf)?;
+
+        let end_position = self.position + read as u64;
+        let start = (self.range.start.clamp(self.position, end_position) - self.position) as usize;
+        let end = (self.range.end.clamp(self.position, end_position) - self.position) as usize;
+
+        if start < end {
+            self.hash.update(&buf[start..end]);
+            self.crc.update(&buf[start..end]);
+        }
+
+        self.position = end_position;
+
+        Ok(read)
+    }
+}
+
+/// Header for a file of `file_size` bytes as described by `options`, before the file has been hashed. If the digests
+/// are needed, the header holds placeholders for them (which take up the same space) and the range of the file to
+/// hash is returned with it. The real header comes from `crate::build_header` once the digests are known
+fn header_before_hashing(file_size: u64, options: &EncodeOptions) -> Result<(Header, Option<Range<u64>>)> {
+    let mut hash_range = None;
+
+    let (header, _) = crate::build_header(file_size, options, |range| {
+        hash_range = Some(range);
+
+        Ok((0, 0))
+    })?;
+
+    Ok((header, hash_range))
+}
+
+/// Returns true if `bytes` start with a bitmap header and the b2b signature. A bitmap whose b2b header is corrupt is
+/// still one, so that it fails to decode rather than being encoded again
+fn is_b2b_bitmap(bytes: &[u8]) -> bool {
+    header::has_b2b_signature(bytes)
+}
+
+/// If `bytes` are a png or raw file created by b2b, rebuilds the bitmap they hold. Returns `None` for anything else
+fn container_to_bitmap(bytes: &[u8]) -> Result<Option<Vec<u8>>> {
+    if bytes.starts_with(&crate::png::PNG_SIGNATURE) {
+        crate::png_to_bitmap(bytes)
+    } else if header::is_b2b_header(bytes) {
+        crate::raw_to_bitmap(bytes)
+    } else {
+        Ok(None)
+    }
+}
+
+/// Fails with `ErrorKind::VerificationFailed` unless `digests`, the digest and CRC of the decoded file, match the ones
+/// stored in `header`. Only the CRC is compared in quick mode. Bitmaps without a digest can't be verified, which is
+/// reported on stderr, as stdout holds the decoded file, or fails the same way in strict mode
+fn check_digests(header: &Header, (digest, crc): (u128, u32), verify: VerifyOptions) -> Result<()> {
+    let outcome = if verify.quick() {
+        header.verify_crc(crc)
+    } else {
+        header.verify(digest)
+    };
+
+    match outcome {
+        VerifyOutcome::NoDigest if !verify.strict() => {
+            eprintln!("Unable to verify as bitmap doesn't contain a digest.");
+
+            Ok(())
+        }
+        VerifyOutcome::Verified => Ok(()),
+        _ => Err(Error::new(ErrorKind::VerificationFailed, format!("{}, so the file was not written", outcome))),
+    }
+}
+
+/// Decodes `bitmap` in memory and writes the original file to `output`, once it has been verified if `verify` is given
+fn decode_bytes<W: Write>(bitmap: Vec<u8>, output: &mut W, verify: Option<VerifyOptions>, cancel: &AtomicBool) -> Result<u64> {
+    let (header, payload) = crate::decode_in_memory(bitmap, cancel)?;
+
+    if let Some(verify) = verify {
+        check_digests(&header, crate::slice_digests(&payload, header.hash_range()), verify)?;
+    }
+
+    output.write_all(&payload)?;
+
+    Ok(payload.len() as u64)
+}
+
+/// Encodes `data` as described by `options` in memory and writes the converted file to `output`. The digests are
+/// computed as the bitmap is written, and the header is filled in afterwards
+pub fn encode_bytes<W: Write>(data: &[u8], output: &mut W, options: &EncodeOptions, cancel: &AtomicBool) -> Result<u64> {
+    let file_size = data.len() as u64;
+
+    let (mut header, hash_range) = header_before_hashing(file_size, options)?;
+
+    let mut bitmap = Vec::with_capacity((header.pixmap_size() + Header::bitmap_header_size()) as usize);
+
+    let mut input = HashingReader::new(data, hash_range.clone().unwrap_or(0..0));
+
+    crate::encode_to_writer(& mut input, & mut bitmap, &header, options.chunk_size(), true, cancel)?;
+
+    if hash_range.is_some() {
+        let digests = input.digests();
+
+        header = crate::build_header(file_size, options, |_| Ok(digests))?.0;
+
+        bincode::serialize_into(& mut bitmap[..], &header)?;
+    }
+
+    if header.layout() != Layout::Row {
+        header.layout().arrange(header.width(), header.bytes_per_pixel(), header.header_bytes_in_pixels(), header.layout_parameter(), & mut bitmap[header.header_size() as usize..]);
+    }
+
+    if options.bitmap_digest() {
+        crate::seal_bitmap(& mut bitmap);
+    }
+
+    let converted = crate::container_bytes(bitmap, &header, options.container());
+
+    output.write_all(&converted)?;
+
+    Ok(converted.len() as u64)
+}
+
+/// Converts the input held in the temporary file at `path`, writing the result to `output`. Bitmaps are decoded
+/// straight from the file, anything else is encoded into a second temporary file
+fn convert_spilled<W: Write>(path: &Path, output: &mut W, options: &EncodeOptions, verify: Option<VerifyOptions>, cancel: &AtomicBool) -> Result<u64> {
+    if crate::is_b2b_bitmap(path)? {
+        let header = crate::read_header(path)?;
+
+        if let Some(verify) = verify {
+            let mut hash = Blake256::new();
+            let mut crc = Crc32::new();
+
+            crate::read_payload(& mut File::open(path)?, &header, options.chunk_size(), cancel, crate::restrict_to_range(header.hash_range(), |chunk| {
+                hash.update(chunk);
+                crc.update(chunk);
+                Ok(())
+            }))?;
+
+            check_digests(&header, (crate::finalize_hash(hash), crc.finalize()), verify)?;
+        }
+
+        return crate::decode_to_writer(path, output, cancel);
+    }
+
+    //Pngs and raw files are rebuilt into a bitmap in memory anyway
+    if let Some(bitmap) = crate::read_b2b_container(path)? {
+        return decode_bytes(bitmap, output, verify, cancel);
+    }
+
+    let converted = TempFile::new("output");
+
+    //Randomised layouts without a seed take one from the digest, and the layout is applied as the bitmap is written,
+    //so the file has to be hashed first. Otherwise it is hashed as it is written and the header is filled in after
+    if options.layout() == Layout::Shuffled && options.seed().is_none() {
+        let (header, _) = crate::prepare_header(path, options, cancel)?;
+
+        crate::write_new_bitmap(& mut File::open(path)?, converted.path(), &header, options, cancel, || Ok(()))?;
+    } else {
+        let file_size = std::fs::metadata(path)?.len();
+
+        let (header, hash_range) = header_before_hashing(file_size, options)?;
+
+        let mut input = HashingReader::new(File::open(path)?, hash_range.clone().unwrap_or(0..0));
+
+        crate::write_new_bitmap(& mut input, converted.path(), &header, options, cancel, || Ok(()))?;
+
+        if hash_range.is_some() {
+            let digests = input.digests();
+
+            crate::update_header_in_place(converted.path(), &crate::build_header(file_size, options, |_| Ok(digests))?.0, cancel)?;
+        }
+    }
+
+    if options.bitmap_digest() {
+        crate::seal_bitmap_file(converted.path(), cancel)?;
+    }
+
+    crate::apply_container(converted.path(), options.container())?;
+
+    Ok(std::io::copy(& mut File::open(converted.path())?, output)?)
+}
+
+/// Converts everything read from `input` and writes the result to `output`: b2b bitmaps, pngs and raw files are
+/// decoded, anything else is encoded as described by `options`. Inputs of up to `memory_limit` bytes are converted
+/// in memory without touching the disk, larger ones are first written to a temporary file, which is removed again
+/// afterwards. Nothing is written to `output` if verification fails. Returns the number of bytes written
+pub fn convert_stream<R: Read, W: Write>(input: &mut R, output: &mut W, options: &EncodeOptions, verify: Option<VerifyOptions>, memory_limit: u64, cancel: &AtomicBool) -> Result<u64> {
+    let mut data = Vec::new();
+
+    //One byte past the limit tells whether there is more to come
+    input.by_ref().take(memory_limit.saturating_add(1)).read_to_end(& mut data)?;
+
+    let written = if data.len() as u64 <= memory_limit {
+        if is_b2b_bitmap(&data) {
+            decode_bytes(data, output, verify, cancel)?
+        } else if let Some(bitmap) = container_to_bitmap(&data)? {
+            decode_bytes(bitmap, output, verify, cancel)?
+        } else {
+            encode_bytes(&data, output, options, cancel)?
+        }
+    } else {
+        let spilled = TempFile::new("input");
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(spilled.path())?;
+
+        file.write_all(&data)?;
+
+        drop(data);
+
+        std::io::copy(input, & mut file)?;
+
+        drop(file);
+
+        convert_spilled(spilled.path(), output, options, verify, cancel)?
+    };
+
+    output.flush()?;
+
+    Ok(written)
+}
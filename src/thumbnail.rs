@@ -0,0 +1,71 @@
+use crate::error::Result;
+use crate::header::{self, Header, BYTES_PER_PIXEL};
+
+use std::io::Read;
+
+/// Default for the largest dimension of a thumbnail, in pixels
+pub const DEFAULT_THUMBNAIL_SIZE: u32 = 64;
+
+/// Width and height of a thumbnail of a `width` by `height` bitmap, scaled so that neither is larger than
+/// `max_dimension` while keeping the aspect ratio. Bitmaps that already fit are not scaled
+pub fn thumbnail_dimensions(width: u32, height: u32, max_dimension: u32) -> (u32, u32) {
+    let largest = width.max(height) as u64;
+
+    if largest <= max_dimension as u64 {
+        return (width, height);
+    }
+
+    let scale = |n: u32| ((n as u64 * max_dimension as u64) / largest).max(1) as u32;
+
+    (scale(width), scale(height))
+}
+
+/// Shrinks the bitmap described by `header`, whose pixels are read from `pixels`, to a thumbnail no larger than
+/// `max_dimension` in either direction. Each pixel of the thumbnail is the average of the block of pixels it covers.
+/// Returns the thumbnail as a complete bitmap. The pixels are read a row at a time, so large bitmaps are never held in
+/// memory
+pub fn thumbnail<R: Read>(header: &Header, mut pixels: R, max_dimension: u32) -> Result<Vec<u8>> {
+    let (width, height) = (header.width(), header.height());
+    let (thumbnail_width, thumbnail_height) = thumbnail_dimensions(width, height, max_dimension);
+
+    let pixel = header.bytes_per_pixel() as usize;
+    let channels = BYTES_PER_PIXEL as usize;
+
+    //Rows are padded to a multiple of four bytes
+    let mut row = vec![0u8; (width as usize * pixel).div_ceil(4) * 4];
+    let mut sums = vec![0u64; thumbnail_width as usize * channels];
+    let mut counts = vec![0u64; thumbnail_width as usize];
+
+    let mut bitmap = header::bitmap_header_bytes(thumbnail_width, thumbnail_height)?;
+
+    let thumbnail_row = |y: u32| (y as u64 * thumbnail_height as u64 / height as u64) as u32;
+
+    for y in 0..height {
+        pixels.read_exact(&mut row)?;
+
+        for x in 0..width as usize {
+            let column = x * thumbnail_width as usize / width as usize;
+
+            for channel in 0..pixel.min(channels) {
+                sums[column * channels + channel] += row[x * pixel + channel] as u64;
+            }
+
+            counts[column] += 1;
+        }
+
+        //Write out the row of the thumbnail once the last row of the bitmap it covers has been added
+        if y + 1 == height || thumbnail_row(y + 1) != thumbnail_row(y) {
+            for (column, count) in counts.iter_mut().enumerate() {
+                for sum in &mut sums[column * channels..(column + 1) * channels] {
+                    bitmap.push((*sum / (*count).max(1)) as u8);
+
+                    *sum = 0;
+                }
+
+                *count = 0;
+            }
+        }
+    }
+
+    Ok(bitmap)
+}
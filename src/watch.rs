@@ -0,0 +1,118 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::error::Result;
+
+/// How often the watched directory is scanned for new files
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// How long a file's size and modification time must stay the same before it is converted, so files that are
+/// still being written (or copied in several bursts) are not converted half finished
+pub const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Size and modification time of a file that has not settled yet, and when it was first seen with them
+struct Pending {
+    len: u64,
+    modified: Option<SystemTime>,
+    since: Instant,
+}
+
+/// Polls a directory for new files, reporting each one once it has stopped changing. Files already in the
+/// directory when the watcher is created are ignored, as are subdirectories
+pub struct Watcher {
+    dir: PathBuf,
+    pending: HashMap<PathBuf, Pending>,
+    seen: HashSet<PathBuf>,
+}
+
+impl Watcher {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Result<Self> {
+        let mut watcher = Self {
+            dir: dir.as_ref().to_path_buf(),
+            pending: HashMap::new(),
+            seen: HashSet::new(),
+        };
+
+        watcher.seen = watcher.files()?.into_iter().map(|(path, _)| path).collect();
+
+        Ok(watcher)
+    }
+
+    /// Regular files in the watched directory, with their metadata
+    fn files(&self) -> Result<Vec<(PathBuf, std::fs::Metadata)>> {
+        let mut files = Vec::new();
+
+        for entry in std::fs::read_dir(&self.dir)? {
+            let entry = entry?;
+
+            //Files can be removed between listing the directory and reading their metadata
+            if let Ok(metadata) = entry.metadata() {
+                if metadata.is_file() {
+                    files.push((entry.path(), metadata));
+                }
+            }
+        }
+
+        Ok(files)
+    }
+
+    /// Scans the directory once, returning the new files whose size and modification time have not changed
+    /// for at least `settle`. Each file is only returned once
+    pub fn poll(&mut self, settle: Duration) -> Result<Vec<PathBuf>> {
+        let now = Instant::now();
+
+        let mut ready = Vec::new();
+        let mut present = HashSet::new();
+
+        for (path, metadata) in self.files()? {
+            if self.seen.contains(&path) {
+                continue;
+            }
+
+            present.insert(path.clone());
+
+            let len = metadata.len();
+            let modified = metadata.modified().ok();
+
+            match self.pending.get_mut(&path) {
+                Some(pending) if pending.len == len && pending.modified == modified => {
+                    if now.duration_since(pending.since) >= settle {
+                        ready.push(path);
+                    }
+                }
+                Some(pending) => {
+                    *pending = Pending { len, modified, since: now };
+                }
+                None => {
+                    self.pending.insert(path, Pending { len, modified, since: now });
+                }
+            }
+        }
+
+        //Forget files that were removed before they settled
+        self.pending.retain(|path, _| present.contains(path));
+
+        for path in &ready {
+            self.pending.remove(path);
+            self.seen.insert(path.clone());
+        }
+
+        Ok(ready)
+    }
+
+    /// Polls the directory every `POLL_INTERVAL` until `cancel` is set, passing each new file to `process`
+    /// once it has settled
+    pub fn run<F: FnMut(&Path)>(&mut self, cancel: &AtomicBool, mut process: F) -> Result<()> {
+        while !cancel.load(Ordering::Relaxed) {
+            for path in self.poll(SETTLE_TIME)? {
+                process(&path);
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+
+        Ok(())
+    }
+}
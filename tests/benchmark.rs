@@ -0,0 +1,35 @@
+//! `--benchmark` converts buffers in memory and reports the throughput of each step
+
+mod common;
+
+use common::{scratch_dir, run_b2b};
+
+#[test]
+fn reports_nonzero_throughput_without_touching_the_disk() {
+    let dir = scratch_dir("benchmark");
+
+    let output = run_b2b(&dir, ["--benchmark", "--fast"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let mut lines = stdout.lines();
+
+    assert!(lines.next().unwrap().starts_with("Size"), "{}", stdout);
+
+    let rows: Vec<&str> = lines.collect();
+
+    assert_eq!(rows.len(), 3, "{}", stdout);
+
+    for row in rows {
+        let throughputs: Vec<f64> = row.split_whitespace().skip(2).map(|column| column.parse().unwrap()).collect();
+
+        assert_eq!(throughputs.len(), 3, "{}", row);
+        assert!(throughputs.iter().all(|&throughput| throughput > 0.0), "{}", row);
+    }
+
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
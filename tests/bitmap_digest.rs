@@ -0,0 +1,96 @@
+//! `--bitmap-digest` stores a digest of the whole bitmap, which `--check-bitmap` uses to detect a change to any byte
+
+mod common;
+
+use std::path::Path;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Encodes `data` with `args` in `dir`, returning the bitmap
+fn encode(dir: &Path, data: &[u8], args: &[&str]) -> Vec<u8> {
+    std::fs::write(dir.join("input"), data).unwrap();
+
+    let output = run_b2b(dir, args.iter().chain(&["input"]));
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    std::fs::remove_file(dir.join("input")).unwrap();
+
+    std::fs::read(dir.join("input.bmp")).unwrap()
+}
+
+#[test]
+fn flipping_any_byte_is_detected() {
+    let dir = scratch_dir("bitmap-digest-flip");
+
+    let bitmap = encode(&dir, &input(3000, 120), &["--bitmap-digest", "--shuffle", "9"]);
+
+    let checked = run_b2b(&dir, ["--check-bitmap", "input.bmp"]);
+
+    assert!(checked.status.success());
+    assert!(String::from_utf8_lossy(&checked.stdout).contains("matches"));
+
+    //Every byte of the headers, including the stored digest itself, then a sample of the payload and padding
+    let offsets = (0..206).chain((206..bitmap.len()).step_by(97)).chain(Some(bitmap.len() - 1));
+
+    for offset in offsets {
+        let mut tampered = bitmap.clone();
+
+        tampered[offset] ^= 0x01;
+
+        std::fs::write(dir.join("input.bmp"), &tampered).unwrap();
+
+        let checked = run_b2b(&dir, ["--check-bitmap", "input.bmp"]);
+
+        assert!(!checked.status.success(), "flipping byte {} was not detected", offset);
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn bitmap_digest_survives_changing_the_stored_digest() {
+    let dir = scratch_dir("bitmap-digest-strip");
+
+    let data = input(2000, 121);
+
+    encode(&dir, &data, &["--bitmap-digest"]);
+
+    assert!(run_b2b(&dir, ["--strip-digest", "input.bmp"]).status.success());
+    assert!(run_b2b(&dir, ["--check-bitmap", "input.bmp"]).status.success());
+
+    assert!(run_b2b(&dir, ["--add-digest", "input.bmp"]).status.success());
+    assert!(run_b2b(&dir, ["--check-bitmap", "input.bmp"]).status.success());
+
+    assert!(run_b2b(&dir, ["input.bmp"]).status.success());
+    assert_eq!(std::fs::read(dir.join("input")).unwrap(), data);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn missing_bitmap_digest_only_fails_in_strict_mode() {
+    let dir = scratch_dir("bitmap-digest-missing");
+
+    encode(&dir, &input(1000, 122), &[]);
+
+    let lenient = run_b2b(&dir, ["--check-bitmap", "input.bmp"]);
+
+    assert!(lenient.status.success());
+    assert!(String::from_utf8_lossy(&lenient.stdout).contains("doesn't contain a bitmap digest"));
+
+    assert_eq!(run_b2b(&dir, ["--check-bitmap", "--strict", "input.bmp"]).status.code(), Some(2));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn other_containers_are_refused() {
+    let dir = scratch_dir("bitmap-digest-png");
+
+    std::fs::write(dir.join("input"), input(1000, 123)).unwrap();
+
+    assert_eq!(run_b2b(&dir, ["--bitmap-digest", "--container", "png", "input"]).status.code(), Some(64));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
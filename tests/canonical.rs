@@ -0,0 +1,56 @@
+//! Bitmaps written with `--canonical` should share block aligned data with bitmaps of similar files
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+const BLOCK: usize = 4096;
+
+/// Encodes `data` with `--canonical` and returns the bitmap
+fn encode_canonical(name: &str, data: &[u8]) -> Vec<u8> {
+    let dir = scratch_dir(&format!("canonical-{}", name));
+
+    std::fs::write(dir.join("input"), data).unwrap();
+
+    let encoded = run_b2b(&dir, ["--canonical", "input"]);
+
+    assert!(encoded.status.success(), "encoding {} failed: {}", name, String::from_utf8_lossy(&encoded.stderr));
+
+    let bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    bitmap
+}
+
+#[test]
+fn common_prefix_shares_aligned_blocks() {
+    let prefix = input(16 * BLOCK, 1);
+
+    let first = [prefix.as_slice(), &input(3000, 2)].concat();
+    let second = [prefix.as_slice(), &input(10_000, 3)].concat();
+
+    let first = encode_canonical("first", &first);
+    let second = encode_canonical("second", &second);
+
+    //The first block holds the headers, which differ, and every block after it holds the same part of the prefix
+    assert_ne!(first[..BLOCK], second[..BLOCK]);
+
+    for block in 1..16 {
+        let range = block * BLOCK..(block + 1) * BLOCK;
+
+        assert_eq!(first[range.clone()], second[range], "block {} differs", block);
+    }
+}
+
+#[test]
+fn headers_fill_the_first_block() {
+    let bitmap = encode_canonical("header", &input(20_000, 4));
+
+    let data = input(20_000, 4);
+
+    //Everything after the headers in the first block is zero, and the file data starts on the second block
+    assert!(bitmap[206..BLOCK].iter().all(|&byte| byte == 0));
+
+    assert_eq!(bitmap[BLOCK..20_000], data[BLOCK..]);
+}
@@ -0,0 +1,68 @@
+//! `--chunk-size` only changes the size of the buffer used to copy file data, never the bitmap or the decoded file
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Chunk sizes to compare: a single byte, one that divides nothing evenly, one smaller than the header region and
+/// one larger than the whole file
+const CHUNK_SIZES: [&str; 4] = ["1", "7", "100", "1048576"];
+
+/// Encodes and decodes `data` with each chunk size, passing `args` as well, and checks the results are identical
+fn same_output(name: &str, data: &[u8], args: &[&str]) {
+    let dir = scratch_dir(&format!("chunk-{}", name));
+
+    let mut bitmaps = Vec::new();
+
+    for size in CHUNK_SIZES {
+        std::fs::write(dir.join("input"), data).unwrap();
+
+        let encoded = run_b2b(&dir, [&["--chunk-size", size][..], args, &["input"]].concat());
+
+        assert!(encoded.status.success(), "{}: {}", size, String::from_utf8_lossy(&encoded.stderr));
+
+        bitmaps.push(std::fs::read(dir.join("input.bmp")).unwrap());
+
+        let _ = std::fs::remove_file(dir.join("input"));
+
+        let decoded = run_b2b(&dir, [&["--chunk-size", size][..], args, &["input.bmp"]].concat());
+
+        assert!(decoded.status.success(), "{}: {}", size, String::from_utf8_lossy(&decoded.stderr));
+        assert_eq!(std::fs::read(dir.join("input")).unwrap(), data, "{}", size);
+
+        let _ = std::fs::remove_file(dir.join("input.bmp"));
+    }
+
+    for (bitmap, size) in bitmaps.iter().zip(CHUNK_SIZES).skip(1) {
+        assert!(bitmap == &bitmaps[0], "chunk size {} wrote a different bitmap", size);
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn chunk_size_does_not_change_the_output() {
+    same_output("copy", &input(20_000, 170), &[]);
+}
+
+#[test]
+fn chunk_size_does_not_change_the_output_in_place() {
+    same_output("in-place", &input(20_000, 171), &["--in-place"]);
+}
+
+#[test]
+fn chunk_size_does_not_change_the_output_of_small_files() {
+    same_output("small", &input(150, 172), &[]);
+}
+
+#[test]
+fn zero_chunk_size_is_rejected() {
+    let dir = scratch_dir("chunk-zero");
+
+    std::fs::write(dir.join("input"), input(100, 173)).unwrap();
+
+    assert_eq!(run_b2b(&dir, ["--chunk-size", "0", "input"]).status.code(), Some(64));
+    assert!(!dir.join("input.bmp").exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,83 @@
+//! `--collect-errors` lists the outcome of every file in a batch as JSON
+
+mod common;
+
+use serde_json::Value;
+
+use common::{input, scratch_dir, run_b2b};
+
+#[test]
+fn statuses_for_a_run_with_one_failure() {
+    let dir = scratch_dir("collect-errors");
+
+    std::fs::create_dir(dir.join("tree")).unwrap();
+    std::fs::write(dir.join("tree/plain"), input(2000, 190)).unwrap();
+    std::fs::write(dir.join("tree/corrupt"), input(3000, 191)).unwrap();
+
+    assert!(run_b2b(&dir, ["tree/corrupt"]).status.success());
+
+    std::fs::remove_file(dir.join("tree/corrupt")).unwrap();
+
+    //Make the stored size of the original one byte too large
+    let mut bitmap = std::fs::read(dir.join("tree/corrupt.bmp")).unwrap();
+
+    bitmap[0x8A + 4] = bitmap[0x8A + 4].wrapping_add(1);
+
+    std::fs::write(dir.join("tree/corrupt.bmp"), &bitmap).unwrap();
+
+    let output = run_b2b(&dir, ["--collect-errors", "statuses.json", "tree"]);
+
+    let statuses: Value = serde_json::from_slice(&std::fs::read(dir.join("statuses.json")).unwrap()).unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(output.status.code(), Some(3));
+
+    let statuses = statuses.as_array().unwrap();
+
+    assert_eq!(statuses.len(), 2, "{:?}", statuses);
+
+    let corrupt = &statuses[0];
+
+    assert_eq!(corrupt["path"], "tree/corrupt.bmp");
+    assert_eq!(corrupt["direction"], "decode");
+    assert_eq!(corrupt["ok"], false);
+    assert!(corrupt["error"].as_str().unwrap().contains("invalid original file size"), "{}", corrupt);
+    assert_eq!(corrupt["exit_code_hint"], 3);
+
+    let plain = &statuses[1];
+
+    assert_eq!(plain["path"], "tree/plain");
+    assert_eq!(plain["direction"], "encode");
+    assert_eq!(plain["ok"], true);
+    assert!(plain["error"].is_null());
+    assert!(plain["exit_code_hint"].is_null());
+}
+
+#[test]
+fn statuses_on_stdout_are_the_only_output() {
+    let dir = scratch_dir("collect-errors-stdout");
+
+    std::fs::create_dir(dir.join("tree")).unwrap();
+    std::fs::write(dir.join("tree/plain"), input(2000, 192)).unwrap();
+    std::fs::write(dir.join("tree/encoded"), input(3000, 193)).unwrap();
+
+    assert!(run_b2b(&dir, ["tree/encoded"]).status.success());
+
+    std::fs::remove_file(dir.join("tree/encoded")).unwrap();
+
+    //Converting, verifying, the report and the stats all print progress
+    let output = run_b2b(&dir, ["--collect-errors", "-", "--report", "--stats", "tree"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let statuses: Value = serde_json::from_slice(&output.stdout).unwrap_or_else(|e| panic!("{}: {}", e, String::from_utf8_lossy(&output.stdout)));
+
+    assert_eq!(statuses.as_array().unwrap().len(), 2, "{:?}", statuses);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(stderr.contains("Converted tree/plain") && stderr.contains("Verification successful."), "{}", stderr);
+}
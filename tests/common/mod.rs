@@ -0,0 +1,37 @@
+//! Helpers shared by the integration tests. Each test crate only uses some of them
+
+#![allow(dead_code)]
+
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// Deterministic, incompressible looking input of `len` bytes
+pub fn input(len: usize, seed: u32) -> Vec<u8> {
+    let mut state = seed;
+
+    (0..len).map(|_| {
+        state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+        (state >> 16) as u8
+    }).collect()
+}
+
+/// Empty directory for a single test case, so the cases can run in parallel. `name` must be unique across the tests
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("b2b-test-{}-{}", std::process::id(), name));
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    std::fs::create_dir_all(&dir).unwrap();
+
+    dir
+}
+
+/// Runs b2b with `args` from `dir`, so a b2b.toml in the working directory can't change the options
+pub fn run_b2b<I: IntoIterator<Item = S>, S: AsRef<OsStr>>(dir: &Path, args: I) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_rust_b2b"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
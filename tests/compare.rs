@@ -0,0 +1,79 @@
+//! `--compare` decodes two bitmaps in memory and reports whether the files they contain are the same
+
+mod common;
+
+use std::path::Path;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Encodes `data` into `dir/<name>.bmp` with `args`
+fn encode(dir: &Path, name: &str, data: &[u8], args: &[&str]) {
+    std::fs::write(dir.join(name), data).unwrap();
+
+    let encoded = run_b2b(dir, args.iter().chain(&[name]));
+
+    assert!(encoded.status.success(), "encoding failed: {}", String::from_utf8_lossy(&encoded.stderr));
+
+    std::fs::remove_file(dir.join(name)).unwrap();
+}
+
+/// Runs `--compare` on two bitmaps in `dir`, checking that neither is modified, and returns what it printed
+fn compare(dir: &Path, first: &str, second: &str) -> String {
+    let before = (std::fs::read(dir.join(first)).unwrap(), std::fs::read(dir.join(second)).unwrap());
+
+    let output = run_b2b(dir, ["--compare", second, first]);
+
+    assert!(output.status.success(), "--compare failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    assert_eq!((std::fs::read(dir.join(first)).unwrap(), std::fs::read(dir.join(second)).unwrap()), before);
+
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn same_file_in_different_layouts_is_identical() {
+    let dir = scratch_dir("compare-identical");
+
+    let data = input(5000, 240);
+
+    encode(&dir, "row", &data, &[]);
+    encode(&dir, "column", &data, &["--layout", "column"]);
+
+    assert_eq!(compare(&dir, "row.bmp", "column.bmp"), "Payloads are identical.\n");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn changed_payload_is_reported_at_its_offset() {
+    let dir = scratch_dir("compare-changed");
+
+    let data = input(5000, 241);
+
+    encode(&dir, "first", &data, &["--fast"]);
+
+    //In the row layout, bytes of the original past the header region are stored at the same offset in the bitmap
+    let mut bitmap = std::fs::read(dir.join("first.bmp")).unwrap();
+
+    bitmap[3000] ^= 0xff;
+
+    std::fs::write(dir.join("second.bmp"), &bitmap).unwrap();
+
+    assert_eq!(compare(&dir, "first.bmp", "second.bmp"), "Payloads differ at offset 3000.\n");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn shorter_file_differs_at_its_end() {
+    let dir = scratch_dir("compare-prefix");
+
+    let data = input(5000, 242);
+
+    encode(&dir, "whole", &data, &[]);
+    encode(&dir, "prefix", &data[..4000], &[]);
+
+    assert_eq!(compare(&dir, "whole.bmp", "prefix.bmp"), "Payloads differ at offset 4000.\n");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
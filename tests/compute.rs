@@ -0,0 +1,80 @@
+//! `--compute-only` prints the same header an actual encode writes, apart from the digests
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+use serde_json::Value;
+
+/// Fields of the header that only depend on the size of the file and the options
+const SIZE_FIELDS: [(&str, &str); 7] = [
+    ("bmp", "width"),
+    ("bmp", "height"),
+    ("bmp", "file_size"),
+    ("bmp", "pixmap_size"),
+    ("b2b", "padding_size"),
+    ("b2b", "header_size"),
+    ("b2b", "original_file_size"),
+];
+
+fn json(output: std::process::Output) -> Value {
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+/// Checks that the header computed for `len` bytes with `args`, both from `--size` and from the file itself, matches
+/// the header of the bitmap encoded with `args`
+fn check_computed(name: &str, len: usize, args: &[&str]) {
+    let dir = scratch_dir(&format!("compute-{}", name));
+
+    std::fs::write(dir.join("input"), input(len, len as u32)).unwrap();
+
+    let size = len.to_string();
+
+    let from_size = json(run_b2b(&dir, [&["--compute-only", "--size", &size], args].concat()));
+    let from_file = json(run_b2b(&dir, [&["--compute-only"], args, &["input"]].concat()));
+
+    let encoded = run_b2b(&dir, [args, &["input"]].concat());
+
+    assert!(encoded.status.success(), "encoding {} failed: {}", name, String::from_utf8_lossy(&encoded.stderr));
+
+    let written = json(run_b2b(&dir, ["--info", "--json", "input.bmp"]));
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    for (part, field) in SIZE_FIELDS {
+        assert_eq!(from_size[part][field], written[part][field], "{}.{} differs for {}", part, field, name);
+        assert_eq!(from_file[part][field], written[part][field], "{}.{} differs for {}", part, field, name);
+    }
+
+    assert!(from_size["b2b"]["od"].is_null(), "computed header has a digest for {}", name);
+}
+
+#[test]
+fn computed_header_matches_an_encode() {
+    check_computed("small", 5000, &[]);
+}
+
+#[test]
+fn computed_header_for_a_file_smaller_than_the_header() {
+    check_computed("tiny", 100, &[]);
+}
+
+#[test]
+fn computed_header_follows_the_options() {
+    check_computed("canonical", 70_000, &["--canonical"]);
+    check_computed("min-dimension", 3000, &["--min-dimension", "200"]);
+    check_computed("max-dimension", 50_000, &["--max-dimension", "150"]);
+}
+
+#[test]
+fn size_requires_compute_only() {
+    let dir = scratch_dir("compute-size-alone");
+
+    let output = run_b2b(&dir, ["--size", "100"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(output.status.code(), Some(64));
+}
@@ -0,0 +1,108 @@
+//! Defaults read from `b2b.toml` in the working directory, or the file given by `--config`, which flags given on the
+//! command line override
+
+mod common;
+
+use std::path::Path;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Encodes a file in `dir` with `args` and returns the `field` that `--info` reports for the bitmap
+fn encoded_field(dir: &Path, args: &[&str], field: &str) -> String {
+    std::fs::write(dir.join("input"), input(3000, 210)).unwrap();
+
+    let _ = std::fs::remove_file(dir.join("input.bmp"));
+
+    let encoded = run_b2b(dir, args.iter().chain(&["input"]));
+
+    assert!(encoded.status.success(), "{:?} failed: {}", args, String::from_utf8_lossy(&encoded.stderr));
+
+    let info = String::from_utf8(run_b2b(dir, ["--info", "input.bmp"]).stdout).unwrap();
+
+    info.lines().find_map(|line| line.strip_prefix(field)?.strip_prefix(':')).unwrap().trim().to_string()
+}
+
+/// Encodes a file in `dir` with `args` and returns the layout `--info` reports for the bitmap
+fn encoded_layout(dir: &Path, args: &[&str]) -> String {
+    encoded_field(dir, args, "Layout")
+}
+
+#[test]
+fn config_gives_defaults_that_flags_override() {
+    let dir = scratch_dir("config-override");
+
+    assert_eq!(encoded_layout(&dir, &[]), "Row");
+
+    std::fs::write(dir.join("b2b.toml"), "layout = \"column\"\n").unwrap();
+
+    assert_eq!(encoded_layout(&dir, &[]), "Column");
+    assert_eq!(encoded_layout(&dir, &["--layout", "interleaved"]), "Interleaved");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn flags_override_conflicting_config_defaults() {
+    let dir = scratch_dir("config-conflict");
+
+    //--strict conflicts with --fast, so the default is dropped and a digest is stored
+    std::fs::write(dir.join("b2b.toml"), "fast = true\n").unwrap();
+
+    assert_eq!(encoded_field(&dir, &[], "Digest"), "none");
+    assert_ne!(encoded_field(&dir, &["--strict"], "Digest"), "none");
+
+    //--seed and --layout each override --shuffle
+    std::fs::write(dir.join("b2b.toml"), "shuffle = 5\n").unwrap();
+
+    assert_eq!(encoded_layout(&dir, &[]), "Shuffled (seed 5)");
+    assert_eq!(encoded_layout(&dir, &["--seed", "3"]), "Row");
+    assert_eq!(encoded_layout(&dir, &["--layout", "column"]), "Column");
+
+    //And --shuffle overrides --seed and --layout
+    std::fs::write(dir.join("b2b.toml"), "seed = 3\nlayout = \"column\"\n").unwrap();
+
+    assert_eq!(encoded_layout(&dir, &["--shuffle", "5"]), "Shuffled (seed 5)");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn in_place_is_not_a_config_option() {
+    let dir = scratch_dir("config-in-place");
+
+    std::fs::write(dir.join("input"), input(100, 212)).unwrap();
+    std::fs::write(dir.join("b2b.toml"), "in-place = true\n").unwrap();
+
+    let output = run_b2b(&dir, ["input"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(output.status.code(), Some(64));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown option 'in-place'"));
+}
+
+#[test]
+fn config_flag_names_the_file() {
+    let dir = scratch_dir("config-flag");
+
+    std::fs::write(dir.join("defaults.toml"), "layout = \"interleaved\"\n").unwrap();
+
+    assert_eq!(encoded_layout(&dir, &["--config", "defaults.toml"]), "Interleaved");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn invalid_config_is_a_usage_error() {
+    let dir = scratch_dir("config-invalid");
+
+    std::fs::write(dir.join("input"), input(100, 211)).unwrap();
+    std::fs::write(dir.join("b2b.toml"), "colour = true\n").unwrap();
+
+    let output = run_b2b(&dir, ["input"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(output.status.code(), Some(64));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown option 'colour'"));
+}
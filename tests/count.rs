@@ -0,0 +1,95 @@
+//! `--count` reports how many files in a tree are already b2b files, without converting anything
+
+mod common;
+
+use std::path::Path;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Builds a tree of three plain files and three b2b files, one in each container
+fn create_mixed_tree(dir: &Path) {
+    let tree = dir.join("tree");
+
+    std::fs::create_dir_all(tree.join("nested/deeper")).unwrap();
+
+    for (i, name) in ["plain", "nested/plain.txt", "nested/deeper/plain.bin", "bitmap", "nested/png", "nested/deeper/raw"].iter().enumerate() {
+        std::fs::write(tree.join(name), input(700, 60 + i as u32)).unwrap();
+    }
+
+    for (name, container) in [("tree/bitmap", "bmp"), ("tree/nested/png", "png"), ("tree/nested/deeper/raw", "raw")] {
+        let output = run_b2b(dir, ["--container", container, name]);
+
+        assert!(output.status.success(), "encoding {} failed: {}", name, String::from_utf8_lossy(&output.stderr));
+
+        std::fs::remove_file(dir.join(name)).unwrap();
+    }
+
+    //A bitmap that was not made by b2b is a plain file
+    let mut foreign = std::fs::read(tree.join("bitmap.bmp")).unwrap();
+    foreign[0x8A + 8] ^= 0xFF;
+    std::fs::write(tree.join("foreign.bmp"), foreign).unwrap();
+}
+
+/// Every file in `dir`, with its contents
+fn snapshot(dir: &Path) -> Vec<(std::path::PathBuf, Vec<u8>)> {
+    let mut files = Vec::new();
+    let mut directories = vec![dir.to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+
+            if path.is_dir() {
+                directories.push(path);
+            } else {
+                files.push((path.clone(), std::fs::read(&path).unwrap()));
+            }
+        }
+    }
+
+    files.sort();
+    files
+}
+
+#[test]
+fn counts_a_known_mix_without_modifying_it() {
+    let dir = scratch_dir("count-mix");
+
+    create_mixed_tree(&dir);
+
+    let before = snapshot(&dir);
+
+    let output = run_b2b(&dir, ["--count", "tree"]);
+
+    assert!(output.status.success(), "--count failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "B2B files:   3\nOther files: 4\nUnreadable:  0\n");
+
+    assert_eq!(snapshot(&dir), before, "--count modified the tree");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn verbose_lists_every_file() {
+    let dir = scratch_dir("count-verbose");
+
+    create_mixed_tree(&dir);
+
+    let output = run_b2b(&dir, ["--count", "--verbose", "--exclude", "plain*", "tree"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    let listed: Vec<&str> = stdout.lines().take_while(|line| !line.starts_with("B2B files")).collect();
+
+    assert_eq!(listed, [
+        "b2b    tree/bitmap.bmp",
+        "plain  tree/foreign.bmp",
+        "b2b    tree/nested/deeper/raw.b2b",
+        "b2b    tree/nested/png.png",
+    ]);
+
+    assert!(stdout.ends_with("B2B files:   3\nOther files: 1\nUnreadable:  0\n"), "{}", stdout);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,83 @@
+//! Converting in place to a directory on another filesystem, where the bitmap can't simply be renamed into place
+
+#![cfg(unix)]
+
+mod common;
+
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use common::{input, scratch_dir};
+
+/// A directory on a different filesystem to `dir`, if the machine has one
+fn other_filesystem(dir: &Path, name: &str) -> Option<std::path::PathBuf> {
+    let shm = Path::new("/dev/shm");
+
+    if !shm.is_dir() || shm.metadata().ok()?.dev() == dir.metadata().ok()?.dev() {
+        return None;
+    }
+
+    let other = shm.join(format!("b2b-test-{}-{}", std::process::id(), name));
+
+    let _ = std::fs::remove_dir_all(&other);
+
+    std::fs::create_dir_all(&other).ok()?;
+
+    Some(other)
+}
+
+#[test]
+fn in_place_watch_output_on_another_filesystem() {
+    let dir = scratch_dir("cross-device");
+
+    let output = match other_filesystem(&dir, "cross-device") {
+        Some(output) => output,
+        None => {
+            eprintln!("Skipped, no second filesystem");
+
+            return;
+        }
+    };
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust_b2b"))
+        .args(["--in-place", "--watch", "."])
+        .arg("--watch-output")
+        .arg(&output)
+        .current_dir(&dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    //Files already in the directory are ignored, so give the watcher time to take stock first
+    std::thread::sleep(Duration::from_millis(500));
+
+    let data = input(5000, 130);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    let bitmap = output.join("input.bmp");
+    let start = Instant::now();
+
+    while (!bitmap.exists() || dir.join("input").exists()) && start.elapsed() < Duration::from_secs(20) {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+
+    let _ = child.kill();
+    let _ = child.wait();
+
+    assert!(bitmap.exists(), "the bitmap was not moved to the other filesystem");
+    assert!(!dir.join("input").exists(), "the original was left behind");
+    assert_eq!(std::fs::read_dir(&output).unwrap().count(), 1, "a temporary file was left behind");
+
+    //The bitmap decodes back to the original
+    let decoded = common::run_b2b(&output, ["input.bmp"]);
+
+    assert!(decoded.status.success(), "{}", String::from_utf8_lossy(&decoded.stderr));
+    assert_eq!(std::fs::read(output.join("input")).unwrap(), data);
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_dir_all(&output);
+}
@@ -0,0 +1,66 @@
+//! `--data` and `--data-file` encode bytes given directly, writing the bitmap to stdout
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Writes `bitmap` to `dir` and converts it back, returning the decoded file
+fn decode(dir: &std::path::Path, bitmap: &[u8]) -> Vec<u8> {
+    std::fs::write(dir.join("out.bmp"), bitmap).unwrap();
+
+    let decoded = run_b2b(dir, ["out.bmp"]);
+
+    assert!(decoded.status.success(), "{}", String::from_utf8_lossy(&decoded.stderr));
+
+    std::fs::read(dir.join("out")).unwrap()
+}
+
+#[test]
+fn hex_data_round_trips() {
+    let dir = scratch_dir("data-hex");
+
+    let encoded = run_b2b(&dir, ["--data", "48656c6c6f2c20623262"]);
+
+    assert!(encoded.status.success(), "{}", String::from_utf8_lossy(&encoded.stderr));
+    assert_eq!(&encoded.stdout[..2], b"BM");
+
+    assert_eq!(decode(&dir, &encoded.stdout), b"Hello, b2b");
+
+    //The same bytes always give the same bitmap
+    assert_eq!(run_b2b(&dir, ["--data", "48656C6C6F2C20623262"]).stdout, encoded.stdout);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn data_file_is_encoded_even_if_it_is_a_bitmap() {
+    let dir = scratch_dir("data-file");
+
+    std::fs::write(dir.join("input"), input(500, 210)).unwrap();
+
+    assert!(run_b2b(&dir, ["input"]).status.success());
+
+    let bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    let encoded = run_b2b(&dir, ["--data-file", "input.bmp"]);
+
+    assert!(encoded.status.success(), "{}", String::from_utf8_lossy(&encoded.stderr));
+
+    assert_eq!(decode(&dir, &encoded.stdout), bitmap);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn invalid_hex_is_rejected() {
+    let dir = scratch_dir("data-invalid");
+
+    for hex in ["abc", "zz", "12 4"] {
+        let output = run_b2b(&dir, ["--data", hex]);
+
+        assert_eq!(output.status.code(), Some(64), "{}", hex);
+        assert!(output.stdout.is_empty());
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
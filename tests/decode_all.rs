@@ -0,0 +1,94 @@
+//! `--decode-all` only decodes files created by b2b, leaving ordinary bitmaps and other files untouched
+
+mod common;
+
+use std::path::Path;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// A 2x2 24 bit bitmap, as any image editor would write it
+fn ordinary_bitmap() -> Vec<u8> {
+    let mut bitmap = Vec::new();
+
+    bitmap.extend_from_slice(b"BM");
+    bitmap.extend_from_slice(&70u32.to_le_bytes());
+    bitmap.extend_from_slice(&0u32.to_le_bytes());
+    bitmap.extend_from_slice(&54u32.to_le_bytes());
+
+    bitmap.extend_from_slice(&40u32.to_le_bytes());
+    bitmap.extend_from_slice(&2i32.to_le_bytes());
+    bitmap.extend_from_slice(&2i32.to_le_bytes());
+    bitmap.extend_from_slice(&1u16.to_le_bytes());
+    bitmap.extend_from_slice(&24u16.to_le_bytes());
+    bitmap.extend_from_slice(&[0u8; 24]);
+
+    //Two rows of two pixels, each row padded to four bytes
+    bitmap.extend_from_slice(&[0, 0, 255, 0, 255, 0, 0, 0, 255, 0, 0, 255, 255, 255, 0, 0]);
+
+    bitmap
+}
+
+#[test]
+fn only_b2b_bitmaps_are_decoded() {
+    let dir = scratch_dir("decode-all");
+    let tree = dir.join("tree");
+
+    std::fs::create_dir_all(tree.join("photos")).unwrap();
+
+    let first = input(900, 70);
+    let second = input(5000, 71);
+
+    std::fs::write(tree.join("first"), &first).unwrap();
+    std::fs::write(tree.join("photos/second"), &second).unwrap();
+
+    for name in ["tree/first", "tree/photos/second"] {
+        assert!(run_b2b(&dir, [name]).status.success());
+
+        std::fs::remove_file(dir.join(name)).unwrap();
+    }
+
+    std::fs::write(tree.join("photos/holiday.bmp"), ordinary_bitmap()).unwrap();
+    std::fs::write(tree.join("icon.bmp"), ordinary_bitmap()).unwrap();
+    std::fs::write(tree.join("notes.txt"), b"not a bitmap at all").unwrap();
+
+    let output = run_b2b(&dir, ["--decode-all", "--stats", "tree"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "b2b failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    assert_eq!(std::fs::read(tree.join("first")).unwrap(), first);
+    assert_eq!(std::fs::read(tree.join("photos/second")).unwrap(), second);
+
+    assert_eq!(std::fs::read(tree.join("photos/holiday.bmp")).unwrap(), ordinary_bitmap());
+    assert_eq!(std::fs::read(tree.join("icon.bmp")).unwrap(), ordinary_bitmap());
+    assert_eq!(std::fs::read(tree.join("notes.txt")).unwrap(), b"not a bitmap at all");
+
+    for skipped in ["icon.bmp", "notes.txt", "holiday.bmp"] {
+        assert!(stdout.lines().any(|line| line.starts_with("Skipped") && line.contains(skipped)), "{} was not reported:\n{}", skipped, stdout);
+    }
+
+    assert!(stdout.contains("Files:                 2\n"), "{}", stdout);
+    assert!(stdout.contains("Skipped files:         3\n"), "{}", stdout);
+
+    for unexpected in ["icon.bmp.bmp", "notes.txt.bmp", "photos/holiday.bmp.bmp"] {
+        assert!(!Path::new(&tree.join(unexpected)).exists(), "{} was encoded", unexpected);
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn single_plain_file_is_skipped() {
+    let dir = scratch_dir("decode-all-single");
+
+    std::fs::write(dir.join("photo.bmp"), ordinary_bitmap()).unwrap();
+
+    let output = run_b2b(&dir, ["--decode-all", "photo.bmp"]);
+
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("Skipped"));
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
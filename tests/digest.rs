@@ -0,0 +1,135 @@
+//! Digests whose top bit is set, which the flag marking a stored digest takes the place of
+
+mod common;
+
+use blake_hash::{Blake256, Digest};
+
+use common::{input, scratch_dir, run_b2b};
+
+/// First 16 bytes of the Blake256 digest of `data`, as b2b computes it before storing it
+fn digest(data: &[u8]) -> u128 {
+    let mut stored = [0u8; 16];
+
+    stored.copy_from_slice(&Blake256::digest(data)[..16]);
+
+    u128::from_be_bytes(stored)
+}
+
+/// An input whose digest has its top bit set
+fn input_with_top_bit_set() -> (Vec<u8>, u128) {
+    (0..).map(|seed| input(1000, seed))
+        .map(|data| {
+            let digest = digest(&data);
+            (data, digest)
+        })
+        .find(|(_, digest)| digest >> 127 == 1)
+        .unwrap()
+}
+
+#[test]
+fn digest_with_top_bit_set_verifies() {
+    let dir = scratch_dir("digest-top-bit");
+
+    let (data, digest) = input_with_top_bit_set();
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    assert!(run_b2b(&dir, ["input"]).status.success());
+
+    //Only the low 127 bits are stored
+    let info = String::from_utf8(run_b2b(&dir, ["--info", "input.bmp"]).stdout).unwrap();
+
+    assert!(info.contains(&format!("Digest:             {:032x}\n", digest & !(1 << 127))), "{}", info);
+
+    std::fs::remove_file(dir.join("input")).unwrap();
+
+    let decoded = run_b2b(&dir, ["input.bmp"]);
+
+    let stdout = String::from_utf8_lossy(&decoded.stdout);
+
+    assert!(decoded.status.success() && stdout.contains("Verification successful"), "{}", stdout);
+    assert_eq!(std::fs::read(dir.join("input")).unwrap(), data);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn added_digest_with_top_bit_set_verifies() {
+    let dir = scratch_dir("digest-top-bit-added");
+
+    let (data, _) = input_with_top_bit_set();
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    assert!(run_b2b(&dir, ["--fast", "input"]).status.success());
+    assert!(run_b2b(&dir, ["--add-digest", "input.bmp"]).status.success());
+
+    let verified = run_b2b(&dir, ["--verify-against", "input", "input.bmp"]);
+
+    assert!(verified.status.success(), "{}", String::from_utf8_lossy(&verified.stderr));
+
+    std::fs::remove_file(dir.join("input")).unwrap();
+
+    let decoded = run_b2b(&dir, ["input.bmp"]);
+
+    assert!(String::from_utf8_lossy(&decoded.stdout).contains("Verification successful"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// Size of the bitmap and b2b headers at the start of a bitmap
+const HEADERS_LEN: usize = 0x8A + 68;
+
+#[test]
+fn changing_the_digest_only_rewrites_the_header() {
+    let dir = scratch_dir("digest-header-only");
+
+    std::fs::write(dir.join("input"), input(5000, 180)).unwrap();
+
+    assert!(run_b2b(&dir, ["input"]).status.success());
+
+    let with_digest = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    assert!(run_b2b(&dir, ["--strip-digest", "input.bmp"]).status.success());
+
+    let stripped = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    assert!(run_b2b(&dir, ["--add-digest", "input.bmp"]).status.success());
+
+    let added = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(stripped.len(), with_digest.len());
+    assert_ne!(stripped[..HEADERS_LEN], with_digest[..HEADERS_LEN]);
+    assert_eq!(stripped[HEADERS_LEN..], with_digest[HEADERS_LEN..]);
+
+    assert_eq!(added, with_digest);
+}
+
+#[test]
+fn changing_the_digest_of_a_first_format_bitmap_only_rewrites_the_header() {
+    let dir = scratch_dir("digest-header-only-legacy");
+
+    //Written by the first release of b2b, whose headers take up 178 bytes (see tests/legacy.rs)
+    let golden = std::fs::read(std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join("baseline.bmp")).unwrap();
+
+    std::fs::write(dir.join("input.bmp"), &golden).unwrap();
+
+    assert!(run_b2b(&dir, ["--strip-digest", "input.bmp"]).status.success());
+
+    let stripped = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    assert!(run_b2b(&dir, ["--add-digest", "input.bmp"]).status.success());
+
+    let added = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(stripped.len(), golden.len());
+    assert_ne!(stripped[..178], golden[..178]);
+    assert_eq!(stripped[178..], golden[178..]);
+
+    assert_eq!(added[178..], golden[178..]);
+    assert_eq!(added[0x8A..178], golden[0x8A..178]);
+}
@@ -0,0 +1,58 @@
+//! `--max-dimension` caps the width and height of the bitmap
+
+mod common;
+
+use std::path::Path;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Width and height recorded in the bitmap header
+fn dimensions(bitmap: &[u8]) -> (u32, u32) {
+    let field = |offset: usize| u32::from_le_bytes([bitmap[offset], bitmap[offset + 1], bitmap[offset + 2], bitmap[offset + 3]]);
+
+    (field(18), field(22))
+}
+
+fn encode(dir: &Path, max_dimension: &str) -> std::process::Output {
+    run_b2b(dir, ["--max-dimension", max_dimension, "input"])
+}
+
+#[test]
+fn large_payload_respects_the_cap() {
+    let dir = scratch_dir("dimension-cap");
+
+    let data = input(1_000_000, 40);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    let output = encode(&dir, "501");
+
+    assert!(output.status.success(), "b2b failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let (width, height) = dimensions(&std::fs::read(dir.join("input.bmp")).unwrap());
+
+    assert!(width <= 501 && height <= 501, "{}x{} is past the cap", width, height);
+
+    std::fs::remove_file(dir.join("input")).unwrap();
+
+    assert!(run_b2b(&dir, ["input.bmp"]).status.success());
+
+    assert_eq!(std::fs::read(dir.join("input")).unwrap(), data);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn payload_too_large_for_the_cap_fails() {
+    let dir = scratch_dir("dimension-too-large");
+
+    std::fs::write(dir.join("input"), input(1_000_000, 41)).unwrap();
+
+    let output = encode(&dir, "500");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--max-dimension"));
+    assert!(!dir.join("input.bmp").exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,65 @@
+//! The predicted size of the bitmap is printed before encoding, with a warning if it is much larger than the file, and
+//! the conversion is refused if it would be far larger and use a lot of space, unless forced
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Start of the warning printed when the bitmap is more than twice the size of the file
+const WARNING: &str = "Warning: the bitmap will be";
+
+#[test]
+fn tiny_file_warns_about_the_expansion() {
+    let dir = scratch_dir("expansion-tiny");
+
+    std::fs::write(dir.join("input"), input(10, 190)).unwrap();
+
+    let encoded = run_b2b(&dir, ["input"]);
+
+    assert!(encoded.status.success(), "{}", String::from_utf8_lossy(&encoded.stderr));
+    assert!(String::from_utf8_lossy(&encoded.stderr).contains(WARNING), "{}", String::from_utf8_lossy(&encoded.stderr));
+
+    //The prediction is exact
+    let size = std::fs::metadata(dir.join("input.bmp")).unwrap().len();
+
+    assert!(String::from_utf8_lossy(&encoded.stderr).contains(&format!("Predicted size: {} bytes (", size)), "{}", String::from_utf8_lossy(&encoded.stderr));
+    assert!(!String::from_utf8_lossy(&encoded.stdout).contains("Predicted size"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn large_file_does_not_warn() {
+    let dir = scratch_dir("expansion-large");
+
+    std::fs::write(dir.join("input"), input(100_000, 191)).unwrap();
+
+    let encoded = run_b2b(&dir, ["input"]);
+
+    assert!(encoded.status.success());
+    assert!(!String::from_utf8_lossy(&encoded.stderr).contains(WARNING), "{}", String::from_utf8_lossy(&encoded.stderr));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn huge_expansion_needs_force() {
+    let dir = scratch_dir("expansion-force");
+
+    std::fs::write(dir.join("input"), input(100, 192)).unwrap();
+
+    //A bitmap at least 2400 pixels square is well over 16 MiB, for a 100 byte file
+    let refused = run_b2b(&dir, ["--min-dimension", "2400", "input"]);
+
+    assert_eq!(refused.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("pass --force to convert anyway"), "{}", String::from_utf8_lossy(&refused.stderr));
+    assert!(!dir.join("input.bmp").exists());
+
+    let forced = run_b2b(&dir, ["--min-dimension", "2400", "--force", "input"]);
+
+    assert!(forced.status.success(), "{}", String::from_utf8_lossy(&forced.stderr));
+    assert!(String::from_utf8_lossy(&forced.stderr).contains(WARNING));
+    assert!(std::fs::metadata(dir.join("input.bmp")).unwrap().len() > 16 << 20);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,56 @@
+//! Bitmaps are recognised by their contents, so a bitmap converts back whatever it is called. Removing the extension
+//! names the output, and bitmaps without one are given a suffix instead of being written over
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+#[test]
+fn bitmap_without_an_extension_decodes_next_to_itself() {
+    let dir = scratch_dir("extension-none");
+
+    let data = input(2000, 230);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    assert!(run_b2b(&dir, ["input"]).status.success());
+
+    std::fs::rename(dir.join("input.bmp"), dir.join("bitmap")).unwrap();
+
+    let bitmap = std::fs::read(dir.join("bitmap")).unwrap();
+
+    let decoded = run_b2b(&dir, ["bitmap"]);
+
+    assert!(decoded.status.success(), "{}", String::from_utf8_lossy(&decoded.stderr));
+    assert_eq!(std::fs::read(dir.join("bitmap.out")).unwrap(), data);
+    assert_eq!(std::fs::read(dir.join("bitmap")).unwrap(), bitmap);
+
+    //In place, the bitmap is renamed the same way
+    std::fs::remove_file(dir.join("bitmap.out")).unwrap();
+
+    let decoded = run_b2b(&dir, ["--in-place", "bitmap"]);
+
+    assert!(decoded.status.success(), "{}", String::from_utf8_lossy(&decoded.stderr));
+    assert_eq!(std::fs::read(dir.join("bitmap.out")).unwrap(), data);
+    assert!(!dir.join("bitmap").exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn bitmap_with_another_extension_loses_it() {
+    let dir = scratch_dir("extension-other");
+
+    let data = input(2000, 231);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    assert!(run_b2b(&dir, ["--ext", "img", "input"]).status.success());
+
+    std::fs::remove_file(dir.join("input")).unwrap();
+
+    assert!(run_b2b(&dir, ["input.img"]).status.success());
+    assert_eq!(std::fs::read(dir.join("input")).unwrap(), data);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
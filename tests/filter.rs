@@ -0,0 +1,79 @@
+//! `--include` and `--exclude` choose which files in a directory are converted
+
+mod common;
+
+use std::path::{Path, PathBuf};
+
+use common::{scratch_dir, run_b2b};
+
+/// Creates a file at each of `paths` under `dir`
+fn create_tree(dir: &Path, paths: &[&str]) {
+    for path in paths {
+        let path = dir.join(path);
+
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, b"some text to convert").unwrap();
+    }
+}
+
+/// Paths of the bitmaps under `dir`, relative to it
+fn bitmaps(dir: &Path) -> Vec<PathBuf> {
+    let mut found = Vec::new();
+    let mut directories = vec![dir.to_path_buf()];
+
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+
+            if path.is_dir() {
+                directories.push(path);
+            } else if path.extension().is_some_and(|extension| extension == "bmp") {
+                found.push(path.strip_prefix(dir).unwrap().to_path_buf());
+            }
+        }
+    }
+
+    found.sort();
+    found
+}
+
+fn convert(name: &str, args: &[&str]) -> Vec<PathBuf> {
+    let dir = scratch_dir(&format!("filter-{}", name));
+    let tree = dir.join("tree");
+
+    create_tree(&tree, &["a.txt", "b.bin", "notes.TXT", "docs/c.txt", "docs/d.md", "docs/old/e.txt", "build/f.txt"]);
+
+    let output = run_b2b(&dir, args.iter().chain(&["tree"]));
+
+    assert!(output.status.success(), "b2b failed: {}", String::from_utf8_lossy(&output.stderr));
+
+    let found = bitmaps(&tree);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    found
+}
+
+fn paths(paths: &[&str]) -> Vec<PathBuf> {
+    paths.iter().map(PathBuf::from).collect()
+}
+
+#[test]
+fn only_txt_files_are_converted() {
+    assert_eq!(convert("include", &["--include", "*.txt"]), paths(&["a.txt.bmp", "build/f.txt.bmp", "docs/c.txt.bmp", "docs/old/e.txt.bmp"]));
+}
+
+#[test]
+fn excludes_take_precedence() {
+    assert_eq!(convert("exclude", &["--include", "*.txt", "--exclude", "build/**", "--exclude", "docs/*/*"]), paths(&["a.txt.bmp", "docs/c.txt.bmp"]));
+}
+
+#[test]
+fn globs_can_be_combined() {
+    assert_eq!(convert("combined", &["--include", "**/*.[mt][dx]*", "--exclude", "[!d]*/**"]), paths(&["a.txt.bmp", "docs/c.txt.bmp", "docs/d.md.bmp", "docs/old/e.txt.bmp"]));
+}
+
+#[test]
+fn no_filters_convert_everything() {
+    assert_eq!(convert("none", &[]).len(), 7);
+}
@@ -0,0 +1,111 @@
+//! Ordinary bitmaps, which weren't created by b2b, are never converted: they are refused, or skipped with
+//! `--tolerate-non-b2b-bmp`, and left byte for byte as they were
+
+mod common;
+
+use std::path::Path;
+use std::process::Output;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// A 4x2 24 bit bitmap with a BITMAPINFOHEADER, as an image editor would write it
+fn plain_bitmap() -> Vec<u8> {
+    let pixels = input(4 * 2 * 3, 230);
+
+    let mut bitmap = Vec::new();
+
+    bitmap.extend_from_slice(b"BM");
+    bitmap.extend_from_slice(&(54 + pixels.len() as u32).to_le_bytes());
+    bitmap.extend_from_slice(&[0; 4]);
+    bitmap.extend_from_slice(&54u32.to_le_bytes());
+    bitmap.extend_from_slice(&40u32.to_le_bytes());
+    bitmap.extend_from_slice(&4i32.to_le_bytes());
+    bitmap.extend_from_slice(&2i32.to_le_bytes());
+    bitmap.extend_from_slice(&1u16.to_le_bytes());
+    bitmap.extend_from_slice(&24u16.to_le_bytes());
+    bitmap.extend_from_slice(&[0; 24]);
+    bitmap.extend_from_slice(&pixels);
+
+    bitmap
+}
+
+/// Writes `files` to a new directory and runs b2b on `target` with `args`, returning the output and the directory
+fn convert(name: &str, files: &[(&str, &[u8])], args: &[&str], target: &str) -> (Output, std::path::PathBuf) {
+    let dir = scratch_dir(&format!("foreign-{}", name));
+
+    for (file, contents) in files {
+        let path = dir.join(file);
+
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, contents).unwrap();
+    }
+
+    let output = run_b2b(&dir, args.iter().chain(&[target]));
+
+    (output, dir)
+}
+
+fn file_names(dir: &Path) -> Vec<String> {
+    let mut names: Vec<String> = std::fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name().into_string().unwrap()).collect();
+
+    names.sort();
+
+    names
+}
+
+#[test]
+fn plain_bitmap_is_refused() {
+    let bitmap = plain_bitmap();
+
+    let (output, dir) = convert("refused", &[("image.bmp", &bitmap)], &[], "image.bmp");
+
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+    assert!(stderr.contains("not created by b2b"), "{}", stderr);
+
+    assert_eq!(std::fs::read(dir.join("image.bmp")).unwrap(), bitmap);
+    assert_eq!(file_names(&dir), ["image.bmp"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn plain_bitmap_is_skipped_when_tolerated() {
+    let bitmap = plain_bitmap();
+
+    let (output, dir) = convert("tolerated", &[("image.bmp", &bitmap)], &["--tolerate-non-b2b-bmp"], "image.bmp");
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("image.bmp is not a B2B bitmap, leaving it untouched."));
+
+    assert_eq!(std::fs::read(dir.join("image.bmp")).unwrap(), bitmap);
+    assert_eq!(file_names(&dir), ["image.bmp"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn tolerated_bitmaps_are_skipped_in_a_directory() {
+    let bitmap = plain_bitmap();
+    let data = input(1000, 231);
+
+    let (output, dir) = convert("tolerated-dir", &[("tree/image.bmp", &bitmap), ("tree/data", &data)], &["--tolerate-non-b2b-bmp"], "tree");
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    assert_eq!(std::fs::read(dir.join("tree/image.bmp")).unwrap(), bitmap);
+    assert_eq!(file_names(&dir.join("tree")), ["data", "data.bmp", "image.bmp"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn file_starting_with_the_bitmap_id_is_encoded() {
+    let (output, dir) = convert("bm-text", &[("notes.txt", b"BMW service history")], &[], "notes.txt");
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(dir.join("notes.txt.bmp").is_file());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
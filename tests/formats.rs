@@ -0,0 +1,36 @@
+//! `--list-formats` reports what this build supports
+
+mod common;
+
+use common::{scratch_dir, run_b2b};
+
+fn list_formats() -> String {
+    let dir = scratch_dir("formats");
+
+    let listed = run_b2b(&dir, ["--list-formats"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(listed.status.success(), "--list-formats failed: {}", String::from_utf8_lossy(&listed.stderr));
+
+    String::from_utf8(listed.stdout).unwrap()
+}
+
+#[test]
+fn formats_are_listed() {
+    let formats = list_formats();
+
+    assert!(formats.contains("Header:         b2b signature b2b000023c5e91a76d08f4e21b97c0da, 206 bytes\n"), "{}", formats);
+    assert!(formats.contains("Decodes:        b2b signature 06fafec0d7ef10c4468e85b0b9c0fb9e, 178 bytes (first format)\n"), "{}", formats);
+    assert!(formats.contains("Containers:     bmp, png, raw\n"), "{}", formats);
+    assert!(formats.contains("Layouts:        row, column, interleaved, shuffled, tiled\n"), "{}", formats);
+}
+
+#[test]
+fn features_match_the_build() {
+    let formats = list_formats();
+
+    let expected = if cfg!(feature = "profile-memory") { "profile-memory: yes\n" } else { "profile-memory: no\n" };
+
+    assert!(formats.contains(expected), "{}", formats);
+}
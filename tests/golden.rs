@@ -0,0 +1,98 @@
+//! Encodes fixed inputs and compares the bitmaps byte for byte against the golden files in `tests/golden`, so any
+//! change to the on-disk format shows up as a failure. If the change is intended, regenerate the golden files with
+//! `B2B_REGENERATE_GOLDEN=1 cargo test --test golden` and commit them
+
+mod common;
+
+use std::ffi::OsStr;
+use std::path::Path;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Environment variable that makes the tests rewrite the golden files instead of comparing against them
+const REGENERATE: &str = "B2B_REGENERATE_GOLDEN";
+
+/// Encodes `data` with `args` and checks the result against `tests/golden/<name>.<extension>`
+fn check_golden(name: &str, data: &[u8], args: &[&str], extension: &str) {
+    let dir = scratch_dir(&format!("golden-{}", name));
+
+    let input = dir.join("input");
+
+    std::fs::write(&input, data).unwrap();
+
+    let status = run_b2b(&dir, args.iter().map(OsStr::new).chain(Some(input.as_os_str())));
+
+    assert!(status.status.success(), "b2b failed for {}: {}", name, String::from_utf8_lossy(&status.stderr));
+
+    let encoded = std::fs::read(dir.join(format!("input.{}", extension))).unwrap();
+
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.{}", name, extension));
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    if std::env::var_os(REGENERATE).is_some() {
+        std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+
+        std::fs::write(&golden, &encoded).unwrap();
+
+        return;
+    }
+
+    let expected = std::fs::read(&golden).unwrap_or_else(|e| panic!("Can't read {} ({}), set {} to create it", golden.display(), e, REGENERATE));
+
+    if let Some(offset) = encoded.iter().zip(&expected).position(|(a, b)| a != b) {
+        panic!("{} differs from {} at offset {}", name, golden.display(), offset);
+    }
+
+    assert_eq!(encoded.len(), expected.len(), "{} is {} bytes but {} is {}", name, encoded.len(), golden.display(), expected.len());
+}
+
+#[test]
+fn row_layout() {
+    check_golden("row", &input(1000, 1), &[], "bmp");
+}
+
+#[test]
+fn row_layout_without_digest() {
+    check_golden("row_fast", &input(4096, 2), &["--fast"], "bmp");
+}
+
+#[test]
+fn column_layout() {
+    check_golden("column", &input(3001, 3), &["--layout", "column"], "bmp");
+}
+
+#[test]
+fn interleaved_layout() {
+    check_golden("interleaved", &input(2500, 4), &["--layout", "interleaved"], "bmp");
+}
+
+#[test]
+fn shuffled_layout() {
+    check_golden("shuffled", &input(2048, 5), &["--shuffle", "7"], "bmp");
+}
+
+#[test]
+fn honor_offset() {
+    check_golden("honor_offset", &input(1500, 6), &["--honor-offset"], "bmp");
+}
+
+#[test]
+fn min_dimension() {
+    check_golden("min_dimension", &input(300, 7), &["--min-dimension", "20"], "bmp");
+}
+
+#[test]
+fn hash_range() {
+    check_golden("hash_range", &input(1200, 8), &["--hash-range", "100:900"], "bmp");
+}
+
+#[test]
+fn canonical() {
+    check_golden("canonical", &input(6000, 10), &["--canonical"], "bmp");
+}
+
+#[test]
+fn png_container() {
+    check_golden("png", &input(5000, 9), &["--container", "png"], "png");
+}
@@ -0,0 +1,172 @@
+//! `--hexdump-header` prints each field of the headers at the start of a bitmap with its offset, name and bytes, and
+//! the rest of the header region the header declares
+
+mod common;
+
+use std::path::Path;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Offset and name of every field of the bitmap and b2b headers, in the order they are written
+const FIELDS: [(usize, &str); 40] = [
+    (0x00, "bmp.id"),
+    (0x02, "bmp.file_size"),
+    (0x06, "bmp.header_crc"),
+    (0x0a, "bmp.offset"),
+    (0x0e, "bmp.dib_size"),
+    (0x12, "bmp.width"),
+    (0x16, "bmp.height"),
+    (0x1a, "bmp.pbnlanes"),
+    (0x1c, "bmp.bpp"),
+    (0x1e, "bmp.compression"),
+    (0x22, "bmp.pixmap_size"),
+    (0x26, "bmp.horizontal"),
+    (0x2a, "bmp.vertical"),
+    (0x2e, "bmp.palette"),
+    (0x32, "bmp.important"),
+    (0x36, "bmp.red_mask"),
+    (0x3a, "bmp.green_mask"),
+    (0x3e, "bmp.blue_mask"),
+    (0x42, "bmp.alpha_mask"),
+    (0x46, "bmp.win"),
+    (0x4a, "bmp.bitmap_digest"),
+    (0x5a, "bmp.unused2b"),
+    (0x6a, "bmp.unused2c"),
+    (0x6e, "bmp.red_gamma"),
+    (0x72, "bmp.green_gamma"),
+    (0x76, "bmp.blue_gamma"),
+    (0x7a, "bmp.intent"),
+    (0x7e, "bmp.profile_data"),
+    (0x82, "bmp.profile_size"),
+    (0x86, "bmp.reserved"),
+    (0x8a, "b2b.padding_size"),
+    (0x8e, "b2b.original_file_size"),
+    (0x92, "b2b.signature"),
+    (0xa2, "b2b.od"),
+    (0xb2, "b2b.crc"),
+    (0xb6, "b2b.header_size"),
+    (0xba, "b2b.layout"),
+    (0xbe, "b2b.hash_start"),
+    (0xc2, "b2b.hash_end"),
+    (0xc6, "b2b.shuffle_seed"),
+];
+
+/// Size of both headers, where the dump ends
+const TOTAL_HEADER_SIZE: usize = 206;
+
+/// Number of fields in headers of the first format, which end after the digest
+const LEGACY_FIELDS: usize = 34;
+
+/// Runs `--hexdump-header` on the bitmap `name` in `dir`, checking it leaves the bitmap as it was
+fn hexdump_header(dir: &Path, name: &str) -> String {
+    let bitmap = std::fs::read(dir.join(name)).unwrap();
+
+    let dump = run_b2b(dir, ["--hexdump-header", name]);
+
+    assert!(dump.status.success(), "{}", String::from_utf8_lossy(&dump.stderr));
+    assert_eq!(std::fs::read(dir.join(name)).unwrap(), bitmap, "the bitmap was modified");
+
+    String::from_utf8(dump.stdout).unwrap()
+}
+
+/// Checks that each of `lines` is the field of `FIELDS` at the same index, holding the bytes of `bitmap` at its offset,
+/// and returns how many bytes the fields cover
+fn check_fields(lines: &[&str], bitmap: &[u8]) -> usize {
+    let mut dumped = Vec::new();
+
+    for (line, (offset, name)) in lines.iter().zip(FIELDS) {
+        let mut columns = line.split_whitespace();
+
+        assert_eq!(columns.next(), Some(format!("{:08x}", offset).as_str()), "{}", line);
+        assert_eq!(columns.next(), Some(name), "{}", line);
+
+        let bytes: Vec<u8> = columns.map(|byte| u8::from_str_radix(byte, 16).unwrap()).collect();
+
+        //Each field holds the bytes of the bitmap at its offset, and ends where the next one starts
+        assert_eq!(offset, dumped.len(), "{}", line);
+        assert_eq!(bytes, &bitmap[offset..offset + bytes.len()], "{}", line);
+
+        dumped.extend(bytes);
+    }
+
+    dumped.len()
+}
+
+#[test]
+fn fields_are_labelled_at_their_offsets() {
+    let dir = scratch_dir("hexdump-header");
+
+    std::fs::write(dir.join("input"), input(1000, 200)).unwrap();
+
+    assert!(run_b2b(&dir, ["input"]).status.success());
+
+    let bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    let dump = hexdump_header(&dir, "input.bmp");
+
+    let lines: Vec<&str> = dump.lines().collect();
+
+    assert_eq!(lines.len(), FIELDS.len(), "{}", dump);
+    assert_eq!(check_fields(&lines, &bitmap), TOTAL_HEADER_SIZE);
+
+    //A few fields whose values are known
+    assert!(lines[0].ends_with(" 42 4d"), "{}", lines[0]);
+    assert!(lines[1].ends_with(&format!(" {:02x} {:02x} 00 00", bitmap.len() & 0xff, bitmap.len() >> 8)), "{}", lines[1]);
+    assert!(lines[3].ends_with(" 8a 00 00 00"), "{}", lines[3]);
+    assert!(lines[31].ends_with(" e8 03 00 00"), "{}", lines[31]);
+    assert!(lines[32].ends_with(" da c0 97 1b e2 f4 08 6d a7 91 5e 3c 02 00 b0 b2"), "{}", lines[32]);
+    assert!(lines[35].ends_with(" ce 00 00 00"), "{}", lines[35]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn first_format_header_ends_at_the_digest() {
+    let dir = scratch_dir("hexdump-header-legacy");
+
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join("baseline.bmp");
+
+    std::fs::copy(golden, dir.join("baseline.bmp")).unwrap();
+
+    let bitmap = std::fs::read(dir.join("baseline.bmp")).unwrap();
+
+    let dump = hexdump_header(&dir, "baseline.bmp");
+
+    let lines: Vec<&str> = dump.lines().collect();
+
+    //The header is 178 bytes long, so nothing of the file data that follows it is dumped
+    assert_eq!(lines.len(), LEGACY_FIELDS, "{}", dump);
+    assert_eq!(check_fields(&lines, &bitmap), 178);
+
+    assert!(lines[32].ends_with(" 9e fb c0 b9 b0 85 8e 46 c4 10 ef d7 c0 fe fa 06"), "{}", lines[32]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn canonical_header_region_is_dumped_whole() {
+    let dir = scratch_dir("hexdump-header-canonical");
+
+    std::fs::write(dir.join("input"), input(1000, 201)).unwrap();
+
+    assert!(run_b2b(&dir, ["--canonical", "input"]).status.success());
+
+    let bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    let dump = hexdump_header(&dir, "input.bmp");
+
+    let lines: Vec<&str> = dump.lines().collect();
+
+    assert_eq!(check_fields(&lines, &bitmap), TOTAL_HEADER_SIZE);
+
+    assert!(lines[35].ends_with(" 00 10 00 00"), "{}", lines[35]);
+
+    //The zeros filling the rest of the 4096 byte block follow, with the repeated lines left out
+    assert_eq!(&lines[FIELDS.len()..], [
+        "000000ce  00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00  ................",
+        "*",
+        "00000ffe  00 00                                            ..",
+    ]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
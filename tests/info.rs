@@ -0,0 +1,184 @@
+//! Output of `--info` for bitmaps with known contents
+
+mod common;
+
+use common::{scratch_dir, run_b2b};
+
+/// Encodes 300 bytes of "abc" with `args` and returns the output of `--info` with `info_args`
+fn info(name: &str, args: &[&str], info_args: &[&str]) -> String {
+    let dir = scratch_dir(&format!("info-{}", name));
+
+    std::fs::write(dir.join("input"), "abc".repeat(100)).unwrap();
+
+    let encoded = run_b2b(&dir, [args, &["input"]].concat());
+
+    assert!(encoded.status.success(), "encoding failed: {}", String::from_utf8_lossy(&encoded.stderr));
+
+    let info = run_b2b(&dir, [&["--info"], info_args, &["input.bmp"]].concat());
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(info.status.success(), "--info failed: {}", String::from_utf8_lossy(&info.stderr));
+
+    String::from_utf8(info.stdout).unwrap()
+}
+
+/// First 127 bits of the Blake256 digest of "abc" repeated 100 times
+const KNOWN_DIGEST: &str = "607b2df545a4fa14444eb56f35344cd2";
+
+#[test]
+fn digest_is_printed_as_hex() {
+    let info = info("digest", &[], &[]);
+
+    assert!(info.contains(&format!("Digest:             {}\n", KNOWN_DIGEST)), "{}", info);
+}
+
+#[test]
+fn json_digest_is_hex() {
+    let info = info("json", &[], &["--json"]);
+
+    assert!(info.contains(&format!("\"od\": \"{}\"", KNOWN_DIGEST)), "{}", info);
+}
+
+#[test]
+fn missing_digest_is_none() {
+    let info = info("none", &["--fast"], &[]);
+
+    assert!(info.contains("Digest:             none\n"), "{}", info);
+}
+
+/// CRC32 of "abc" repeated 100 times
+const KNOWN_CRC: &str = "d0c4b884";
+
+#[test]
+fn every_field_is_printed() {
+    let info = info("fields", &[], &[]);
+
+    let expected = format!("\
+Dimensions:         10 x 10
+Pixmap size:        400 bytes
+Original file size: 300 bytes
+Padding size:       32 bytes
+Header size:        206 bytes
+Digest:             {}
+CRC32:              {}
+Bitmap digest:      none
+Hash range:         0:300
+Layout:             Row
+Signature:          b2b000023c5e91a76d08f4e21b97c0da
+", KNOWN_DIGEST, KNOWN_CRC);
+
+    assert_eq!(info, expected);
+}
+
+#[test]
+fn options_are_reflected_in_the_fields() {
+    let info = info("options", &["--fast", "--shuffle", "9", "--hash-range", "10:250"], &[]);
+
+    assert!(info.contains("CRC32:              none\n"), "{}", info);
+    assert!(info.contains("Hash range:         10:250\n"), "{}", info);
+    assert!(info.contains("Layout:             Shuffled (seed 9)\n"), "{}", info);
+}
+
+/// Encodes files at `names` (relative to `tree`) in a new tree and adds a plain file that isn't a bitmap
+fn tree(name: &str, names: &[&str]) -> std::path::PathBuf {
+    let dir = scratch_dir(&format!("info-{}", name));
+
+    std::fs::create_dir_all(dir.join("tree/sub")).unwrap();
+
+    for (i, name) in names.iter().enumerate() {
+        std::fs::write(dir.join("tree").join(name), "abc".repeat(100 * (i + 1))).unwrap();
+
+        let encoded = run_b2b(&dir, [format!("tree/{}", name)]);
+
+        assert!(encoded.status.success(), "encoding failed: {}", String::from_utf8_lossy(&encoded.stderr));
+    }
+
+    std::fs::write(dir.join("tree/notes.txt"), "not a bitmap").unwrap();
+
+    dir
+}
+
+#[test]
+fn recursive_info_lists_every_bitmap() {
+    let dir = tree("recursive", &["a", "sub/b"]);
+
+    let info = run_b2b(&dir, ["--info", "--recursive", "tree"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(info.status.success(), "--info failed: {}", String::from_utf8_lossy(&info.stderr));
+
+    let info = String::from_utf8(info.stdout).unwrap();
+    let lines: Vec<_> = info.lines().collect();
+
+    assert_eq!(lines.len(), 3, "{}", info);
+    assert!(lines[0].starts_with("Path"), "{}", info);
+    assert!(lines[1].starts_with("tree/a.bmp") && lines[1].contains(" 300  ") && lines[1].ends_with(KNOWN_DIGEST), "{}", info);
+    assert!(lines[2].starts_with("tree/sub/b.bmp") && lines[2].contains(" 600  "), "{}", info);
+    assert!(!info.contains("notes.txt"), "{}", info);
+}
+
+#[test]
+fn recursive_info_as_json() {
+    let dir = tree("recursive-json", &["a", "sub/b"]);
+
+    let info = run_b2b(&dir, ["--info", "--all", "--json", "tree"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(info.status.success(), "--info failed: {}", String::from_utf8_lossy(&info.stderr));
+
+    let info = String::from_utf8(info.stdout).unwrap();
+
+    assert!(info.trim_start().starts_with('['), "{}", info);
+    assert!(info.contains("\"path\": \"tree/a.bmp\""), "{}", info);
+    assert!(info.contains("\"path\": \"tree/sub/b.bmp\""), "{}", info);
+    assert!(info.contains(&format!("\"od\": \"{}\"", KNOWN_DIGEST)), "{}", info);
+    assert!(!info.contains("notes.txt"), "{}", info);
+}
+
+/// Value of the `--info` field `name`, up to the first space
+fn field(info: &str, name: &str) -> u32 {
+    info.lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(':'))
+        .and_then(|value| value.split_whitespace().next())
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn ascii_preview_has_the_shape_of_the_bitmap() {
+    let info = info("ascii-preview", &[], &["--ascii-preview"]);
+
+    let dimensions = info.lines().find_map(|line| line.strip_prefix("Dimensions:")).unwrap();
+
+    let (width, height) = dimensions.trim().split_once(" x ").unwrap();
+
+    let (width, height): (f64, f64) = (width.parse().unwrap(), height.parse().unwrap());
+
+    let rows: Vec<&str> = info.lines().filter_map(|line| line.strip_prefix('|')?.strip_suffix('|')).collect();
+
+    let columns = rows[0].len();
+
+    //At most 16 rows of the same length, in a box whose border fits them
+    assert!(!rows.is_empty() && rows.len() <= 16, "{}", info);
+    assert!(rows.iter().all(|row| row.len() == columns), "{}", info);
+    assert_eq!(info.matches(&format!("+{}+\n", "-".repeat(columns))).count(), 2, "{}", info);
+
+    //Pixels are drawn two columns wide, so the box has the aspect ratio of the bitmap at twice its width
+    let aspect = columns as f64 / rows.len() as f64;
+
+    assert!((aspect - 2.0 * width / height).abs() < 0.25 * aspect, "{}", info);
+
+    //The padding is at the end of the data, which fills the box from the bottom up
+    let padding = field(&info, "Padding size") as f64 / field(&info, "Pixmap size") as f64;
+
+    let cells: String = rows.iter().rev().copied().collect();
+
+    let data_cells = cells.len() - (cells.len() as f64 * padding).round() as usize;
+
+    assert_eq!(cells, format!("{}{}", "#".repeat(data_cells), ".".repeat(cells.len() - data_cells)), "{}", info);
+    assert!(info.contains(&format!("# data  . padding ({:.1}% padding)\n", padding * 100.0)), "{}", info);
+}
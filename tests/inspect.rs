@@ -0,0 +1,52 @@
+//! `--inspect-pixels` reports the entropy of the file stored in a bitmap without converting it
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Encodes `data` and returns the entropy `--inspect-pixels` reports for it
+fn entropy(name: &str, data: &[u8], args: &[&str]) -> f64 {
+    let dir = scratch_dir(&format!("inspect-{}", name));
+
+    std::fs::write(dir.join("input"), data).unwrap();
+
+    assert!(run_b2b(&dir, args.iter().chain(&["input"])).status.success());
+
+    let bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    let output = run_b2b(&dir, ["--inspect-pixels", "input.bmp"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.starts_with(&format!("Payload size: {} bytes\n", data.len())), "{}", stdout);
+    assert_eq!(std::fs::read(dir.join("input.bmp")).unwrap(), bitmap, "the bitmap was modified");
+
+    let entropy = stdout.lines()
+        .find_map(|line| line.strip_prefix("Entropy:"))
+        .and_then(|line| line.split_whitespace().next())
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    entropy
+}
+
+#[test]
+fn compressed_looking_payload_has_higher_entropy_than_text() {
+    let text = "The quick brown fox jumps over the lazy dog. ".repeat(200).into_bytes();
+
+    //The input helper produces incompressible bytes, as a compressed or encrypted file would be
+    let random = input(text.len(), 110);
+
+    let text_entropy = entropy("text", &text, &[]);
+    let random_entropy = entropy("random", &random, &[]);
+
+    assert!(text_entropy < 5.0, "{}", text_entropy);
+    assert!(random_entropy > 7.5, "{}", random_entropy);
+
+    //The layout only changes where the bytes are stored, not which ones they are
+    assert_eq!(entropy("shuffled", &random, &["--shuffle", "3"]), random_entropy);
+}
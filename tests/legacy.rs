@@ -0,0 +1,106 @@
+//! Bitmaps written in the first header format, by the first release of b2b, can still be read. The golden files
+//! `baseline.bmp` and `baseline_fast.bmp` were written by that release from `input(1000, 11)` and, in fast mode,
+//! `input(300, 12)`
+
+mod common;
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Copies the golden bitmap `name` into a scratch directory, returning the directory
+fn golden_copy(name: &str) -> std::path::PathBuf {
+    let dir = scratch_dir(&format!("legacy-{}", name));
+
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join(format!("{}.bmp", name));
+
+    std::fs::copy(golden, dir.join(format!("{}.bmp", name))).unwrap();
+
+    dir
+}
+
+#[test]
+fn first_format_header_is_read() {
+    let dir = golden_copy("baseline");
+
+    let info = run_b2b(&dir, ["--info", "baseline.bmp"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(info.status.success(), "--info failed: {}", String::from_utf8_lossy(&info.stderr));
+
+    let info = String::from_utf8(info.stdout).unwrap();
+
+    assert!(info.contains("Original file size: 1000 bytes\n"), "{}", info);
+    assert!(info.contains("Header size:        178 bytes\n"), "{}", info);
+    assert!(info.contains("CRC32:              none\n"), "{}", info);
+    assert!(info.contains("Hash range:         0:1000\n"), "{}", info);
+    assert!(info.contains("Layout:             Row\n"), "{}", info);
+    assert!(info.contains("Signature:          06fafec0d7ef10c4468e85b0b9c0fb9e\n"), "{}", info);
+}
+
+#[test]
+fn first_format_bitmap_decodes_and_verifies() {
+    let dir = golden_copy("baseline");
+
+    let decoded = run_b2b(&dir, ["baseline.bmp"]);
+
+    assert!(decoded.status.success(), "decoding failed: {}", String::from_utf8_lossy(&decoded.stderr));
+    assert!(String::from_utf8_lossy(&decoded.stdout).contains("Verification successful"));
+
+    assert_eq!(std::fs::read(dir.join("baseline")).unwrap(), input(1000, 11));
+    assert!(!dir.join("baseline.bmp.bmp").exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn first_format_bitmap_without_digest_decodes() {
+    let dir = golden_copy("baseline_fast");
+
+    let decoded = run_b2b(&dir, ["baseline_fast.bmp"]);
+
+    assert!(decoded.status.success(), "decoding failed: {}", String::from_utf8_lossy(&decoded.stderr));
+
+    assert_eq!(std::fs::read(dir.join("baseline_fast")).unwrap(), input(300, 12));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn first_format_hexdump_stops_after_the_digest() {
+    let dir = golden_copy("baseline");
+
+    let dump = run_b2b(&dir, ["--hexdump-header", "baseline.bmp"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(dump.status.success());
+
+    let dump = String::from_utf8(dump.stdout).unwrap();
+
+    assert!(dump.lines().last().unwrap().starts_with("000000a2  b2b.od "), "{}", dump);
+    assert!(!dump.contains("b2b.crc"), "{}", dump);
+}
+
+#[test]
+fn first_format_bitmap_decodes_from_stdin() {
+    let golden = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden").join("baseline.bmp");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust_b2b"))
+        .args(["--stdin", "--stdout"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(&std::fs::read(golden).unwrap()).unwrap();
+
+    let decoded = child.wait_with_output().unwrap();
+
+    assert!(decoded.status.success(), "decoding failed: {}", String::from_utf8_lossy(&decoded.stderr));
+    assert_eq!(decoded.stdout, input(1000, 11));
+}
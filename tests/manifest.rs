@@ -0,0 +1,71 @@
+//! Checking a tree of bitmaps against a manifest of expected digests with `--manifest`
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+use blake_hash::{Blake256, Digest};
+
+/// Whole Blake256 digest of `data` in hex, as a tool like `b2sum` would list it
+fn hex_digest(data: &[u8]) -> String {
+    Blake256::digest(data).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Encodes `files` in a new tree, passing `--fast` for the ones marked so that they have no stored digest
+fn tree(name: &str, files: &[(&str, &[u8], bool)]) -> std::path::PathBuf {
+    let dir = scratch_dir(&format!("manifest-{}", name));
+
+    std::fs::create_dir_all(dir.join("tree/sub")).unwrap();
+
+    for (name, data, fast) in files {
+        std::fs::write(dir.join("tree").join(name), data).unwrap();
+
+        let path = format!("tree/{}", name);
+        let args = if *fast { vec!["--fast", &path] } else { vec![&path[..]] };
+
+        let encoded = run_b2b(&dir, args);
+
+        assert!(encoded.status.success(), "encoding failed: {}", String::from_utf8_lossy(&encoded.stderr));
+    }
+
+    dir
+}
+
+#[test]
+fn mismatch_and_missing_entries_are_reported() {
+    let (a, b, c) = (input(1000, 1), input(2000, 2), input(3000, 3));
+
+    let dir = tree("mismatch", &[("a", &a, false), ("sub/b", &b, true), ("c", &c, false)]);
+
+    let manifest = format!("# expected digests\n{}  a\n{} *sub/b\n{}  c\n{}  gone\n", hex_digest(&a), hex_digest(&b), hex_digest(&a), hex_digest(&c));
+
+    std::fs::write(dir.join("manifest.txt"), manifest).unwrap();
+
+    let checked = run_b2b(&dir, ["--manifest", "manifest.txt", "tree"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let output = String::from_utf8(checked.stdout).unwrap();
+
+    assert_eq!(checked.status.code(), Some(2), "{}", output);
+    assert!(output.contains("OK         a\n"), "{}", output);
+    assert!(output.contains("OK         sub/b\n"), "{}", output);
+    assert!(output.contains("MISMATCH   c (expected "), "{}", output);
+    assert!(output.contains("MISSING    gone\n"), "{}", output);
+    assert!(output.contains("Mismatched: 1\n"), "{}", output);
+}
+
+#[test]
+fn matching_tree_passes() {
+    let (a, b) = (input(1000, 4), input(2000, 5));
+
+    let dir = tree("match", &[("a", &a, false), ("sub/b", &b, false)]);
+
+    std::fs::write(dir.join("manifest.txt"), format!("{} a\n{} sub/b\n", hex_digest(&a), hex_digest(&b))).unwrap();
+
+    let checked = run_b2b(&dir, ["--manifest", "manifest.txt", "tree"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(checked.status.success(), "{}", String::from_utf8_lossy(&checked.stdout));
+}
@@ -0,0 +1,79 @@
+//! `--profile-memory` shows that large files are converted in chunks rather than read into memory whole
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Size of the file converted, far more than the conversion should need at once
+const FILE_SIZE: usize = 24 << 20;
+
+/// Most memory the conversion may use, which leaves room for the executable itself and the copy buffer
+const MEMORY_BOUND: u64 = 12 << 20;
+
+/// Value of the line of `--profile-memory` output starting with `label`, if it was measured
+fn measurement(stderr: &str, label: &str) -> Option<u64> {
+    let line = stderr.lines().find(|line| line.starts_with(label)).unwrap_or_else(|| panic!("no {} in {}", label, stderr));
+
+    line[label.len()..].trim().strip_suffix(" bytes").map(|bytes| bytes.parse().unwrap())
+}
+
+/// Checks the memory reported in `stderr` is within the bound
+fn assert_bounded(stderr: &str) {
+    let rss = measurement(stderr, "Peak resident memory:").unwrap();
+
+    assert!(rss < MEMORY_BOUND, "{} bytes resident", rss);
+
+    //Only measured with the profile-memory feature
+    if let Some(allocated) = measurement(stderr, "Peak heap allocated:") {
+        assert!(allocated < MEMORY_BOUND, "{} bytes allocated", allocated);
+    }
+}
+
+#[test]
+fn large_file_is_converted_in_chunks() {
+    let dir = scratch_dir("memory-file");
+
+    std::fs::write(dir.join("input"), input(FILE_SIZE, 220)).unwrap();
+
+    let output = run_b2b(&dir, ["--profile-memory", "input"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{}", stderr);
+
+    assert_bounded(&stderr);
+}
+
+#[test]
+fn large_stream_is_spilled_in_chunks() {
+    let dir = scratch_dir("memory-stream");
+
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust_b2b"))
+        .args(["--stdin", "--stdout", "--memory-limit", "1048576", "--profile-memory"])
+        .current_dir(&dir)
+        .env("TMPDIR", &dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(&input(FILE_SIZE, 221)).unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{}", stderr);
+
+    assert_bounded(&stderr);
+}
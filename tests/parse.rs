@@ -0,0 +1,66 @@
+//! Option values are parsed into their enums, and unknown values are rejected with the valid ones listed
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Runs b2b with `args` on a new file, returning whether it succeeded and what it printed to stderr
+fn encode(name: &str, args: &[&str]) -> (Option<i32>, String) {
+    let dir = scratch_dir(&format!("parse-{}", name));
+
+    std::fs::write(dir.join("input"), input(1000, 200)).unwrap();
+
+    let output = run_b2b(&dir, [args, &["input"]].concat());
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    (output.status.code(), String::from_utf8_lossy(&output.stderr).into_owned())
+}
+
+#[test]
+fn every_layout_parses() {
+    for layout in ["row", "column", "interleaved", "shuffled", "tiled"] {
+        let (code, stderr) = encode(&format!("layout-{}", layout), &["--layout", layout]);
+
+        assert_eq!(code, Some(0), "{}: {}", layout, stderr);
+    }
+}
+
+#[test]
+fn every_container_parses() {
+    for container in ["bmp", "png", "raw"] {
+        let (code, stderr) = encode(&format!("container-{}", container), &["--container", container]);
+
+        assert_eq!(code, Some(0), "{}: {}", container, stderr);
+    }
+}
+
+#[test]
+fn unknown_layout_lists_the_layouts() {
+    let (code, stderr) = encode("unknown-layout", &["--layout", "diagonal"]);
+
+    assert_eq!(code, Some(64));
+    assert!(stderr.contains("unknown layout 'diagonal', expected one of row, column, interleaved, shuffled, tiled"), "{}", stderr);
+}
+
+#[test]
+fn unknown_container_lists_the_containers() {
+    let (code, stderr) = encode("unknown-container", &["--container", "gif"]);
+
+    assert_eq!(code, Some(64));
+    assert!(stderr.contains("unknown container 'gif', expected one of bmp, png, raw"), "{}", stderr);
+}
+
+#[test]
+fn help_and_version_exit_successfully() {
+    let dir = scratch_dir("parse-help");
+
+    for flag in ["--help", "--version"] {
+        let output = run_b2b(&dir, [flag]);
+
+        assert_eq!(output.status.code(), Some(0), "{}", flag);
+        assert!(!output.stdout.is_empty(), "{}", flag);
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,42 @@
+//! File names are handled as `OsStr`s, so names that aren't valid UTF-8 convert like any other
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+
+use common::{input, scratch_dir, run_b2b};
+
+#[test]
+fn non_utf8_file_name_round_trips() {
+    let dir = scratch_dir("path-non-utf8");
+
+    let name = OsStr::from_bytes(b"\xff.bin");
+    let bitmap = OsStr::from_bytes(b"\xff.bin.bmp");
+
+    let data = input(2000, 220);
+
+    std::fs::write(dir.join(name), &data).unwrap();
+
+    let encoded = run_b2b(&dir, [name]);
+
+    assert!(encoded.status.success(), "encoding failed: {}", String::from_utf8_lossy(&encoded.stderr));
+    assert!(dir.join(bitmap).is_file());
+
+    std::fs::remove_file(dir.join(name)).unwrap();
+
+    let decoded = run_b2b(&dir, [bitmap]);
+
+    assert!(decoded.status.success(), "decoding failed: {}", String::from_utf8_lossy(&decoded.stderr));
+    assert_eq!(std::fs::read(dir.join(name)).unwrap(), data);
+
+    //Converting in place renames the file itself
+    let in_place = run_b2b(&dir, [OsStr::new("--in-place"), name]);
+
+    assert!(in_place.status.success(), "converting in place failed: {}", String::from_utf8_lossy(&in_place.stderr));
+    assert!(!dir.join(name).exists() && dir.join(bitmap).is_file());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,90 @@
+//! Reads the whole payload of bitmaps back through `PayloadReader` (via `--verify-against`, which decodes the
+//! bitmap without modifying it) and compares it to the original
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Encodes `len` bytes with `args`, then checks that the payload read back from the bitmap matches the input
+fn check_payload(name: &str, len: usize, args: &[&str]) {
+    let dir = scratch_dir(&format!("payload-{}", name));
+
+    std::fs::write(dir.join("input"), input(len, len as u32)).unwrap();
+
+    let encoded = run_b2b(&dir, args.iter().copied().chain(Some("input")));
+
+    assert!(encoded.status.success(), "encoding {} failed: {}", name, String::from_utf8_lossy(&encoded.stderr));
+
+    let verified = run_b2b(&dir, ["--verify-against", "input", "input.bmp"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(verified.status.success(), "reading {} failed: {}", name, String::from_utf8_lossy(&verified.stderr));
+
+    let stdout = String::from_utf8_lossy(&verified.stdout);
+
+    assert!(stdout.contains("Bitmap reproduces"), "payload of {} differs: {}", name, stdout);
+}
+
+#[test]
+fn payload_smaller_than_a_chunk() {
+    check_payload("small", 700, &[]);
+}
+
+#[test]
+fn payload_of_many_chunks() {
+    check_payload("large", 100_000, &[]);
+}
+
+#[test]
+fn payload_with_honor_offset() {
+    check_payload("honor_offset", 5000, &["--honor-offset"]);
+}
+
+#[test]
+fn payload_with_honor_offset_and_the_bmp_container() {
+    check_payload("honor_offset_bmp", 5000, &["--honor-offset", "--container", "bmp"]);
+}
+
+#[test]
+fn honor_offset_is_refused_by_other_containers() {
+    let dir = scratch_dir("payload-honor-offset-containers");
+
+    std::fs::write(dir.join("input"), input(1000, 124)).unwrap();
+
+    for container in ["png", "raw"] {
+        let output = run_b2b(&dir, ["--honor-offset", "--container", container, "input"]);
+
+        assert_eq!(output.status.code(), Some(64), "{}", container);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("only supported by the bmp container"), "{}", container);
+    }
+
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn payload_with_column_layout() {
+    check_payload("column", 5000, &["--layout", "column"]);
+}
+
+#[test]
+fn payload_with_shuffled_layout() {
+    check_payload("shuffled", 5000, &["--shuffle", "3"]);
+}
+
+#[test]
+fn payload_smaller_than_the_header() {
+    check_payload("tiny", 100, &[]);
+}
+
+#[test]
+fn payload_with_canonical_header() {
+    check_payload("canonical", 20_000, &["--canonical"]);
+}
+
+#[test]
+fn payload_smaller_than_the_canonical_header() {
+    check_payload("canonical_tiny", 1000, &["--canonical"]);
+}
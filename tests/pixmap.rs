@@ -0,0 +1,63 @@
+//! `--pixmap-only` writes out the pixel array of a bitmap created by b2b, leaving the bitmap as it is
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Offset of the pixmap size in the bitmap header
+const PIXMAP_SIZE_OFFSET: usize = 34;
+
+/// Size of the bitmap header, after which the pixmap starts
+const BITMAP_HEADER_SIZE: usize = 0x8A;
+
+#[test]
+fn pixmap_is_the_pixel_array_of_the_bitmap() {
+    let dir = scratch_dir("pixmap-only");
+
+    std::fs::write(dir.join("input"), input(7000, 250)).unwrap();
+
+    for (i, args) in [&[][..], &["--layout", "column"][..], &["--fast"][..]].iter().enumerate() {
+        let extension = format!("{}.bmp", i);
+
+        assert!(run_b2b(&dir, [&["--ext", &extension][..], args, &["input"]].concat()).status.success());
+
+        let bitmap = std::fs::read(dir.join(format!("input.{}", extension))).unwrap();
+
+        let mut size = [0u8; 4];
+
+        size.copy_from_slice(&bitmap[PIXMAP_SIZE_OFFSET..PIXMAP_SIZE_OFFSET + 4]);
+
+        let pixmap_size = u32::from_le_bytes(size) as usize;
+
+        let pixmap = format!("pixmap.{}", i);
+
+        let exported = run_b2b(&dir, ["--pixmap-only", &pixmap, &format!("input.{}", extension)]);
+
+        assert!(exported.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&exported.stderr));
+        assert!(String::from_utf8_lossy(&exported.stdout).contains(&format!("Wrote {} bytes of pixmap", pixmap_size)), "{:?}", args);
+
+        assert_eq!(std::fs::read(dir.join(&pixmap)).unwrap(), &bitmap[BITMAP_HEADER_SIZE..BITMAP_HEADER_SIZE + pixmap_size], "{:?}", args);
+        assert_eq!(bitmap.len(), BITMAP_HEADER_SIZE + pixmap_size, "{:?}", args);
+
+        assert_eq!(std::fs::read(dir.join(format!("input.{}", extension))).unwrap(), bitmap, "{:?}", args);
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn existing_output_is_not_overwritten() {
+    let dir = scratch_dir("pixmap-only-exists");
+
+    std::fs::write(dir.join("input"), input(1000, 251)).unwrap();
+
+    assert!(run_b2b(&dir, ["input"]).status.success());
+
+    std::fs::write(dir.join("pixmap"), "keep").unwrap();
+
+    assert!(!run_b2b(&dir, ["--pixmap-only", "pixmap", "input.bmp"]).status.success());
+
+    assert_eq!(std::fs::read(dir.join("pixmap")).unwrap(), b"keep");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,61 @@
+//! Converting a file in place allocates the whole bitmap on disk, padding included, instead of leaving holes, unless
+//! `--sparse` asks for the padding to be left as a hole
+
+#![cfg(target_os = "linux")]
+
+mod common;
+
+use std::os::unix::fs::MetadataExt;
+
+use common::{input, scratch_dir, run_b2b};
+
+#[test]
+fn padding_is_allocated() {
+    let dir = scratch_dir("preallocate");
+
+    std::fs::write(dir.join("input"), input(10_000, 160)).unwrap();
+
+    //A 200x200 bitmap is about 160 KB, almost all of it padding
+    let output = run_b2b(&dir, ["--in-place", "--min-dimension", "200", "input"]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let metadata = std::fs::metadata(dir.join("input.bmp")).unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    //Blocks are counted in 512 byte units, whatever the block size of the filesystem
+    assert!(metadata.blocks() * 512 >= metadata.len(), "{} bytes allocated for a file of {} bytes", metadata.blocks() * 512, metadata.len());
+}
+
+/// Encodes 10 KB into a 200x200 bitmap, which is about 160 KB and almost all padding, with `args` and returns the
+/// bitmap with the bytes allocated for it on disk
+fn sparse_bitmap(name: &str, args: &[&str]) -> (Vec<u8>, u64) {
+    let dir = scratch_dir(&format!("sparse-{}", name));
+
+    std::fs::write(dir.join("input"), input(10_000, 161)).unwrap();
+
+    let output = run_b2b(&dir, [&["--min-dimension", "200"], args, &["input"]].concat());
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let allocated = std::fs::metadata(dir.join("input.bmp")).unwrap().blocks() * 512;
+    let bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    (bitmap, allocated)
+}
+
+#[test]
+fn sparse_padding_is_a_hole() {
+    let (dense, _) = sparse_bitmap("dense", &[]);
+
+    for (name, args) in [("copy", &["--sparse"][..]), ("in-place", &["--sparse", "--in-place"][..])] {
+        let (bitmap, allocated) = sparse_bitmap(name, args);
+
+        //The data takes up about 10 KB, leave room for the filesystem's block size
+        assert!(allocated < bitmap.len() as u64 / 2, "{} bytes allocated for a sparse bitmap of {} bytes ({})", allocated, bitmap.len(), name);
+        assert_eq!(bitmap, dense, "sparse bitmap differs ({})", name);
+    }
+}
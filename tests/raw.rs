@@ -0,0 +1,78 @@
+//! Round trips through the raw container, which is the bitmap without its bitmap header
+
+mod common;
+
+use std::path::Path;
+
+use common::{input, scratch_dir, run_b2b};
+
+const BITMAP_HEADER_SIZE: usize = 0x8A;
+
+/// Runs b2b with `args` in `dir`, failing the test if it fails
+fn b2b(dir: &Path, args: &[&str]) {
+    let output = run_b2b(dir, args);
+
+    assert!(output.status.success(), "b2b {:?} failed: {}", args, String::from_utf8_lossy(&output.stderr));
+}
+
+fn round_trip(name: &str, len: usize, args: &[&str]) {
+    let dir = scratch_dir(&format!("raw-{}", name));
+
+    let data = input(len, 20);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    //The same file as a bitmap, to compare the raw file with
+    b2b(&dir, &["input"]);
+
+    let bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    b2b(&dir, &[&["--container", "raw", "--ext", "raw"], args, &["input"]].concat());
+
+    let raw = std::fs::read(dir.join("input.raw")).unwrap();
+
+    //Converting in place has already moved the input
+    let _ = std::fs::remove_file(dir.join("input"));
+
+    b2b(&dir, &["input.raw"]);
+
+    let decoded = std::fs::read(dir.join("input")).unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    if args.is_empty() {
+        assert_eq!(raw, bitmap[BITMAP_HEADER_SIZE..]);
+    }
+
+    assert_eq!(decoded, data);
+}
+
+#[test]
+fn raw_round_trip() {
+    round_trip("plain", 10_000, &[]);
+}
+
+#[test]
+fn raw_round_trip_smaller_than_the_header() {
+    round_trip("tiny", 50, &[]);
+}
+
+#[test]
+fn raw_round_trip_in_place() {
+    round_trip("in_place", 10_000, &["--in-place"]);
+}
+
+#[test]
+fn raw_round_trip_canonical() {
+    round_trip("canonical", 10_000, &["--canonical"]);
+}
+
+#[test]
+fn raw_round_trip_across_sizes() {
+    //The rebuilt header must be consistent for any size, however the pixels factor
+    for (i, len) in [0, 1, 67, 68, 69, 500, 4095, 4096, 70001].iter().enumerate() {
+        for args in [&[][..], &["--min-dimension", "1"]] {
+            round_trip(&format!("size-{}-{}", i, args.len()), *len, args);
+        }
+    }
+}
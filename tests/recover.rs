@@ -0,0 +1,178 @@
+//! Recovers original files from bitmaps and pixmaps whose headers are damaged
+
+mod common;
+
+use std::path::Path;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Size of the bitmap header, and of the bitmap and b2b headers together
+const BITMAP_HEADER_SIZE: usize = 0x8A;
+const HEADER_SIZE: usize = 206;
+
+/// Encodes `data` in `dir`, returning the bitmap
+fn encode(dir: &Path, data: &[u8]) -> Vec<u8> {
+    std::fs::write(dir.join("input"), data).unwrap();
+
+    let encoded = run_b2b(dir, ["input"]);
+
+    assert!(encoded.status.success(), "encoding failed: {}", String::from_utf8_lossy(&encoded.stderr));
+
+    std::fs::read(dir.join("input.bmp")).unwrap()
+}
+
+/// Writes `damaged` to a file and recovers it with `args`, returning the recovered file if it succeeded
+fn recover(dir: &Path, damaged: &[u8], args: &[&str]) -> Option<Vec<u8>> {
+    std::fs::write(dir.join("damaged"), damaged).unwrap();
+
+    let _ = std::fs::remove_file(dir.join("recovered"));
+
+    let recovered = run_b2b(dir, ["--recover-headerless", "recovered"].iter().chain(args).chain(&["damaged"]));
+
+    if recovered.status.success() {
+        Some(std::fs::read(dir.join("recovered")).unwrap())
+    } else {
+        None
+    }
+}
+
+/// Input whose first `HEADER_SIZE` bytes end with a non zero byte, with a run of zeros in the middle
+fn input_with_zeros(len: usize) -> Vec<u8> {
+    let mut data = input(len, 11);
+
+    data[HEADER_SIZE - 1] = 0xFF;
+
+    for byte in &mut data[1000..3000] {
+        *byte = 0;
+    }
+
+    data
+}
+
+#[test]
+fn damaged_bitmap_header_uses_the_stored_length() {
+    let dir = scratch_dir("recover-bitmap-header");
+
+    let data = input(10_000, 12);
+
+    let mut bitmap = encode(&dir, &data);
+
+    for byte in &mut bitmap[..BITMAP_HEADER_SIZE] {
+        *byte = 0;
+    }
+
+    let recovered = recover(&dir, &bitmap, &[]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(recovered.as_deref(), Some(data.as_slice()));
+}
+
+#[test]
+fn damaged_b2b_header_needs_trim() {
+    let dir = scratch_dir("recover-needs-trim");
+
+    let mut bitmap = encode(&dir, &input_with_zeros(10_000));
+
+    for byte in &mut bitmap[BITMAP_HEADER_SIZE..HEADER_SIZE] {
+        *byte = 0;
+    }
+
+    let recovered = recover(&dir, &bitmap[BITMAP_HEADER_SIZE..], &[]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(recovered.is_none());
+}
+
+#[test]
+fn trim_removes_only_trailing_zeros() {
+    let dir = scratch_dir("recover-trim");
+
+    let data = input_with_zeros(10_000);
+
+    let mut bitmap = encode(&dir, &data);
+
+    for byte in &mut bitmap[BITMAP_HEADER_SIZE..HEADER_SIZE] {
+        *byte = 0;
+    }
+
+    let recovered = recover(&dir, &bitmap[BITMAP_HEADER_SIZE..], &["--trim"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    //The zeros in the middle of the file are kept, and the padding after it is gone
+    assert_eq!(recovered.as_deref(), Some(data.as_slice()));
+}
+
+/// Writes the first `len` bytes of `bitmap` to a file and runs `--recover-truncated` on it. Returns the exit code,
+/// what was printed and the recovered file, if one was written
+fn recover_truncated(dir: &Path, bitmap: &[u8], len: usize) -> (Option<i32>, String, Option<Vec<u8>>) {
+    std::fs::write(dir.join("truncated.bmp"), &bitmap[..len]).unwrap();
+
+    let _ = std::fs::remove_file(dir.join("truncated"));
+
+    let recovered = run_b2b(dir, ["--recover-truncated", "truncated.bmp"]);
+
+    //The bitmap itself is never modified
+    assert_eq!(std::fs::read(dir.join("truncated.bmp")).unwrap(), &bitmap[..len]);
+
+    (recovered.status.code(), String::from_utf8_lossy(&recovered.stdout).into_owned(), std::fs::read(dir.join("truncated")).ok())
+}
+
+#[test]
+fn truncated_bitmap_recovers_what_remains() {
+    let dir = scratch_dir("recover-truncated");
+
+    let data = input(10_000, 13);
+
+    let bitmap = encode(&dir, &data);
+
+    let mut padding = [0u8; 4];
+
+    padding.copy_from_slice(&bitmap[BITMAP_HEADER_SIZE..BITMAP_HEADER_SIZE + 4]);
+
+    //The first HEADER_SIZE bytes of the original are stored after the rest of it, followed by the padding
+    let padding_start = bitmap.len() - u32::from_le_bytes(padding) as usize;
+    let front_offset = padding_start - HEADER_SIZE;
+
+    //Only padding lost
+    let (code, output, recovered) = recover_truncated(&dir, &bitmap, padding_start);
+
+    assert_eq!(code, Some(0), "{}", output);
+    assert!(output.contains("Recovered the whole original file"), "{}", output);
+    assert_eq!(recovered.as_deref(), Some(data.as_slice()));
+
+    //Part of the front of the original lost, which is replaced with zeros
+    let (code, output, recovered) = recover_truncated(&dir, &bitmap, front_offset + 50);
+
+    let mut expected = data.clone();
+
+    expected[50..HEADER_SIZE].fill(0);
+
+    assert_eq!(code, Some(3), "{}", output);
+    assert!(output.contains(&format!("bytes 50 to {} of the original were lost", HEADER_SIZE)), "{}", output);
+    assert_eq!(recovered.as_deref(), Some(expected.as_slice()));
+
+    //The whole front lost, but nothing after it
+    let (code, _, recovered) = recover_truncated(&dir, &bitmap, front_offset);
+
+    expected[..HEADER_SIZE].fill(0);
+
+    assert_eq!(code, Some(3));
+    assert_eq!(recovered.as_deref(), Some(expected.as_slice()));
+
+    //Cut off inside the rest of the original, which can't be recovered
+    let (code, _, recovered) = recover_truncated(&dir, &bitmap, front_offset - 1);
+
+    assert_eq!(code, Some(3));
+    assert!(recovered.is_none());
+
+    //Not truncated at all
+    let (code, _, recovered) = recover_truncated(&dir, &bitmap, bitmap.len());
+
+    assert_eq!(code, Some(3));
+    assert!(recovered.is_none());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,86 @@
+//! `--reencode` replaces a bitmap created by b2b with one encoded using other options, holding the same file
+
+mod common;
+
+use std::path::Path;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Offset of the first byte of the original file stored in a row layout bitmap, after the bitmap header, the b2b header
+/// and the first 206 bytes of the file, which are stored at the end
+const PAYLOAD_OFFSET: usize = 0x8A + 68 + 206;
+
+/// Re-encodes `input.bmp` in `dir` with `args`, checking it still holds `data` and returning the new bitmap
+fn reencode(dir: &Path, data: &[u8], args: &[&str]) -> Vec<u8> {
+    let reencoded = run_b2b(dir, [&["--reencode"][..], args, &["input.bmp"]].concat());
+
+    assert!(reencoded.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&reencoded.stderr));
+    assert!(!dir.join("input").exists(), "{:?}: the file was written to disk", args);
+    assert!(!dir.join("input.bmp.tmp").exists(), "{:?}", args);
+
+    let bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    //Decoded from a copy, so the bitmap is there to be re-encoded again
+    std::fs::write(dir.join("copy.bmp"), &bitmap).unwrap();
+
+    let decoded = run_b2b(dir, ["copy.bmp"]);
+
+    assert!(decoded.status.success(), "{:?}: {}", args, String::from_utf8_lossy(&decoded.stderr));
+    assert_eq!(std::fs::read(dir.join("copy")).unwrap(), data, "{:?}", args);
+
+    let _ = std::fs::remove_file(dir.join("copy"));
+
+    bitmap
+}
+
+#[test]
+fn reencoded_bitmaps_round_trip() {
+    let dir = scratch_dir("reencode");
+
+    let data = input(30_000, 180);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    assert!(run_b2b(&dir, ["input"]).status.success());
+
+    std::fs::remove_file(dir.join("input")).unwrap();
+
+    let row = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    let column = reencode(&dir, &data, &["--layout", "column"]);
+
+    assert_ne!(column, row);
+    assert!(String::from_utf8_lossy(&run_b2b(&dir, ["--info", "--json", "input.bmp"]).stdout).contains("\"layout\": \"Column\""));
+
+    let shuffled = reencode(&dir, &data, &["--shuffle", "7"]);
+
+    assert_ne!(shuffled, column);
+    assert_ne!(shuffled, row);
+
+    //Back to the options it was first encoded with, so back to the same bitmap
+    assert_eq!(reencode(&dir, &data, &[]), row);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn corrupt_bitmap_is_not_reencoded() {
+    let dir = scratch_dir("reencode-corrupt");
+
+    std::fs::write(dir.join("input"), input(5000, 181)).unwrap();
+
+    assert!(run_b2b(&dir, ["input"]).status.success());
+
+    let mut bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    bitmap[PAYLOAD_OFFSET] ^= 0xff;
+
+    std::fs::write(dir.join("input.bmp"), &bitmap).unwrap();
+
+    assert_eq!(run_b2b(&dir, ["--reencode", "--layout", "column", "input.bmp"]).status.code(), Some(2));
+
+    assert_eq!(std::fs::read(dir.join("input.bmp")).unwrap(), bitmap);
+    assert!(!dir.join("input.bmp.tmp").exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,59 @@
+//! `copy_region` moves bytes within a file through a bounded buffer, whether or not the ranges overlap
+
+#[path = "../src/region.rs"]
+mod region;
+
+use std::io::Cursor;
+
+use region::copy_region;
+
+/// Copies with `copy_region` through a buffer of `buf_len` bytes, and checks the result against `copy_within`
+fn check(from: usize, to: usize, len: usize, buf_len: usize) {
+    let original: Vec<u8> = (0..100).map(|i| i as u8).collect();
+
+    let mut file = Cursor::new(original.clone());
+
+    copy_region(& mut file, from as u64, to as u64, len as u64, & mut vec![0u8; buf_len]).unwrap();
+
+    let mut expected = original;
+
+    expected.copy_within(from..from + len, to);
+
+    assert_eq!(file.into_inner(), expected, "copying {} bytes from {} to {} through {} bytes", len, from, to, buf_len);
+}
+
+#[test]
+fn overlapping_forwards() {
+    for buf_len in [1, 3, 7, 40, 64] {
+        check(10, 20, 40, buf_len);
+    }
+}
+
+#[test]
+fn overlapping_backwards() {
+    for buf_len in [1, 3, 7, 40, 64] {
+        check(20, 10, 40, buf_len);
+    }
+}
+
+#[test]
+fn disjoint_and_degenerate_ranges() {
+    check(0, 60, 30, 8);
+    check(60, 0, 30, 8);
+    check(5, 5, 50, 8);
+    check(5, 50, 0, 8);
+}
+
+#[test]
+fn destination_past_the_end_extends_the_file() {
+    let mut file = Cursor::new((0..10).collect::<Vec<u8>>());
+
+    copy_region(& mut file, 0, 10, 10, & mut [0u8; 4]).unwrap();
+
+    assert_eq!(file.into_inner(), [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+}
+
+#[test]
+fn empty_buffer_is_an_error() {
+    assert!(copy_region(& mut Cursor::new(vec![0u8; 10]), 0, 5, 5, & mut []).is_err());
+}
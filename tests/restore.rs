@@ -0,0 +1,67 @@
+//! `--restore-on-error` puts a file back as it was when converting it in place fails partway
+
+#![cfg(unix)]
+
+mod common;
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use common::{input, scratch_dir};
+
+/// Runs b2b with `args` from `dir`, with files limited to `limit_kib` KiB. Growing a file past the limit fails with
+/// EFBIG, as the signal that would otherwise kill b2b is ignored
+fn run_b2b_limited(dir: &Path, limit_kib: u32, args: &[&str]) -> Output {
+    //ulimit -f counts 512 byte blocks in some shells and KiB in others, either is fine here
+    Command::new("sh")
+        .arg("-c")
+        .arg(format!("trap '' XFSZ; ulimit -f {}; exec \"$0\" \"$@\"", limit_kib))
+        .arg(env!("CARGO_BIN_EXE_rust_b2b"))
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .unwrap()
+}
+
+/// Converts a file in place with `args`, failing after its header has been written, when the padding is added.
+/// Returns the file afterwards, with the original
+fn failed_conversion(name: &str, args: &[&str]) -> (Vec<u8>, Vec<u8>) {
+    let dir = scratch_dir(&format!("restore-{}", name));
+
+    let data = input(10_000, 140);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    //The file fits within the limit with its front copied to the end, but a 200x200 bitmap is about 160 KB
+    let output = run_b2b_limited(&dir, 20, &[&["--in-place", "--min-dimension", "200"], args, &["input"]].concat());
+
+    assert!(!output.status.success(), "the conversion did not fail");
+    assert!(!dir.join("input.bmp").exists());
+
+    let after = std::fs::read(dir.join("input")).unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    (after, data)
+}
+
+#[test]
+fn file_is_left_half_converted_by_default() {
+    let (after, data) = failed_conversion("default", &[]);
+
+    assert_ne!(after, data);
+}
+
+#[test]
+fn file_is_restored_on_error() {
+    let (after, data) = failed_conversion("restored", &["--restore-on-error"]);
+
+    assert_eq!(after, data);
+}
+
+#[test]
+fn file_is_restored_on_error_with_another_layout() {
+    let (after, data) = failed_conversion("column", &["--restore-on-error", "--layout", "column"]);
+
+    assert_eq!(after, data);
+}
@@ -0,0 +1,78 @@
+//! `--retry` tries a file again when converting it fails with a transient error, but not when it is corrupt
+
+#![cfg(unix)]
+
+mod common;
+
+use std::process::{Child, Command};
+use std::time::Duration;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Runs the executable at `path`, which makes writing to it fail with "text file busy" until it exits
+fn run_busy(path: &std::path::Path, seconds: &str) -> Child {
+    loop {
+        //A test running in parallel may briefly hold the file open for writing, which makes running it fail with
+        //the same error
+        match Command::new(path).arg(seconds).spawn() {
+            Err(e) if e.raw_os_error() == Some(26) => std::thread::sleep(Duration::from_millis(10)),
+            result => return result.unwrap(),
+        }
+    }
+}
+
+#[test]
+fn busy_file_is_retried() {
+    let dir = scratch_dir("retry-busy");
+
+    std::fs::create_dir(dir.join("tree")).unwrap();
+    std::fs::copy("/bin/sleep", dir.join("tree/busy")).unwrap();
+
+    let original = std::fs::read(dir.join("tree/busy")).unwrap();
+
+    //The file stays busy for less time than b2b waits before the first retry
+    let mut busy = run_busy(&dir.join("tree/busy"), "0.1");
+
+    let output = run_b2b(&dir, ["--in-place", "--retry", "1", "tree"]);
+
+    busy.wait().unwrap();
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert!(output.status.success(), "{}", stderr);
+    assert_eq!(stderr.matches("retrying").count(), 1, "{}", stderr);
+
+    assert!(run_b2b(&dir, ["tree/busy.bmp"]).status.success());
+    assert_eq!(std::fs::read(dir.join("tree/busy")).unwrap(), original);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn corrupt_bitmap_is_not_retried() {
+    let dir = scratch_dir("retry-corrupt");
+
+    std::fs::create_dir(dir.join("tree")).unwrap();
+    std::fs::write(dir.join("tree/input"), input(3000, 150)).unwrap();
+
+    assert!(run_b2b(&dir, ["tree/input"]).status.success());
+
+    std::fs::remove_file(dir.join("tree/input")).unwrap();
+
+    //Make the stored size of the original one byte too large
+    let mut bitmap = std::fs::read(dir.join("tree/input.bmp")).unwrap();
+
+    bitmap[0x8A + 4] = bitmap[0x8A + 4].wrapping_add(1);
+
+    std::fs::write(dir.join("tree/input.bmp"), &bitmap).unwrap();
+
+    let output = run_b2b(&dir, ["--retry", "3", "tree"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+    assert!(stderr.contains("invalid original file size"), "{}", stderr);
+    assert!(!stderr.contains("retrying"), "{}", stderr);
+}
@@ -0,0 +1,61 @@
+//! The seed of the pseudo-random features makes the bitmaps they produce reproducible
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Encodes `data` with `args` in a directory of its own, and returns the bitmap after checking that it decodes
+fn encode(name: &str, data: &[u8], args: &[&str]) -> Vec<u8> {
+    let dir = scratch_dir(&format!("seed-{}", name));
+
+    std::fs::write(dir.join("input"), data).unwrap();
+
+    let encoded = run_b2b(&dir, args.iter().copied().chain(Some("input")));
+
+    assert!(encoded.status.success(), "encoding {} failed: {}", name, String::from_utf8_lossy(&encoded.stderr));
+
+    let bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    std::fs::remove_file(dir.join("input")).unwrap();
+
+    assert!(run_b2b(&dir, ["input.bmp"]).status.success(), "decoding {} failed", name);
+    assert_eq!(std::fs::read(dir.join("input")).unwrap(), data, "{} did not round trip", name);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    bitmap
+}
+
+#[test]
+fn identical_seeds_give_identical_bitmaps() {
+    let data = input(4000, 80);
+
+    let first = encode("same-1", &data, &["--layout", "shuffled", "--seed", "42"]);
+    let second = encode("same-2", &data, &["--layout", "shuffled", "--seed", "42"]);
+    let shorthand = encode("same-3", &data, &["--shuffle", "42"]);
+
+    assert_eq!(first, second);
+    assert_eq!(first, shorthand);
+}
+
+#[test]
+fn different_seeds_give_different_bitmaps() {
+    let data = input(4000, 81);
+
+    assert_ne!(encode("different-1", &data, &["--layout", "shuffled", "--seed", "1"]), encode("different-2", &data, &["--layout", "shuffled", "--seed", "2"]));
+}
+
+#[test]
+fn seed_defaults_to_one_derived_from_the_digest() {
+    let data = input(4000, 82);
+
+    let first = encode("derived-1", &data, &["--layout", "shuffled"]);
+    let second = encode("derived-2", &data, &["--layout", "shuffled"]);
+
+    assert_eq!(first, second);
+
+    //Still derived from the digest when it isn't stored
+    let fast = encode("derived-fast", &data, &["--layout", "shuffled", "--fast"]);
+
+    assert_eq!(first[206..], fast[206..]);
+}
@@ -0,0 +1,66 @@
+//! Files smaller than the header region, which are stored whole after the header rather than split around it
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Encodes `len` bytes both in place and to a new file, checks the two bitmaps are the same, then decodes the one
+/// converted in place in place and checks it matches the input
+fn round_trip(name: &str, len: usize, args: &[&str]) {
+    let dir = scratch_dir(&format!("small-{}", name));
+
+    let data = input(len, len as u32 + 7);
+
+    std::fs::write(dir.join("copied"), &data).unwrap();
+    std::fs::write(dir.join("in-place"), &data).unwrap();
+
+    let copied = run_b2b(&dir, [args, &["copied"]].concat());
+    let in_place = run_b2b(&dir, [args, &["--in-place", "in-place"]].concat());
+
+    assert!(copied.status.success(), "encoding {} failed: {}", name, String::from_utf8_lossy(&copied.stderr));
+    assert!(in_place.status.success(), "encoding {} in place failed: {}", name, String::from_utf8_lossy(&in_place.stderr));
+
+    let bitmap = std::fs::read(dir.join("in-place.bmp")).unwrap();
+
+    assert_eq!(bitmap, std::fs::read(dir.join("copied.bmp")).unwrap(), "bitmaps of {} differ", name);
+
+    let decoded = run_b2b(&dir, ["--in-place", "in-place.bmp"]);
+
+    assert!(decoded.status.success(), "decoding {} failed: {}", name, String::from_utf8_lossy(&decoded.stderr));
+
+    let after = std::fs::read(dir.join("in-place")).unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(after, data, "{} did not round trip", name);
+}
+
+#[test]
+fn empty_file() {
+    round_trip("empty", 0, &[]);
+}
+
+#[test]
+fn single_byte() {
+    round_trip("byte", 1, &[]);
+}
+
+#[test]
+fn file_smaller_than_the_header() {
+    round_trip("tiny", 100, &[]);
+}
+
+#[test]
+fn file_one_byte_smaller_than_the_header() {
+    round_trip("almost", 205, &[]);
+}
+
+#[test]
+fn file_smaller_than_the_canonical_header() {
+    round_trip("canonical", 1000, &["--canonical"]);
+}
+
+#[test]
+fn file_smaller_than_the_header_with_column_layout() {
+    round_trip("column", 100, &["--layout", "column"]);
+}
@@ -0,0 +1,217 @@
+//! Conversions from stdin to stdout, which happen in memory unless the input is larger than `--memory-limit`
+
+mod common;
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+use common::{input, scratch_dir};
+
+/// Runs b2b with `args` from `dir`, passing `data` on stdin and using `temp` as the temporary directory
+fn pipe_b2b(dir: &Path, temp: &Path, args: &[&str], data: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_rust_b2b"))
+        .args(["--stdin", "--stdout"])
+        .args(args)
+        .current_dir(dir)
+        .env("TMPDIR", temp)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    child.stdin.take().unwrap().write_all(data).unwrap();
+
+    child.wait_with_output().unwrap()
+}
+
+/// Pipes `data` through b2b with `args`, then pipes the result back, returning the converted and decoded data
+fn round_trip(dir: &Path, temp: &Path, args: &[&str], data: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let encoded = pipe_b2b(dir, temp, args, data);
+
+    assert!(encoded.status.success(), "encoding failed: {}", String::from_utf8_lossy(&encoded.stderr));
+
+    let decoded = pipe_b2b(dir, temp, &[], &encoded.stdout);
+
+    assert!(decoded.status.success(), "decoding failed: {}", String::from_utf8_lossy(&decoded.stderr));
+
+    (encoded.stdout, decoded.stdout)
+}
+
+#[test]
+fn small_input_never_touches_the_temp_dir() {
+    let dir = scratch_dir("stream-small");
+
+    //Anything created in the temporary directory would fail, as it is a file rather than a directory
+    let temp = dir.join("not-a-dir");
+
+    std::fs::write(&temp, b"").unwrap();
+
+    for args in [&[][..], &["--layout", "column"], &["--container", "png"], &["--container", "raw"]] {
+        let data = input(5000, 30);
+
+        let (encoded, decoded) = round_trip(&dir, &temp, args, &data);
+
+        assert!(encoded.len() > data.len(), "{:?}", args);
+        assert_eq!(decoded, data, "{:?}", args);
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn matches_converting_a_file() {
+    let dir = scratch_dir("stream-file");
+    let temp = scratch_dir("stream-file-temp");
+
+    let data = input(3000, 31);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    assert!(common::run_b2b(&dir, ["input"]).status.success());
+
+    let (encoded, _) = round_trip(&dir, &temp, &[], &data);
+
+    assert_eq!(encoded, std::fs::read(dir.join("input.bmp")).unwrap());
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_dir_all(&temp);
+}
+
+#[test]
+fn large_input_spills_and_cleans_up() {
+    let dir = scratch_dir("stream-large");
+    let temp = scratch_dir("stream-large-temp");
+
+    let data = input(20000, 32);
+
+    let (_, decoded) = round_trip(&dir, &temp, &["--memory-limit", "1000", "--shuffle", "5"], &data);
+
+    assert_eq!(decoded, data);
+
+    assert_eq!(std::fs::read_dir(&temp).unwrap().count(), 0, "temporary files were left behind");
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_dir_all(&temp);
+}
+
+#[test]
+fn failed_verification_writes_nothing() {
+    let dir = scratch_dir("stream-verify");
+    let temp = scratch_dir("stream-verify-temp");
+
+    let data = input(2000, 33);
+
+    let encoded = pipe_b2b(&dir, &temp, &[], &data);
+
+    let mut corrupt = encoded.stdout;
+
+    //Flip a byte of the payload, which follows the 206 bytes of headers
+    corrupt[500] ^= 0xFF;
+
+    let decoded = pipe_b2b(&dir, &temp, &[], &corrupt);
+
+    assert_eq!(decoded.status.code(), Some(2));
+    assert!(decoded.stdout.is_empty());
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_dir_all(&temp);
+}
+
+#[test]
+fn decodes_bitmaps_converted_on_disk() {
+    let dir = scratch_dir("stream-decode");
+    let temp = scratch_dir("stream-decode-temp");
+
+    let data = input(12000, 34);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    for args in [&[][..], &["--canonical"], &["--layout", "interleaved"]] {
+        assert!(common::run_b2b(&dir, args.iter().chain(&["input"])).status.success(), "{:?}", args);
+
+        let bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+        std::fs::remove_file(dir.join("input.bmp")).unwrap();
+
+        //A pipe can't be seeked, whether the bitmap is decoded in memory or spilled to a temporary file first
+        for limit in ["1000000", "1000"] {
+            let decoded = pipe_b2b(&dir, &temp, &["--memory-limit", limit], &bitmap);
+
+            assert!(decoded.status.success(), "{:?} {}: {}", args, limit, String::from_utf8_lossy(&decoded.stderr));
+            assert_eq!(decoded.stdout, data, "{:?} {}", args, limit);
+        }
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_dir_all(&temp);
+}
+
+/// Digest of the original as stored in `bitmap`, i.e. the low 127 bits of the field after the top bit flag
+fn stored_digest(bitmap: &[u8]) -> u128 {
+    let mut field = [0u8; 16];
+
+    //The digest follows the signature, original size, padding size and header size in the b2b header
+    field.copy_from_slice(&bitmap[0x8A + 24..0x8A + 40]);
+
+    u128::from_le_bytes(field)
+}
+
+#[test]
+fn digest_is_computed_while_encoding() {
+    let dir = scratch_dir("stream-digest");
+    let temp = scratch_dir("stream-digest-temp");
+
+    let data = input(5000, 33);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    let cases: [&[&str]; 3] = [&[], &["--hash-range", "100:3000"], &["--layout", "shuffled"]];
+
+    for (i, args) in cases.iter().enumerate() {
+        //Encoding the file on disk hashes it before writing the bitmap
+        let extension = format!("{}.bmp", i);
+
+        assert!(common::run_b2b(&dir, [&["--ext", &extension], *args, &["input"]].concat()).status.success());
+
+        let expected = std::fs::read(dir.join(format!("input.{}", extension))).unwrap();
+
+        assert_ne!(stored_digest(&expected), 0, "{:?}", args);
+
+        //Both in memory and spilled to a temporary file
+        for limit in ["10000", "1000"] {
+            let (encoded, decoded) = round_trip(&dir, &temp, &[&["--memory-limit", limit], *args].concat(), &data);
+
+            assert_eq!(stored_digest(&encoded), stored_digest(&expected), "{:?} with a limit of {}", args, limit);
+            assert_eq!(encoded, expected, "{:?} with a limit of {}", args, limit);
+            assert_eq!(decoded, data);
+        }
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_dir_all(&temp);
+}
+
+#[test]
+fn bitmap_digest_is_sealed_when_spilled() {
+    let dir = scratch_dir("stream-bitmap-digest");
+    let temp = scratch_dir("stream-bitmap-digest-temp");
+
+    //Both in memory and spilled to a temporary file
+    for (limit, args) in [("100000", &[][..]), ("10", &[][..]), ("10", &["--shuffle", "4"][..])] {
+        let encoded = pipe_b2b(&dir, &temp, &[&["--bitmap-digest", "--memory-limit", limit], args].concat(), &input(8000, 34));
+
+        assert!(encoded.status.success(), "encoding failed: {}", String::from_utf8_lossy(&encoded.stderr));
+
+        std::fs::write(dir.join("input.bmp"), &encoded.stdout).unwrap();
+
+        let checked = common::run_b2b(&dir, ["--check-bitmap", "input.bmp"]);
+
+        assert!(checked.status.success(), "{:?} with a limit of {}: {}", args, limit, String::from_utf8_lossy(&checked.stderr));
+        assert!(String::from_utf8_lossy(&checked.stdout).contains("matches"), "{:?} with a limit of {}", args, limit);
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+    let _ = std::fs::remove_dir_all(&temp);
+}
@@ -0,0 +1,94 @@
+//! `--strict` treats a bitmap without a digest as a failed verification, where the default only warns about it
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+#[test]
+fn no_digest_fails_only_in_strict_mode() {
+    let dir = scratch_dir("strict-no-digest");
+
+    let data = input(3000, 90);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    assert!(run_b2b(&dir, ["--fast", "input"]).status.success());
+
+    let bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    std::fs::remove_file(dir.join("input")).unwrap();
+
+    let lenient = run_b2b(&dir, ["input.bmp"]);
+
+    assert!(lenient.status.success());
+    assert!(String::from_utf8_lossy(&lenient.stdout).contains("Unable to verify"));
+    assert_eq!(std::fs::read(dir.join("input")).unwrap(), data);
+
+    std::fs::remove_file(dir.join("input")).unwrap();
+    std::fs::write(dir.join("input.bmp"), &bitmap).unwrap();
+
+    let strict = run_b2b(&dir, ["--strict", "input.bmp"]);
+
+    assert_eq!(strict.status.code(), Some(2));
+    assert_eq!(std::fs::read(dir.join("input")).unwrap(), data, "the converted file is kept");
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn strict_directory_counts_missing_digests_as_failures() {
+    let dir = scratch_dir("strict-directory");
+    let tree = dir.join("tree");
+
+    std::fs::create_dir_all(&tree).unwrap();
+
+    std::fs::write(tree.join("verified"), input(1000, 91)).unwrap();
+    std::fs::write(tree.join("unverified"), input(1000, 92)).unwrap();
+
+    assert!(run_b2b(&dir, ["tree/verified"]).status.success());
+    assert!(run_b2b(&dir, ["--fast", "tree/unverified"]).status.success());
+
+    std::fs::remove_file(tree.join("verified")).unwrap();
+    std::fs::remove_file(tree.join("unverified")).unwrap();
+
+    let output = run_b2b(&dir, ["--strict", "--stats", "tree"]);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(output.status.code(), Some(2), "{}", stdout);
+    assert!(stdout.contains("Verification failures: 1\n"), "{}", stdout);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn strict_stream_writes_nothing_without_a_digest() {
+    let dir = scratch_dir("strict-stream");
+
+    std::fs::write(dir.join("input"), input(2000, 93)).unwrap();
+
+    assert!(run_b2b(&dir, ["--fast", "input"]).status.success());
+
+    let stdin = std::fs::File::open(dir.join("input.bmp")).unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_rust_b2b"))
+        .current_dir(&dir)
+        .args(["--stdin", "--stdout", "--strict"])
+        .stdin(stdin)
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(output.stdout.is_empty());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn strict_conflicts_with_fast() {
+    let dir = scratch_dir("strict-fast");
+
+    assert_eq!(run_b2b(&dir, ["--strict", "--fast", "input"]).status.code(), Some(64));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
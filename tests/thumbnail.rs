@@ -0,0 +1,74 @@
+//! `--thumbnail` writes a small preview of the bitmap alongside it
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Width and height stored in the bitmap header of `bitmap`
+fn dimensions(bitmap: &[u8]) -> (u32, u32) {
+    let field = |offset: usize| u32::from_le_bytes([bitmap[offset], bitmap[offset + 1], bitmap[offset + 2], bitmap[offset + 3]]);
+
+    (field(18), field(22))
+}
+
+/// Encodes `len` bytes with `args` and a thumbnail of at most `size` pixels, returning the bitmap and thumbnail
+fn encode_with_thumbnail(name: &str, len: usize, size: &str, args: &[&str]) -> (Vec<u8>, Vec<u8>) {
+    let dir = scratch_dir(&format!("thumbnail-{}", name));
+
+    std::fs::write(dir.join("input"), input(len, 170)).unwrap();
+
+    let output = run_b2b(&dir, [args, &["--thumbnail", "thumb.bmp", "--thumbnail-size", size, "input"]].concat());
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    let bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+    let thumbnail = std::fs::read(dir.join("thumb.bmp")).unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    (bitmap, thumbnail)
+}
+
+#[test]
+fn thumbnail_fits_the_requested_size() {
+    let (bitmap, thumbnail) = encode_with_thumbnail("square", 10_000, "16", &["--min-dimension", "100"]);
+
+    assert_eq!(dimensions(&bitmap), (100, 100));
+    assert_eq!(dimensions(&thumbnail), (16, 16));
+    assert_eq!(thumbnail.len(), 0x8A + 16 * 16 * 4);
+}
+
+#[test]
+fn thumbnail_of_a_png() {
+    let dir = scratch_dir("thumbnail-png");
+
+    std::fs::write(dir.join("input"), input(10_000, 171)).unwrap();
+
+    let output = run_b2b(&dir, ["--container", "png", "--min-dimension", "100", "--thumbnail", "thumb.bmp", "--thumbnail-size", "16", "input"]);
+
+    let thumbnail = std::fs::read(dir.join("thumb.bmp"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(dimensions(&thumbnail.unwrap()), (16, 16));
+}
+
+#[test]
+fn thumbnail_of_a_uniform_bitmap_is_uniform() {
+    let dir = scratch_dir("thumbnail-uniform");
+
+    //Zeros encode to black pixels, so the thumbnail is black apart from the pixels covering the b2b header. The
+    //bitmap is 101x100, so the thumbnail keeps it slightly wider than it is tall
+    std::fs::write(dir.join("input"), vec![0u8; 40_000]).unwrap();
+
+    assert!(run_b2b(&dir, ["--thumbnail", "thumb.bmp", "--thumbnail-size", "10", "input"]).status.success());
+
+    let thumbnail = std::fs::read(dir.join("thumb.bmp")).unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(dimensions(&thumbnail), (10, 9));
+    assert_eq!(thumbnail.len(), 0x8A + 10 * 9 * 4);
+    assert!(thumbnail[0x8A + 4 * 10..].iter().all(|&byte| byte == 0));
+}
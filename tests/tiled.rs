@@ -0,0 +1,84 @@
+//! The tiled layout packs the file data into square tiles, and reverses exactly whatever the size of the file
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Encodes `len` bytes with the tiled layout and tiles of `tile_size`, checks the bitmap differs from the row layout,
+/// then converts it back and checks it matches the input. Converts in place if `in_place` is set
+fn round_trip(len: usize, tile_size: u32, in_place: bool) {
+    let name = format!("tiled-{}-{}-{}", len, tile_size, in_place);
+    let dir = scratch_dir(&name);
+
+    let data = input(len, len as u32 ^ tile_size);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+    std::fs::write(dir.join("row"), &data).unwrap();
+
+    //Tiles of one pixel, or tiles covering the whole bitmap, leave the pixels in row order
+    let rearranged = tile_size > 1 && (tile_size * tile_size * 4) < len as u32;
+
+    let tile_size = tile_size.to_string();
+    let in_place = if in_place { &["--in-place"][..] } else { &[] };
+
+    let encoded = run_b2b(&dir, [&["--pack", "tiled", "--tile-size", &tile_size], in_place, &["input"]].concat());
+
+    assert!(encoded.status.success(), "encoding {} failed: {}", name, String::from_utf8_lossy(&encoded.stderr));
+    assert!(run_b2b(&dir, ["row"]).status.success());
+
+    let tiled = std::fs::read(dir.join("input.bmp")).unwrap();
+    let row = std::fs::read(dir.join("row.bmp")).unwrap();
+
+    let _ = std::fs::remove_file(dir.join("input"));
+
+    let decoded = run_b2b(&dir, [in_place, &["input.bmp"]].concat());
+
+    assert!(decoded.status.success(), "decoding {} failed: {}", name, String::from_utf8_lossy(&decoded.stderr));
+    assert!(String::from_utf8_lossy(&decoded.stdout).contains("Verification successful"), "{}", String::from_utf8_lossy(&decoded.stdout));
+
+    let after = std::fs::read(dir.join("input")).unwrap();
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(after, data, "{} did not round trip", name);
+
+    if rearranged {
+        assert_ne!(tiled[206..], row[206..], "{} was not tiled", name);
+    }
+}
+
+#[test]
+fn round_trip_sizes_not_aligned_to_tiles() {
+    for len in [1, 100, 1001, 4097, 33_333] {
+        round_trip(len, 16, false);
+    }
+}
+
+#[test]
+fn round_trip_tile_sizes() {
+    for tile_size in [1, 3, 7, 1000] {
+        round_trip(5003, tile_size, false);
+    }
+}
+
+#[test]
+fn round_trip_in_place() {
+    round_trip(33_333, 5, true);
+}
+
+#[test]
+fn tile_size_is_stored_in_the_header() {
+    let dir = scratch_dir("tiled-info");
+
+    std::fs::write(dir.join("input"), input(3000, 9)).unwrap();
+
+    assert!(run_b2b(&dir, ["--layout", "tiled", "--tile-size", "4", "input"]).status.success());
+
+    let info = run_b2b(&dir, ["--info", "input.bmp"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let info = String::from_utf8(info.stdout).unwrap();
+
+    assert!(info.contains("Layout:             Tiled (4x4 tiles)\n"), "{}", info);
+}
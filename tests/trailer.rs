@@ -0,0 +1,125 @@
+//! `--trailer-bytes` reports where the first bytes of the original file are stored at the end of a bitmap, and dumps
+//! them, without converting it
+
+mod common;
+
+use std::path::Path;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Size of the bitmap header, after which the b2b header starts with the padding size
+const BITMAP_HEADER_SIZE: usize = 0x8A;
+
+/// Runs `--trailer-bytes count` on the bitmap `name` in `dir`, returning the reported length and offset of the trailer
+/// and the bytes read back from the hexdump
+fn trailer(dir: &Path, name: &str, count: usize) -> (u64, u64, Vec<u8>) {
+    let bitmap = std::fs::read(dir.join(name)).unwrap();
+
+    let output = run_b2b(dir, ["--trailer-bytes", &count.to_string(), name]);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert_eq!(std::fs::read(dir.join(name)).unwrap(), bitmap, "the bitmap was modified");
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    let mut lines = stdout.lines();
+
+    let summary: Vec<&str> = lines.next().unwrap().split_whitespace().collect();
+
+    assert_eq!(&summary[..1], ["Trailer:"], "{}", stdout);
+
+    //Each line of the dump is an offset, then up to 16 bytes in hex
+    let bytes = lines
+        .flat_map(|line| line[10..58].split_whitespace().map(|byte| u8::from_str_radix(byte, 16).unwrap()).collect::<Vec<_>>())
+        .collect();
+
+    (summary[1].parse().unwrap(), summary[6].parse().unwrap(), bytes)
+}
+
+/// Size of the bitmap and b2b headers of the bitmap `name` in `dir`, as reported by `--info`. This many bytes of the
+/// original are stored at the end
+fn header_size(dir: &Path, name: &str) -> u64 {
+    let info = String::from_utf8(run_b2b(dir, ["--info", name]).stdout).unwrap();
+
+    info.lines()
+        .find_map(|line| line.strip_prefix("Header size:"))
+        .and_then(|value| value.split_whitespace().next())
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn trailer_is_the_start_of_the_original() {
+    let dir = scratch_dir("trailer-row");
+
+    let data = input(5000, 160);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    assert!(run_b2b(&dir, ["input"]).status.success());
+
+    let bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    let mut padding = [0u8; 4];
+
+    padding.copy_from_slice(&bitmap[BITMAP_HEADER_SIZE..BITMAP_HEADER_SIZE + 4]);
+
+    let (length, offset, bytes) = trailer(&dir, "input.bmp", 40);
+
+    //The trailer is stored right before the padding at the end of the pixmap
+    assert_eq!(length, header_size(&dir, "input.bmp"));
+    assert_eq!(offset + length + u32::from_le_bytes(padding) as u64, bitmap.len() as u64);
+
+    assert_eq!(bytes, &data[..40]);
+    assert_eq!(&bitmap[offset as usize..offset as usize + 40], &data[..40]);
+
+    //Asking for more than the trailer holds dumps all of it
+    let (_, _, bytes) = trailer(&dir, "input.bmp", 1000);
+
+    assert_eq!(bytes, &data[..length as usize]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn trailer_is_restored_for_other_layouts() {
+    let dir = scratch_dir("trailer-layouts");
+
+    let data = input(5000, 161);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    for layout in ["column", "interleaved"] {
+        let extension = format!("{}.bmp", layout);
+
+        assert!(run_b2b(&dir, ["--layout", layout, "--ext", &extension, "input"]).status.success());
+
+        let name = format!("input.{}", extension);
+
+        let (length, _, bytes) = trailer(&dir, &name, 100);
+
+        assert_eq!(length, header_size(&dir, &name), "{}", layout);
+        assert_eq!(bytes, &data[..100], "{}", layout);
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn trailer_of_a_small_file_is_the_whole_file() {
+    let dir = scratch_dir("trailer-small");
+
+    let data = input(50, 162);
+
+    std::fs::write(dir.join("input"), &data).unwrap();
+
+    assert!(run_b2b(&dir, ["input"]).status.success());
+
+    let (length, _, bytes) = trailer(&dir, "input.bmp", 206);
+
+    assert_eq!(length, 50);
+    assert_eq!(bytes, data);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,139 @@
+//! Bitmaps whose headers contradict themselves are rejected before anything is modified
+
+mod common;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Offset of the pixmap size in the bitmap header
+const PIXMAP_SIZE_OFFSET: usize = 34;
+
+/// Offset of the width in the bitmap header
+const WIDTH_OFFSET: usize = 18;
+
+/// Offset of the height in the bitmap header
+const HEIGHT_OFFSET: usize = 22;
+
+/// Offset of the bits per pixel in the bitmap header
+const BPP_OFFSET: usize = 28;
+
+/// Offset of the size of the original file in the b2b header, which follows the 0x8A bytes of the bitmap header
+const ORIGINAL_SIZE_OFFSET: usize = 0x8A + 4;
+
+/// Offset of the layout in the b2b header: after the padding size, original file size, signature, digest, CRC and
+/// header size
+const LAYOUT_OFFSET: usize = 0x8A + 4 + 4 + 16 + 16 + 4 + 4;
+
+/// Encodes a file, applies `corrupt` to the bitmap and checks that converting it back fails with `message`, leaving
+/// the bitmap untouched
+fn rejects(name: &str, message: &str, corrupt: impl FnOnce(&mut Vec<u8>)) {
+    let dir = scratch_dir(&format!("validate-{}", name));
+
+    std::fs::write(dir.join("input"), input(3000, 100)).unwrap();
+
+    assert!(run_b2b(&dir, ["input"]).status.success());
+
+    std::fs::remove_file(dir.join("input")).unwrap();
+
+    let mut bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    corrupt(&mut bitmap);
+
+    std::fs::write(dir.join("input.bmp"), &bitmap).unwrap();
+
+    let output = run_b2b(&dir, ["input.bmp"]);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+    assert!(stderr.contains(message), "{}", stderr);
+
+    assert_eq!(std::fs::read(dir.join("input.bmp")).unwrap(), bitmap);
+    assert!(!dir.join("input").exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+fn add_to_field(bitmap: &mut [u8], offset: usize, delta: u32) {
+    let mut field = [0u8; 4];
+
+    field.copy_from_slice(&bitmap[offset..offset + 4]);
+
+    bitmap[offset..offset + 4].copy_from_slice(&u32::from_le_bytes(field).wrapping_add(delta).to_le_bytes());
+}
+
+#[test]
+fn pixmap_size_not_a_whole_number_of_pixels() {
+    rejects("unaligned", "Pixmap size does not match", |bitmap| add_to_field(bitmap, PIXMAP_SIZE_OFFSET, 2));
+}
+
+#[test]
+fn pixmap_size_inconsistent_with_dimensions() {
+    rejects("dimensions", "Pixmap size does not match", |bitmap| add_to_field(bitmap, WIDTH_OFFSET, 1));
+}
+
+#[test]
+fn pixmap_size_overflows_the_bitmap() {
+    rejects("offset-overflow", "offsets outside the range of a bitmap", |bitmap| bitmap[PIXMAP_SIZE_OFFSET..PIXMAP_SIZE_OFFSET + 4].copy_from_slice(&(u32::MAX - 2).to_le_bytes()));
+}
+
+#[test]
+fn original_size_slightly_too_large() {
+    rejects("original-larger", "invalid original file size", |bitmap| add_to_field(bitmap, ORIGINAL_SIZE_OFFSET, 1));
+}
+
+#[test]
+fn original_size_slightly_too_small() {
+    rejects("original-smaller", "invalid original file size", |bitmap| add_to_field(bitmap, ORIGINAL_SIZE_OFFSET, u32::MAX));
+}
+
+#[test]
+fn unreadable_b2b_header_is_not_encoded_again() {
+    rejects("layout", "Could not read header", |bitmap| bitmap[LAYOUT_OFFSET..LAYOUT_OFFSET + 4].copy_from_slice(&u32::MAX.to_le_bytes()));
+}
+
+/// Encodes 300 bytes, giving a 10x10 pixmap of 400 bytes, then reshapes it as `height` rows of `width` pixels of `bpp`
+/// bits and returns the result of `--info --json` on it
+fn reshaped_info(name: &str, width: u32, height: u32, bpp: u16) -> std::process::Output {
+    let dir = scratch_dir(&format!("validate-{}", name));
+
+    std::fs::write(dir.join("input"), input(300, 101)).unwrap();
+
+    assert!(run_b2b(&dir, ["input"]).status.success());
+
+    let mut bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    bitmap[WIDTH_OFFSET..WIDTH_OFFSET + 4].copy_from_slice(&width.to_le_bytes());
+    bitmap[HEIGHT_OFFSET..HEIGHT_OFFSET + 4].copy_from_slice(&height.to_le_bytes());
+    bitmap[BPP_OFFSET..BPP_OFFSET + 2].copy_from_slice(&bpp.to_le_bytes());
+
+    std::fs::write(dir.join("input.bmp"), &bitmap).unwrap();
+
+    let output = run_b2b(&dir, ["--info", "--json", "input.bmp"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    output
+}
+
+#[test]
+fn twenty_four_bit_header_has_three_bytes_per_pixel() {
+    // 33 pixels of 3 bytes make a row of 99 bytes, padded to 100, so 4 rows fill the 400 bytes
+    let output = reshaped_info("24-bit", 33, 4, 24);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(stdout.contains("\"bpp\": 24"), "{}", stdout);
+}
+
+#[test]
+fn thirty_two_bit_header_has_four_bytes_per_pixel() {
+    let output = reshaped_info("32-bit", 33, 4, 32);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(3), "{}", stderr);
+    assert!(stderr.contains("should hold 528"), "{}", stderr);
+
+    assert!(reshaped_info("32-bit-square", 10, 10, 32).status.success());
+}
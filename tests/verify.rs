@@ -0,0 +1,105 @@
+//! Verification tells a corrupt payload apart from a modified header, using the CRC of the b2b header
+
+mod common;
+
+use std::process::Output;
+
+use common::{input, scratch_dir, run_b2b};
+
+/// Offset of the CRC of the b2b header, in the reserved bytes of the bitmap file header
+const HEADER_CRC_OFFSET: usize = 6;
+
+/// Offset of the stored digest: the bitmap header, then the padding size, original file size and signature
+const DIGEST_OFFSET: usize = 0x8A + 4 + 4 + 16;
+
+/// Offset of the first payload byte
+const PAYLOAD_OFFSET: usize = 206;
+
+/// Encodes a file, changes the bitmap with `tamper`, then converts it back
+fn decode_tampered<F: FnOnce(&mut Vec<u8>)>(name: &str, args: &[&str], tamper: F) -> Output {
+    let dir = scratch_dir(&format!("verify-{}", name));
+
+    std::fs::write(dir.join("input"), input(3000, 50)).unwrap();
+
+    assert!(run_b2b(&dir, ["input"]).status.success());
+
+    std::fs::remove_file(dir.join("input")).unwrap();
+
+    let mut bitmap = std::fs::read(dir.join("input.bmp")).unwrap();
+
+    tamper(& mut bitmap);
+
+    std::fs::write(dir.join("input.bmp"), &bitmap).unwrap();
+
+    let output = run_b2b(&dir, args.iter().chain(&["input.bmp"]));
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    output
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn intact_bitmap_verifies() {
+    let output = decode_tampered("intact", &[], |_| {});
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Verification successful"));
+}
+
+#[test]
+fn corrupt_payload_is_reported_as_corrupt() {
+    let output = decode_tampered("payload", &[], |bitmap| bitmap[PAYLOAD_OFFSET + 100] ^= 0xFF);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stdout(&output).contains("the file data is corrupt"), "{}", stdout(&output));
+}
+
+#[test]
+fn modified_digest_is_reported_as_tampering() {
+    let output = decode_tampered("digest", &[], |bitmap| bitmap[DIGEST_OFFSET] ^= 0xFF);
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stdout(&output).contains("the stored digest was modified"), "{}", stdout(&output));
+}
+
+#[test]
+fn quick_verify_reports_the_same_outcomes() {
+    let payload = decode_tampered("quick-payload", &["--quick-verify"], |bitmap| bitmap[PAYLOAD_OFFSET + 100] ^= 0xFF);
+    let digest = decode_tampered("quick-digest", &["--quick-verify"], |bitmap| bitmap[DIGEST_OFFSET] ^= 0xFF);
+
+    assert!(stdout(&payload).contains("the file data is corrupt"), "{}", stdout(&payload));
+    assert!(stdout(&digest).contains("the stored digest was modified"), "{}", stdout(&digest));
+}
+
+#[test]
+fn bitmap_without_header_crc_is_ambiguous() {
+    let output = decode_tampered("legacy", &[], |bitmap| {
+        bitmap[HEADER_CRC_OFFSET..HEADER_CRC_OFFSET + 4].fill(0);
+        bitmap[PAYLOAD_OFFSET + 100] ^= 0xFF;
+    });
+
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stdout(&output).contains("either may have been modified"), "{}", stdout(&output));
+}
+
+#[test]
+fn bitmap_without_digest_cannot_be_verified() {
+    let dir = scratch_dir("verify-no-digest");
+
+    std::fs::write(dir.join("input"), input(3000, 51)).unwrap();
+
+    assert!(run_b2b(&dir, ["--fast", "input"]).status.success());
+
+    std::fs::remove_file(dir.join("input")).unwrap();
+
+    let output = run_b2b(&dir, ["input.bmp"]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert!(output.status.success());
+    assert!(stdout(&output).contains("Unable to verify as bitmap doesn't contain digest"), "{}", stdout(&output));
+}